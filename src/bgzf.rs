@@ -0,0 +1,222 @@
+use flate2::read::DeflateDecoder;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+// fixed size of a BGZF block header, the block size field is its last two bytes
+const HEADER_LEN: usize = 18;
+
+// A bgzip file read as its uncompressed content. Every block is a gzip member of at most 64KB of
+// input, so seeking to an uncompressed offset only inflates the block holding it. Block starts come
+// from the `.gzi` index written by `bgzip -i` when there is one, otherwise from walking the block
+// headers, which doesn't inflate anything.
+pub(crate) struct Bgzf<R> {
+    inner: R,
+    // (compressed offset, uncompressed offset) of every block start known so far, in order
+    index: Vec<(u64, u64)>,
+    // the inflated block being read, its uncompressed offset and the read position in it
+    block: Vec<u8>,
+    block_start: u64,
+    pos: usize,
+    // compressed offset of the block after the current one
+    next_block: u64,
+}
+
+impl Bgzf<BufReader<File>> {
+    pub fn open(file: &str) -> io::Result<Self> {
+        let inner = File::open(file)?;
+        let len = inner.metadata()?.len();
+        let mut bgzf = Bgzf::new(BufReader::new(inner));
+        if let Ok(gzi) = File::open(format!("{}.gzi", file)) {
+            let blocks = read_gzi(BufReader::new(gzi))?;
+            check_gzi(&blocks, len)?;
+            bgzf.index.extend(blocks);
+        }
+        Ok(bgzf)
+    }
+}
+
+impl<R: Read + Seek> Bgzf<R> {
+    pub fn new(inner: R) -> Self {
+        Bgzf { inner, index: vec![(0, 0)], block: Vec::new(), block_start: 0, pos: 0, next_block: 0 }
+    }
+
+    // Header of the block at compressed offset `at`: its compressed length, or None at the end of the file
+    fn block_len(&mut self, at: u64) -> io::Result<Option<u64>> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        self.inner.seek(SeekFrom::Start(at))?;
+        self.inner.by_ref().take(HEADER_LEN as u64).read_to_end(&mut header)?;
+        if header.is_empty() {
+            return Ok(None);
+        }
+        if !is_bgzf(&header) {
+            return Err(invalid("not a BGZF block"));
+        }
+        let len = u16::from_le_bytes([header[16], header[17]]) as u64 + 1;
+        // the header and the CRC32 and length trailer at least
+        if len < (HEADER_LEN + 8) as u64 {
+            return Err(invalid("bad BGZF block size"));
+        }
+        Ok(Some(len))
+    }
+
+    // inflate the block at compressed offset `at`, false at the end of the file
+    fn load(&mut self, at: u64, start: u64) -> io::Result<bool> {
+        let len = match self.block_len(at)? {
+            Some(len) => len,
+            None => return Ok(false),
+        };
+        let mut data = vec![0u8; len as usize - HEADER_LEN];
+        self.inner.read_exact(&mut data).map_err(truncated)?;
+        // the deflate data is followed by the CRC32 and length of the uncompressed block
+        let (deflated, trailer) = data.split_at(data.len() - 8);
+        let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]) as usize;
+        self.block.clear();
+        DeflateDecoder::new(deflated).read_to_end(&mut self.block)?;
+        if self.block.len() != size {
+            return Err(invalid("BGZF block has the wrong length"));
+        }
+        self.block_start = start;
+        self.pos = 0;
+        self.next_block = at + len;
+        if self.index.last().is_some_and(|(compressed, _)| *compressed < self.next_block) {
+            self.index.push((self.next_block, start + size as u64));
+        }
+        Ok(true)
+    }
+
+    // Index entry of the block holding uncompressed `offset`, walking block headers past the known ones
+    fn find(&mut self, offset: u64) -> io::Result<(u64, u64)> {
+        while let Some(&(at, start)) = self.index.last().filter(|(_, start)| *start <= offset) {
+            let len = match self.block_len(at)? {
+                Some(len) => len,
+                None => break,
+            };
+            let mut size = [0u8; 4];
+            self.inner.seek(SeekFrom::Start(at + len - 4))?;
+            self.inner.read_exact(&mut size).map_err(truncated)?;
+            self.index.push((at + len, start + u32::from_le_bytes(size) as u64));
+        }
+        let found = self.index.partition_point(|(_, start)| *start <= offset);
+        Ok(self.index[found.saturating_sub(1)])
+    }
+}
+
+impl<R: Read + Seek> Read for Bgzf<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // skip empty blocks, like the end of file marker
+        while self.pos == self.block.len() {
+            let start = self.block_start + self.block.len() as u64;
+            if !self.load(self.next_block, start)? {
+                return Ok(0);
+            }
+        }
+        let read = (&self.block[self.pos..]).read(buf)?;
+        self.pos += read;
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for Bgzf<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let offset = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(delta) => self.block_start.saturating_add_signed(self.pos as i64 + delta),
+            SeekFrom::End(_) => return Err(io::Error::new(io::ErrorKind::Unsupported, "BGZF can't seek from the end")),
+        };
+        let in_block = self.block_start <= offset && offset < self.block_start + self.block.len() as u64;
+        if !in_block {
+            let (at, start) = self.find(offset)?;
+            if !self.load(at, start)? {
+                self.block.clear();
+                self.block_start = start;
+                self.next_block = at;
+            }
+        }
+        self.pos = ((offset - self.block_start) as usize).min(self.block.len());
+        Ok(self.block_start + self.pos as u64)
+    }
+}
+
+// a gzip member header with the `BC` extra subfield holding the block size
+pub(crate) fn is_bgzf(header: &[u8]) -> bool {
+    header.len() >= HEADER_LEN && header[..4] == [0x1f, 0x8b, 0x08, 0x04] && header[12..14] == *b"BC"
+}
+
+// `.gzi` index: the number of entries, then (compressed, uncompressed) offsets of every block but the first
+fn read_gzi<R: Read>(mut reader: R) -> io::Result<Vec<(u64, u64)>> {
+    let mut word = [0u8; 8];
+    let mut next = |reader: &mut R| reader.read_exact(&mut word).map(|_| u64::from_le_bytes(word));
+    let count = next(&mut reader)?;
+    (0..count).map(|_| Ok((next(&mut reader)?, next(&mut reader)?))).collect()
+}
+
+// block starts of a `.gzi` index are in order and inside the compressed file
+fn check_gzi(blocks: &[(u64, u64)], len: u64) -> io::Result<()> {
+    let mut last = (0, 0);
+    for &(compressed, uncompressed) in blocks {
+        if compressed < last.0 || uncompressed < last.1 || compressed > len {
+            return Err(invalid("bad BGZF index"));
+        }
+        last = (compressed, uncompressed);
+    }
+    Ok(())
+}
+
+fn truncated(e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        invalid("truncated BGZF block")
+    } else {
+        e
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seek_to_block() {
+        let plain = std::fs::read("tests/fixtures/compressed/store.json").unwrap();
+        let file = "tests/fixtures/compressed/store.json.bgz";
+        // with the `.gzi` index next to it, and walking the block headers
+        let indexed = Bgzf::open(file).unwrap();
+        let walked = Bgzf::new(BufReader::new(File::open(file).unwrap()));
+        assert!(indexed.index.len() > 2);
+        for mut bgzf in [indexed, walked] {
+            for offset in [9000, 20, 4096, plain.len() as u64 - 5] {
+                let mut buff = [0u8; 5];
+                bgzf.seek(SeekFrom::Start(offset)).unwrap();
+                bgzf.read_exact(&mut buff).unwrap();
+                assert_eq!(buff, plain[offset as usize..offset as usize + 5], "at {}", offset);
+            }
+            let mut all = Vec::new();
+            bgzf.seek(SeekFrom::Start(0)).unwrap();
+            bgzf.read_to_end(&mut all).unwrap();
+            assert_eq!(all, plain);
+        }
+    }
+
+    #[test]
+    fn corrupt_blocks() {
+        let header = |size: u16| {
+            let mut header = vec![0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0];
+            header.extend(size.to_le_bytes());
+            header
+        };
+        // a block size too small for the header and trailer, and a block cut short
+        let mut cut = header(200);
+        cut.extend([0u8; 10]);
+        for data in [header(0), header(20), cut] {
+            let mut bgzf = Bgzf::new(io::Cursor::new(data));
+            let e = bgzf.read(&mut [0u8; 16]).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        }
+        assert!(check_gzi(&[(100, 500), (300, 900)], 400).is_ok());
+        assert_eq!(check_gzi(&[(300, 900), (100, 500)], 400).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(check_gzi(&[(100, 500), (500, 900)], 400).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
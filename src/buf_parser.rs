@@ -1,249 +1,556 @@
-use crate::utils::{array_ind, checkpoint_depth, find_str, sanitize_output, token_pos};
-use crate::{buf_parser, utils};
+use crate::utils::{find_str, numeric_key, sanitize_output, token_pos, value_error, RawMatch};
+use crate::utils;
+use crate::error::JistError;
+use crate::input::{self, Mapped};
+use crate::parallel;
+use crate::windows::{window_heads, Window};
 use json_tools::{BufferType, Lexer, TokenType};
 use log::debug;
-use std::cmp::Ordering;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Seek};
 use crate::model::j_struct_tracker::JStructTracker;
+use crate::model::json_path::JsonPath;
+use crate::model::path_segment::{select, PathSegment};
 use crate::model::stream_tracker::StreamTracker;
 
+const CHUNK_SIZE: usize = 1_000_000;
+
+// byte ranges handed to each worker by `search_parallel`, within these bounds
+const RANGES_PER_THREAD: usize = 4;
+const MIN_RANGE: usize = 1 << 20;
+const MAX_RANGE: usize = 64 << 20;
+
 pub fn search(
     haystack: Option<&str>,
     file: Option<&str>, // Keep this as Option<&str> for future flexibility with testing & dev
     search_key: &str,
-) -> Result<String, &'static str> {
-    if search_key.is_empty() {
-        return Err("search_key is empty");
-    }
-    let search_path = utils::parse_search_key(search_key);
-    if file.is_some() {
-        let f = File::open(file.unwrap()).unwrap();
-        let mut reader = BufReader::new(&f);
-        let mut seeker = BufReader::new(&f);
-        _search(&mut reader, &mut seeker, &search_path)
+) -> Result<String, JistError> {
+    let query = parse_queries(&[search_key])?.remove(0);
+    let matches = scan(haystack, file, std::slice::from_ref(&query))?.remove(0);
+    finish(&query, matches)
+}
+
+// Like `search`, reading the input front to back from `reader`, e.g. a pipe. Only the text of
+// values that are still being matched is held in memory.
+pub fn search_reader<R: Read>(reader: R, search_key: &str) -> Result<String, JistError> {
+    _search(reader, None::<io::Empty>, search_key)
+}
+
+// Several search keys answered in a single pass over the input, one JSON value per key: the value
+// itself for a definite path, otherwise the array (or object keyed by path) a single search returns
+pub fn search_many(
+    haystack: Option<&str>,
+    file: Option<&str>,
+    search_keys: &[&str],
+) -> Result<Vec<Value>, JistError> {
+    let queries = parse_queries(search_keys)?;
+    let found = scan(haystack, file, &queries)?;
+    finish_many(&queries, found)
+}
+
+// Like `search`, with the input split between `threads` workers. Paths of object keys leading to an
+// array, followed by an index, wildcard, filter or forward slice, are searched in just the parts of
+// that array that can hold matches. Anything else, and compressed input, gets a single pass.
+pub fn search_parallel(
+    haystack: Option<&str>,
+    file: Option<&str>,
+    search_key: &str,
+    threads: usize,
+) -> Result<String, JistError> {
+    let query = parse_queries(&[search_key])?.remove(0);
+    if threads > 1 {
+        let map = file.map(Mapped::open).transpose()?.flatten();
+        let bytes = match (&map, file, haystack) {
+            (Some(map), _, _) => Some(&map[..]),
+            (None, None, Some(haystack)) if !haystack.is_empty() => Some(haystack.as_bytes()),
+            _ => None,
+        };
+        if let Some(bytes) = bytes {
+            let range_size = (bytes.len() / (threads * RANGES_PER_THREAD)).clamp(MIN_RANGE, MAX_RANGE);
+            if let Some(result) = search_split(bytes, &query, threads, range_size)? {
+                return Ok(result);
+            }
+        }
+    }
+    let matches = scan(haystack, file, std::slice::from_ref(&query))?.remove(0);
+    finish(&query, matches)
+}
+
+// Like `search_many`, reading the input front to back from `reader`
+pub fn search_many_reader<R: Read>(reader: R, search_keys: &[&str]) -> Result<Vec<Value>, JistError> {
+    let queries = parse_queries(search_keys)?;
+    let found = _search_many(reader, None::<io::Empty>, &queries)?;
+    finish_many(&queries, found)
+}
+
+// The matches of a search key in every record of NDJSON input, like `simd_parser::search_records`,
+// searching a line at a time
+pub fn search_records(
+    haystack: Option<&str>,
+    file: Option<&str>,
+    search_key: &str,
+) -> Result<Vec<Value>, JistError> {
+    match (haystack, file) {
+        (_, Some(file)) => search_records_reader(BufReader::new(input::reader(file)?), search_key),
+        (Some(haystack), None) if !haystack.is_empty() => search_records_reader(haystack.as_bytes(), search_key),
+        _ => Err(JistError::InvalidInput("Invalid input - empty data")),
+    }
+}
+
+// Like `search_records`, reading the records front to back from `reader`
+pub fn search_records_reader<R: BufRead>(reader: R, search_key: &str) -> Result<Vec<Value>, JistError> {
+    let queries = parse_queries(&[search_key])?;
+    let mut records = Vec::new();
+    each_record(reader, &queries, |mut found| {
+        records.extend(record_value(&queries[0], found.remove(0))?);
+        Ok(())
+    })?;
+    if queries[0].is_definite() && records.is_empty() {
+        return Err(JistError::NotFound);
+    }
+    Ok(records)
+}
+
+// Several search keys in every record of NDJSON input, one object keyed by search key per record.
+// Keys with no match in a record are left out of its object, records where none match are left out.
+pub fn search_records_many(
+    haystack: Option<&str>,
+    file: Option<&str>,
+    search_keys: &[&str],
+) -> Result<Vec<Value>, JistError> {
+    match (haystack, file) {
+        (_, Some(file)) => search_records_many_reader(BufReader::new(input::reader(file)?), search_keys),
+        (Some(haystack), None) if !haystack.is_empty() => search_records_many_reader(haystack.as_bytes(), search_keys),
+        _ => Err(JistError::InvalidInput("Invalid input - empty data")),
+    }
+}
+
+// Like `search_records_many`, reading the records front to back from `reader`
+pub fn search_records_many_reader<R: BufRead>(reader: R, search_keys: &[&str]) -> Result<Vec<Value>, JistError> {
+    let queries = parse_queries(search_keys)?;
+    let mut records = Vec::new();
+    each_record(reader, &queries, |found| {
+        let mut record = Map::new();
+        for ((search_key, query), matches) in search_keys.iter().zip(&queries).zip(found) {
+            if let Some(value) = record_value(query, matches)? {
+                record.insert(search_key.to_string(), value);
+            }
+        }
+        if !record.is_empty() {
+            records.push(Value::Object(record));
+        }
+        Ok(())
+    })?;
+    if queries.iter().all(JsonPath::is_definite) && records.is_empty() {
+        return Err(JistError::NotFound);
+    }
+    Ok(records)
+}
+
+// The search of `search_key` in a single record of NDJSON input when it starts with an index like
+// `[2]` or `[-1]`, which picks the record rather than an element of every record. Only the last few
+// records are held back for a negative index. None for other search keys.
+pub fn search_record(
+    haystack: Option<&str>,
+    file: Option<&str>,
+    search_key: &str,
+) -> Result<Option<String>, JistError> {
+    match (haystack, file) {
+        (_, Some(file)) => search_record_reader(BufReader::new(input::reader(file)?), search_key),
+        (Some(haystack), None) if !haystack.is_empty() => search_record_reader(haystack.as_bytes(), search_key),
+        _ => Err(JistError::InvalidInput("Invalid input - empty data")),
+    }
+}
+
+// Like `search_record`, reading the records front to back from `reader`
+pub fn search_record_reader<R: BufRead>(reader: R, search_key: &str) -> Result<Option<String>, JistError> {
+    let query = JsonPath::parse(search_key).map_err(JistError::InvalidPath)?;
+    // the concrete paths of recursive descent matches would start inside the record
+    let (Some(&PathSegment::Index(i)), false) = (query.segments.first(), query.with_paths()) else {
+        return Ok(None);
+    };
+    // the record at a positive index, or the last ones for a negative index
+    let mut last = VecDeque::new();
+    let (mut n, mut pos) = (0, 0);
+    for line in reader.split(b'\n') {
+        let line = line?;
+        let start = pos;
+        pos += line.len() as u64 + 1;
+        let Some(at) = line.iter().position(|b| !b.is_ascii_whitespace()) else {
+            continue;
+        };
+        let end = line.len() - line.iter().rev().take_while(|b| b.is_ascii_whitespace()).count();
+        let record = (start + at as u64, line[at..end].to_vec());
+        if n == i {
+            last.push_back(record);
+            break;
+        }
+        n += 1;
+        if i < 0 {
+            last.push_back(record);
+            if last.len() > i.unsigned_abs() as usize {
+                last.pop_front();
+            }
+        }
+    }
+    let found = (i >= 0 || last.len() == i.unsigned_abs() as usize).then(|| last.pop_front()).flatten();
+    match found {
+        Some((start, record)) => search_value(&record, start, &query, 1).map(Some),
+        None if query.is_definite() => Err(JistError::NotFound),
+        None => Ok(Some("[]".to_string())),
+    }
+}
+
+// Search every line of NDJSON input, handing the matches of each query in a record to `record`
+fn each_record<R: BufRead, F>(reader: R, queries: &[JsonPath], mut record: F) -> Result<(), JistError>
+where
+    F: FnMut(Vec<Vec<RawMatch>>) -> Result<(), JistError>,
+{
+    let mut pos = 0;
+    for line in reader.split(b'\n') {
+        let line = line?;
+        let start = pos;
+        pos += line.len() as u64 + 1;
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        _search_slice(&line, queries, |_| {}).and_then(&mut record).map_err(|error| error.shifted(start))?;
+    }
+    Ok(())
+}
+
+// the value a query found in a record, None when it found nothing
+fn record_value(query: &JsonPath, matches: Vec<RawMatch>) -> Result<Option<Value>, JistError> {
+    match finish_many(std::slice::from_ref(query), vec![matches]) {
+        Ok(mut values) => match values.remove(0) {
+            Value::Array(values) if values.is_empty() && !query.is_definite() => Ok(None),
+            Value::Object(values) if values.is_empty() && query.with_paths() => Ok(None),
+            value => Ok(Some(value)),
+        },
+        Err(JistError::NotFound) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+fn parse_queries(search_keys: &[&str]) -> Result<Vec<JsonPath>, JistError> {
+    if search_keys.iter().any(|key| key.is_empty()) {
+        return Err(JistError::InvalidInput("search_key is empty"));
+    }
+    search_keys
+        .iter()
+        .map(|key| JsonPath::parse(key).map_err(JistError::InvalidPath))
+        .collect()
+}
+
+// one JSON value per query: the value itself for a definite path, otherwise every match
+fn finish_many(queries: &[JsonPath], found: Vec<Vec<RawMatch>>) -> Result<Vec<Value>, JistError> {
+    queries
+        .iter()
+        .zip(found)
+        .map(|(query, matches)| match matches.first() {
+            Some((_, start, raw)) if query.is_definite() => {
+                serde_json::from_str(raw.trim()).map_err(|e| value_error(*start, raw, e))
+            }
+            None if query.is_definite() => Err(JistError::NotFound),
+            _ => utils::join_matches(&matches, query.with_paths()),
+        })
+        .collect()
+}
+
+fn scan(
+    haystack: Option<&str>,
+    file: Option<&str>,
+    queries: &[JsonPath],
+) -> Result<Vec<Vec<RawMatch>>, JistError> {
+    if let Some(file) = file {
+        match Mapped::open(file)? {
+            Some(map) => _search_slice(&map, queries, |pos| map.release(pos)),
+            // compressed input is decompressed on the fly, matches are read back from bgzip blocks
+            // or kept in memory for compression that can't seek
+            None => _search_many(input::reader(file)?, input::seeker(file)?, queries),
+        }
     } else if haystack.is_some() {
         let haystack_str = haystack.unwrap();
         if haystack_str.is_empty() {
-            return Err("Invalid input - empty data");
+            return Err(JistError::InvalidInput("Invalid input - empty data"));
         }
-        let mut reader = Cursor::new(haystack_str.as_bytes());
-        let mut seeker = Cursor::new(haystack_str.as_bytes());
-        _search(&mut reader, &mut seeker, &search_path)
+        _search_slice(haystack_str.as_bytes(), queries, |_| {})
     } else {
-        Err("Invalid input - empty data")
+        Err(JistError::InvalidInput("Invalid input - empty data"))
+    }
+}
+
+// a definite path returns its first match, anything else every match
+fn finish(query: &JsonPath, matches: Vec<RawMatch>) -> Result<String, JistError> {
+    if query.is_definite() {
+        return match matches.first() {
+            Some((_, start, raw)) => sanitize_output(raw).map_err(|e| value_error(*start, raw, e)),
+            None => Err(JistError::NotFound),
+        };
     }
+    utils::join_matches(&matches, query.with_paths()).map(|value| value.to_string())
 }
 
-pub fn _search<R: Read + Seek + BufRead>(
+pub fn _search<R: Read, S: Read + Seek>(
+    reader: R,
+    seeker: Option<S>,
+    search_key: &str,
+) -> Result<String, JistError> {
+    let query = parse_queries(&[search_key])?.remove(0);
+    let matches = _search_many(reader, seeker, std::slice::from_ref(&query))?.remove(0);
+    finish(&query, matches)
+}
+
+// One lexer pass feeding a tracker per query. Returns the matches of every query. Stops early once
+// every query is definite and has found its value. The input is only read forward, matches are read
+// back through `seeker`, or without one kept in memory until they are complete.
+pub(crate) fn _search_many<R: Read, S: Read + Seek>(
     mut reader: R,
-    mut seeker: R,
-    search_path: &[String],
-) -> Result<String, &'static str> {
-    let chunk_size = 1_000_000;
+    mut seeker: Option<S>,
+    queries: &[JsonPath],
+) -> Result<Vec<Vec<RawMatch>>, JistError> {
+    let chunk_size = CHUNK_SIZE;
     let mut stream_t = StreamTracker::new(chunk_size);
-    let mut struct_t = JStructTracker::new(search_path);
+    let mut pass = Pass::new(queries);
 
     loop {
-        reader.seek(SeekFrom::Start(stream_t.last_stream_pos)).expect("Unable to seek");
         let bytes_read = reader
             .by_ref()
             .take(chunk_size as u64)
-            .read_to_end(&mut stream_t.buffer)
-            .unwrap();
+            .read_to_end(&mut stream_t.buffer)?;
         if bytes_read == 0 && stream_t.buffer.is_empty() {
-            return Err("result not found");
+            break;
         }
 
-        // Find the last line break (add one if last buffer read)
-        let mut chunk_str = String::from_utf8(stream_t.buffer.clone()).unwrap();
-        if bytes_read < chunk_size {
-            chunk_str.push('\n');
-        }
-        if let Some(last_chunk_tup) = chunk_str.rsplit_once('\n') {
-            let last_chunk = last_chunk_tup.0;
+        // Find the end of the last complete token
+        let exhausted = bytes_read < chunk_size;
+        let last_chunk = utils::complete_tokens(&stream_t.buffer, exhausted, stream_t.last_stream_pos)?;
+        if let Some(last_chunk) = last_chunk {
             debug!("last_chunk: {}", last_chunk);
-            // Process the chunk that ends with a newline
-            stream_t.chunk.extend_from_slice(last_chunk.as_bytes());
-            stream_t.chunk.push(b'\n');
-            stream_t.last_chunk_len = stream_t.chunk.len();
-
-            // Process chunk here
-            let mut token_iter = Lexer::new(stream_t.chunk.clone(), BufferType::Span).peekable();
-
-            loop {
-                let token_opt = token_iter.next();
-                if token_opt.is_none() {
-                    break;
-                }
-
-                let mut token = token_opt.unwrap();
-
-                match token.kind {
-                    TokenType::CurlyOpen => {
-                        struct_t.depth_curr.0 += 1;
-                        struct_t.depth_curr.2 += 1;
-                        struct_t.last_open.push(TokenType::CurlyOpen);
-                    }
-                    TokenType::CurlyClose => {
-                        struct_t.depth_curr.0 -= 1;
-                        struct_t.depth_curr.2 -= 1;
-                        struct_t.last_open.pop();
-                    }
-                    TokenType::BracketOpen => {
-                        struct_t.depth_curr.0 += 1;
-                        struct_t.depth_curr.1 += 1;
-                        struct_t.arr_idx.push(0);
-                        struct_t.last_open.push(TokenType::BracketOpen);
-                    }
-                    TokenType::BracketClose => {
-                        struct_t.depth_curr.0 -= 1;
-                        struct_t.depth_curr.1 -= 1;
-                        struct_t.arr_idx.pop();
-                        struct_t.last_open.pop();
-                    }
-                    TokenType::Comma => {
-                        if struct_t.depth_curr.1 > -1                    // must be inside an array
-                            && *struct_t.last_open.last().unwrap() == TokenType::BracketOpen
-                        {
-                            let arr_idx_len = struct_t.arr_idx.len();
-                            struct_t.arr_idx[arr_idx_len - 1] += 1;
-                        }
-                    }
-                    _ => {}
-                }
-                debug!("depth_curr: {:?}, arr_idx: {:?}, arr_tgt: {:?}, search_keys: {:?}, kind: {:?}, last_open: {:?}, checkpoints: {:?}, checkpoint_start: {:?}", struct_t.depth_curr, struct_t.arr_idx, struct_t.arr_tgt, struct_t.search_keys, &token.kind, struct_t.last_open, struct_t.checkpoints, struct_t.checkpoint_start);
-
-                if struct_t
-                    .depth_curr
-                    .cmp(struct_t.checkpoints.last().unwrap())
-                    == Ordering::Equal
-                {
-                    // check if inside an array and iterating to find the expected place
-                    if *struct_t.last_open.last().unwrap() == TokenType::BracketOpen
-                        && struct_t
-                            .arr_idx
-                            .last()
-                            .unwrap()
-                            .cmp(struct_t.arr_tgt.last().unwrap())
-                            == Ordering::Equal
-                    {
-                        let (first, end) = token_pos(&token.buf)?;
-
-                        // terminal point
-                        if struct_t.checkpoints.len() == 1
-                            && struct_t.checkpoint_start.len() == struct_t.arr_tgt_size
-                        {
-                            debug!("[checkpoint ended]");
-
-                            // add 1 to starting index to exclude commas or brackets
-                            let result = find_str(
-                                &mut seeker,
-                                (struct_t.checkpoint_start.last().unwrap()) + 1,
-                                end + stream_t.last_stream_pos,
-                            )
-                            .unwrap();
-                            return Ok(sanitize_output(result.as_str()));
-                        } else {
-                            debug!("[checkpoint started]");
-
-                            struct_t
-                                .checkpoint_start
-                                .push(first + stream_t.last_stream_pos);
-                            if struct_t.arr_tgt.len() > 1 {
-                                struct_t.arr_tgt.pop();
-                            }
-                        }
-
-                        if struct_t.checkpoints.len() > 1 {
-                            struct_t.checkpoints.pop();
-                        }
-                    } else if *struct_t.last_open.last().unwrap() == TokenType::CurlyOpen {
-                        if token.kind == TokenType::String && struct_t.last_token_key_delimiter {
-                            let (first, end) = token_pos(&token.buf)?;
-                            let key = find_str(
-                                &mut seeker,
-                                first + stream_t.last_stream_pos,
-                                end + stream_t.last_stream_pos,
-                            )
-                            .unwrap();
-                            if key
-                                .trim_start_matches('\"')
-                                .trim_end_matches('\"')
-                                .cmp(struct_t.search_keys.last().unwrap())
-                                == Ordering::Equal
-                            {
-                                debug!(">>>found next key: {:?}", key);
-                                struct_t.search_keys.pop();
-
-                                if struct_t.checkpoints.len() > 1 {
-                                    struct_t.checkpoints.pop();
-                                }
-                            }
-                            struct_t.last_token_key_delimiter = false;
-                        } else if (token.kind == TokenType::CurlyOpen
-                            || token.kind == TokenType::Comma)
-                            && !struct_t.search_keys.is_empty()
-                        {
-                            if token.kind == TokenType::CurlyOpen {
-                                debug!("\\checkpoint started\\");
-                            }
-                            struct_t.last_token_key_delimiter = true;
-                        } else if struct_t.search_keys.is_empty() && struct_t.checkpoints.len() == 1
-                        {
-                            let (first, end) = token_pos(&token.buf)?;
-                            match token.kind {
-                                TokenType::String
-                                | TokenType::Number
-                                | TokenType::BooleanFalse
-                                | TokenType::BooleanTrue
-                                | TokenType::Null => {
-                                    let result = find_str(
-                                        &mut seeker,
-                                        first + stream_t.last_stream_pos,
-                                        end + stream_t.last_stream_pos,
-                                    )
-                                    .unwrap();
-                                    return Ok(sanitize_output(result.as_str()));
-                                }
-                                TokenType::Colon => {
-                                    struct_t
-                                        .checkpoint_start
-                                        .push(first + stream_t.last_stream_pos);
-                                }
-                                TokenType::CurlyClose | TokenType::BracketClose => {
-                                    let result = find_str(
-                                        &mut seeker,
-                                        struct_t.checkpoint_start.last().unwrap() + 1,
-                                        end + stream_t.last_stream_pos,
-                                    )
-                                    .unwrap();
-                                    return Ok(sanitize_output(result.as_str()));
-                                }
-                                _ => {}
-                            }
-                            struct_t.last_token_key_delimiter = false;
-                        }
-                    }
-                }
-
-                if token_iter.peek().is_none() {
-                    break;
-                }
+            if seeker.is_none() {
+                stream_t.retained.extend_from_slice(last_chunk.as_bytes());
             }
+            // Process the chunk that ends on a token boundary
+            let (retained, retained_pos) = (&stream_t.retained, stream_t.retained_pos);
+            let done = pass.lex(last_chunk.as_bytes(), stream_t.last_stream_pos, exhausted, |start, end| match seeker.as_mut() {
+                Some(seeker) => find_str(seeker, start, end),
+                None => Ok(retained_str(retained, retained_pos, start, end)),
+            })?;
+            if done {
+                break;
+            }
+            stream_t.last_chunk_len = last_chunk.len();
 
-            // Clear chunk for next iteration
-            stream_t.chunk.clear();
-            // Remove processed data from buffer
-            stream_t.buffer.clear();
+            // Remove processed data from buffer, a token cut off at its end is lexed with the next chunk
+            stream_t.buffer.drain(..stream_t.last_chunk_len);
         } else {
-            return Err("result not found");
+            // no token boundary yet, keep reading
+            continue;
         }
 
         stream_t.last_stream_pos += stream_t.last_chunk_len as u64;
+        if seeker.is_none() {
+            stream_t.release(pass.pending_from().unwrap_or(stream_t.last_stream_pos));
+        }
         debug!(
             "page finished - stream_position: {:?}",
             stream_t.last_stream_pos
         );
     }
+
+    pass.finish(stream_t.last_stream_pos)?;
+    Ok(pass.found)
+}
+
+// Matches of every query in `bytes`, lexed in place a chunk at a time, e.g. a file mapped into
+// memory. `release` is told how far the lexer got after every chunk.
+pub(crate) fn _search_slice<F: FnMut(usize)>(
+    bytes: &[u8],
+    queries: &[JsonPath],
+    mut release: F,
+) -> Result<Vec<Vec<RawMatch>>, JistError> {
+    let mut pass = Pass::new(queries);
+    for chunk in utils::token_chunks(bytes, CHUNK_SIZE) {
+        let (pos, chunk, last) = chunk?;
+        let text = |start: u64, end: u64| Ok(String::from_utf8_lossy(&bytes[start as usize..end as usize]).into_owned());
+        if pass.lex(chunk.as_bytes(), pos, last, text)? {
+            break;
+        }
+        release(pos as usize + chunk.len());
+    }
+    pass.finish(bytes.len() as u64)?;
+    Ok(pass.found)
+}
+
+// `query` searched in `bytes` by `threads` workers: the array its leading keys lead to is split
+// into windows by `parallel::array_windows` and the windows that can hold matches are searched side
+// by side. None when the path can't be split.
+pub(crate) fn search_split(
+    bytes: &[u8],
+    query: &JsonPath,
+    threads: usize,
+    range_size: usize,
+) -> Result<Option<String>, JistError> {
+    // numeric keys may be indices, they end the prefix
+    let key = |segment: &PathSegment| match segment {
+        PathSegment::Key(k) => numeric_key(k).is_none(),
+        PathSegment::Name(_) => true,
+        _ => false,
+    };
+    let prefix = query.segments.iter().take_while(|segment| key(segment)).count();
+    let Some((head, rest)) = query.segments[prefix..].split_first() else {
+        return Ok(None);
+    };
+    // the concrete paths of recursive descent matches would start inside the array
+    if query.with_paths() || window_heads(&[], head).is_none() {
+        return Ok(None);
+    }
+    let Some(windows) = parallel::array_windows(bytes, &query.segments[..prefix], threads, range_size) else {
+        return Ok(None);
+    };
+    let heads = window_heads(&windows, head).unwrap_or_default();
+
+    let found = parallel::map_parallel(heads.len(), threads, |i| {
+        let (window, head) = &heads[i];
+        let segments = std::iter::once(head.clone()).chain(rest.iter().cloned()).collect();
+        search_window(bytes, window, &JsonPath { segments, rfc: query.rfc })
+    });
+    let mut matches = Vec::new();
+    for found in found {
+        matches.extend(found?);
+    }
+    finish(query, matches).map(Some)
+}
+
+// `query` with its first `skip` segments already resolved to `bytes`, the value at stream position
+// `start`, e.g. an element looked up in an index
+pub(crate) fn search_value(bytes: &[u8], start: u64, query: &JsonPath, skip: usize) -> Result<String, JistError> {
+    let rest = JsonPath { segments: query.segments[skip..].to_vec(), rfc: query.rfc };
+    let mut matches = if rest.segments.is_empty() {
+        vec![(String::new(), 0, String::from_utf8_lossy(bytes).into_owned())]
+    } else {
+        _search_slice(bytes, std::slice::from_ref(&rest), |_| {}).map_err(|e| e.shifted(start))?.remove(0)
+    };
+    for (_, pos, _) in matches.iter_mut() {
+        *pos += start;
+    }
+    finish(query, matches)
+}
+
+// Matches of `query` in `window`, searched as an array of its own with the separators around it
+// taken for brackets
+fn search_window(bytes: &[u8], window: &Window, query: &JsonPath) -> Result<Vec<RawMatch>, JistError> {
+    let mut pass = Pass::new(std::slice::from_ref(query));
+    let mut text = |start: u64, end: u64| Ok(String::from_utf8_lossy(&bytes[start as usize..end as usize]).into_owned());
+    let (start, end) = (window.start, window.end);
+    if !pass.token(&TokenType::BracketOpen, start, start + 1, b"[", &mut text)? {
+        for chunk in utils::token_chunks(&bytes[start as usize + 1..end as usize], CHUNK_SIZE) {
+            let (pos, chunk, last) = chunk.map_err(|e| e.shifted(start + 1))?;
+            if pass.lex(chunk.as_bytes(), start + 1 + pos, last, &mut text)? {
+                return Ok(pass.found.remove(0));
+            }
+        }
+        pass.token(&TokenType::BracketClose, end, end + 1, b"]", &mut text)?;
+    }
+    Ok(pass.found.remove(0))
+}
+
+// input between stream positions `start` and `end`, out of the input retained from `retained_pos`
+fn retained_str(retained: &[u8], retained_pos: u64, start: u64, end: u64) -> String {
+    String::from_utf8_lossy(&retained[(start - retained_pos) as usize..(end - retained_pos) as usize]).into_owned()
+}
+
+// The trackers of one pass over the input, one per query, and the matches found so far. The
+// concrete path of a match is only filled in for recursive descent.
+struct Pass {
+    trackers: Vec<JStructTracker>,
+    definite: Vec<bool>,
+    found: Vec<Vec<RawMatch>>,
+}
+
+impl Pass {
+    fn new(queries: &[JsonPath]) -> Pass {
+        Pass {
+            trackers: queries.iter().map(JStructTracker::new).collect(),
+            definite: queries.iter().map(|query| query.is_definite()).collect(),
+            found: vec![Vec::new(); queries.len()],
+        }
+    }
+
+    // every query is definite and has found its value
+    fn done(&self) -> bool {
+        self.definite.iter().zip(&self.found).all(|(d, m)| *d && !m.is_empty())
+    }
+
+    // Lex `chunk`, which starts at stream position `pos` and ends on a token boundary. `text` reads
+    // the input between two stream positions back. Returns true once the pass is done.
+    fn lex<F>(&mut self, chunk: &[u8], pos: u64, last: bool, mut text: F) -> Result<bool, JistError>
+    where
+        F: FnMut(u64, u64) -> Result<String, JistError>,
+    {
+        // the lexer needs a delimiter after the last token of the input
+        let delimiter = last.then_some(b'\n');
+        for token in Lexer::new(chunk.iter().copied().chain(delimiter), BufferType::Span) {
+            let (first, end) = token_pos(&token.buf)?;
+            if self.token(&token.kind, first + pos, end + pos, &chunk[first as usize..end as usize], &mut text)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // Feed a single token at stream positions [first, end) to every tracker. Returns true once the
+    // pass is done.
+    fn token<F>(&mut self, kind: &TokenType, first: u64, end: u64, raw: &[u8], text: &mut F) -> Result<bool, JistError>
+    where
+        F: FnMut(u64, u64) -> Result<String, JistError>,
+    {
+        let trackers = self.trackers.iter_mut().zip(self.found.iter_mut()).zip(&self.definite);
+        for ((struct_t, matches), definite) in trackers {
+            if *definite && !matches.is_empty() {
+                continue;
+            }
+            struct_t.advance(kind, first, end, raw)?;
+            debug!("arr_idx: {:?}, obj_key: {:?}, kind: {:?}, last_open: {:?}, path_states: {:?}, captures: {:?}", struct_t.arr_idx, struct_t.obj_key, kind, struct_t.last_open, struct_t.path_states, struct_t.captures);
+
+            for span in std::mem::take(&mut struct_t.spans) {
+                let result = text(span.start, span.end)?;
+                if span.state == struct_t.search_path.len() && !span.filtered {
+                    matches.push((span.path, span.start, result));
+                } else {
+                    // held back array element or filter candidate, the rest of the path is applied in memory
+                    let value: Value = serde_json::from_str(result.trim())
+                        .map_err(|e| value_error(span.start, &result, e))?;
+                    if span.filtered && !struct_t.search_path[span.state - 1].test(&value) {
+                        continue;
+                    }
+                    let mut selected = Vec::new();
+                    select(&value, &struct_t.search_path[span.state..], &span.path, &mut selected);
+                    matches.extend(selected.iter().map(|(p, v)| (p.clone(), span.start, v.to_string())));
+                }
+            }
+        }
+        Ok(self.done())
+    }
+
+    // The input ended at stream position `end`, queries still searching need it to be complete
+    fn finish(&self, end: u64) -> Result<(), JistError> {
+        let searching = self.trackers.iter().zip(&self.found).zip(&self.definite).filter(|((_, matches), definite)| !**definite || matches.is_empty());
+        for ((struct_t, _), _) in searching {
+            struct_t.finish(end)?;
+        }
+        Ok(())
+    }
+
+    // earliest stream position a value still to be output can start at
+    fn pending_from(&self) -> Option<u64> {
+        self.trackers
+            .iter()
+            .zip(&self.found)
+            .zip(&self.definite)
+            .filter(|((_, matches), definite)| !**definite || matches.is_empty())
+            .filter_map(|((struct_t, _), _)| struct_t.retain_from())
+            .min()
+    }
 }
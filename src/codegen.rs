@@ -0,0 +1,551 @@
+use crate::presence::parse_key;
+use crate::stats::is_enum;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashSet};
+
+// Languages type definitions are emitted in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Language {
+    TypeScript,
+    // structs and enums deriving serde's Serialize and Deserialize
+    Rust,
+    Go,
+}
+
+const RUST_KEYWORDS: [&str; 38] = [
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn", "for", "if", "impl", "in",
+    "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true",
+    "type", "unsafe", "use", "where", "while",
+];
+
+// type names that would shadow standard or imported ones in one of the languages
+const RESERVED: [&str; 19] = [
+    "Array", "Boolean", "Box", "Date", "Deserialize", "Err", "Map", "None", "Number", "Object", "Ok", "Option", "Record", "Result", "Serialize",
+    "Set", "Some", "String", "Vec",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    // anything, e.g. the elements of an array that was always empty
+    Any,
+    // a struct or an enum of strings
+    Named(String),
+    // a union of types, e.g. of the elements of ["number","string"]
+    Union(String),
+    Array(Box<Type>),
+    Nullable(Box<Type>),
+}
+
+#[derive(Debug)]
+struct Field {
+    key: String,
+    kind: Type,
+    // some objects don't have the key
+    optional: bool,
+}
+
+#[derive(Debug)]
+enum Def {
+    Alias(String, Type),
+    Struct(String, Vec<Field>),
+    Enum(String, Vec<String>),
+    Union(String, Vec<Type>),
+}
+
+// Definitions in the order their names were taken, parents before the types of their members
+struct Types {
+    defs: Vec<Option<Def>>,
+    names: HashSet<String>,
+}
+
+// Type definitions of a jist schema, as `unionize_schema` or the merged NDJSON records give it:
+// optional keys marked `key?` and nullable types `type|null`. A top-level object is `Root`, the
+// records of a top-level array `Item`, and nested types are named after their key path, e.g. `DstGeo`
+// for the object at `dst.geo` and `TagsItem` for the elements of `tags`. Object variants of an array
+// are merged, a key they don't all have is optional, and elements of several types are a union.
+pub fn emit(schema: &Value, language: Language) -> String {
+    let mut types = Types { defs: Vec::new(), names: RESERVED.iter().map(|name| name.to_string()).collect() };
+    match schema {
+        Value::Object(members) if !is_enum(members) => {
+            types.build(&[schema], "Root", "");
+        }
+        Value::Array(variants) => {
+            types.names.insert("Root".to_string());
+            let slot = types.reserve();
+            let item = types.build(&variants.iter().collect::<Vec<_>>(), "Item", "");
+            types.defs[slot] = Some(Def::Alias("Root".to_string(), Type::Array(Box::new(item))));
+        }
+        _ => {
+            types.names.insert("Root".to_string());
+            let slot = types.reserve();
+            let kind = types.build(&[schema], "Root", "");
+            types.defs[slot] = Some(Def::Alias("Root".to_string(), kind));
+        }
+    }
+    let defs = types.defs.iter().flatten();
+    let rendered: Vec<String> = match language {
+        Language::TypeScript => defs.map(typescript).collect(),
+        Language::Rust => std::iter::once("use serde::{Deserialize, Serialize};".to_string()).chain(defs.map(rust)).collect(),
+        Language::Go => defs.map(go).collect(),
+    };
+    rendered.join("\n\n")
+}
+
+impl Types {
+    fn reserve(&mut self) -> usize {
+        self.defs.push(None);
+        self.defs.len() - 1
+    }
+
+    // `name`, or with a number appended if it's taken
+    fn unique(&mut self, name: &str) -> String {
+        let mut unique = name.to_string();
+        let mut n = 2;
+        while !self.names.insert(unique.clone()) {
+            unique = format!("{}{}", name, n);
+            n += 1;
+        }
+        unique
+    }
+
+    // The type of a value matching any of `variants`, named `name` where it needs a definition.
+    // Types of members are named starting with `prefix`.
+    fn build(&mut self, variants: &[&Value], name: &str, prefix: &str) -> Type {
+        let mut nullable = false;
+        let mut scalars: Vec<Type> = Vec::new();
+        let mut values: Vec<String> = Vec::new();
+        let mut objects: Vec<&Map<String, Value>> = Vec::new();
+        let mut elements: Vec<&Value> = Vec::new();
+        let mut arrays = false;
+        for variant in variants {
+            match variant {
+                Value::String(kinds) => {
+                    for kind in kinds.split('|') {
+                        let scalar = match kind {
+                            "null" => {
+                                nullable = true;
+                                continue;
+                            }
+                            "number" => Type::Number,
+                            "integer" => Type::Integer,
+                            "boolean" => Type::Boolean,
+                            // strings and their formats
+                            _ => Type::String,
+                        };
+                        if !scalars.contains(&scalar) {
+                            scalars.push(scalar);
+                        }
+                    }
+                }
+                Value::Object(members) if is_enum(members) => {
+                    for value in members["enum"].as_array().into_iter().flatten() {
+                        match value.as_str() {
+                            Some(value) if !values.iter().any(|v| v == value) => values.push(value.to_string()),
+                            Some(_) => {}
+                            None => nullable = true,
+                        }
+                    }
+                }
+                Value::Object(members) => objects.push(members),
+                Value::Array(items) => {
+                    arrays = true;
+                    elements.extend(items);
+                }
+                _ => {}
+            }
+        }
+        // integers are numbers too, and any string covers the values of an enum
+        if scalars.contains(&Type::Number) {
+            scalars.retain(|scalar| *scalar != Type::Integer);
+        }
+        if scalars.contains(&Type::String) {
+            values.clear();
+        }
+
+        let kinds = scalars.len() + usize::from(!values.is_empty()) + usize::from(!objects.is_empty()) + usize::from(arrays);
+        let union = (kinds > 1).then(|| (self.unique(name), self.reserve()));
+        let component = |suffix: &str| if union.is_some() { format!("{}{}", name, suffix) } else { name.to_string() };
+        let mut members = scalars;
+        if !values.is_empty() {
+            let name = self.unique(&component("Value"));
+            self.defs.push(Some(Def::Enum(name.clone(), values)));
+            members.push(Type::Named(name));
+        }
+        if !objects.is_empty() {
+            let name = self.unique(&component("Object"));
+            let slot = self.reserve();
+            let fields = self.fields(&objects, if union.is_some() { &name } else { prefix });
+            self.defs[slot] = Some(Def::Struct(name.clone(), fields));
+            members.push(Type::Named(name));
+        }
+        if arrays {
+            let item = format!("{}Item", component("Array"));
+            members.push(Type::Array(Box::new(self.build(&elements, &item, &item))));
+        }
+        let kind = match union {
+            Some((name, slot)) => {
+                self.defs[slot] = Some(Def::Union(name.clone(), members));
+                Type::Union(name)
+            }
+            None => members.pop().unwrap_or(Type::Any),
+        };
+        match kind {
+            Type::Any => Type::Any,
+            kind if nullable => Type::Nullable(Box::new(kind)),
+            kind => kind,
+        }
+    }
+
+    // members of objects merged, in the order of their keys
+    fn fields(&mut self, objects: &[&Map<String, Value>], prefix: &str) -> Vec<Field> {
+        let mut members: BTreeMap<&str, (Vec<&Value>, usize, bool)> = BTreeMap::new();
+        for object in objects {
+            for (key, member) in *object {
+                let (key, marked) = parse_key(key);
+                let entry = members.entry(key).or_default();
+                entry.0.push(member);
+                entry.1 += 1;
+                entry.2 |= marked;
+            }
+        }
+        members
+            .into_iter()
+            .map(|(key, (variants, count, marked))| {
+                let name = format!("{}{}", prefix, pascal(key, false));
+                let kind = self.build(&variants, &name, &name);
+                Field { key: key.to_string(), kind, optional: marked || count < objects.len() }
+            })
+            .collect()
+    }
+}
+
+// `ip_protocol` as `IpProtocol`, keeping the case of the rest of words unless `lower`
+fn pascal(key: &str, lower: bool) -> String {
+    let mut name = String::new();
+    for word in key.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()) {
+        let mut chars = word.chars();
+        name.extend(chars.next().into_iter().flat_map(char::to_uppercase));
+        if lower {
+            name.extend(chars.flat_map(char::to_lowercase));
+        } else {
+            name.extend(chars);
+        }
+    }
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, 'X');
+    }
+    name
+}
+
+// `sensorUUID` as `sensor_uuid`, keywords as raw identifiers
+fn snake(key: &str) -> String {
+    let mut name = String::new();
+    let mut previous: Option<char> = None;
+    for c in key.chars() {
+        if !c.is_alphanumeric() {
+            if !name.ends_with('_') {
+                name.push('_');
+            }
+        } else {
+            if c.is_uppercase() && previous.is_some_and(|p| p.is_lowercase() || p.is_ascii_digit()) && !name.ends_with('_') {
+                name.push('_');
+            }
+            name.extend(c.to_lowercase());
+        }
+        previous = Some(c);
+    }
+    let name = name.trim_matches('_').to_string();
+    match name.as_str() {
+        "" => "field".to_string(),
+        "self" | "super" | "crate" => format!("{}_", name),
+        _ if name.starts_with(|c: char| c.is_ascii_digit()) => format!("_{}", name),
+        _ if RUST_KEYWORDS.contains(&name.as_str()) => format!("r#{}", name),
+        _ => name,
+    }
+}
+
+// names of fields or variants, numbered when two come out the same
+fn distinct(names: impl Iterator<Item = String>) -> Vec<String> {
+    let mut taken = HashSet::new();
+    names
+        .map(|name| {
+            let mut unique = name.clone();
+            let mut n = 2;
+            while !taken.insert(unique.clone()) {
+                unique = format!("{}{}", name, n);
+                n += 1;
+            }
+            unique
+        })
+        .collect()
+}
+
+fn ts_type(kind: &Type) -> String {
+    match kind {
+        Type::String => "string".to_string(),
+        Type::Number | Type::Integer => "number".to_string(),
+        Type::Boolean => "boolean".to_string(),
+        Type::Any => "unknown".to_string(),
+        Type::Named(name) | Type::Union(name) => name.clone(),
+        Type::Array(item) if matches!(**item, Type::Nullable(_)) => format!("({})[]", ts_type(item)),
+        Type::Array(item) => format!("{}[]", ts_type(item)),
+        Type::Nullable(inner) => format!("{} | null", ts_type(inner)),
+    }
+}
+
+fn typescript(def: &Def) -> String {
+    match def {
+        Def::Alias(name, alias) => format!("export type {} = {};", name, ts_type(alias)),
+        Def::Struct(name, fields) => {
+            let mut lines = vec![format!("export interface {} {{", name)];
+            for field in fields {
+                let identifier = field.key.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '$')
+                    && field.key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '$');
+                let key = if identifier { field.key.clone() } else { Value::String(field.key.clone()).to_string() };
+                lines.push(format!("  {}{}: {};", key, if field.optional { "?" } else { "" }, ts_type(&field.kind)));
+            }
+            lines.push("}".to_string());
+            lines.join("\n")
+        }
+        Def::Enum(name, values) => {
+            let values: Vec<String> = values.iter().map(|value| Value::String(value.clone()).to_string()).collect();
+            format!("export type {} = {};", name, values.join(" | "))
+        }
+        Def::Union(name, members) => {
+            let members: Vec<String> = members.iter().map(ts_type).collect();
+            format!("export type {} = {};", name, members.join(" | "))
+        }
+    }
+}
+
+fn rust_type(kind: &Type) -> String {
+    match kind {
+        Type::String => "String".to_string(),
+        Type::Number => "f64".to_string(),
+        Type::Integer => "i64".to_string(),
+        Type::Boolean => "bool".to_string(),
+        Type::Any => "serde_json::Value".to_string(),
+        Type::Named(name) | Type::Union(name) => name.clone(),
+        Type::Array(item) => format!("Vec<{}>", rust_type(item)),
+        Type::Nullable(inner) => format!("Option<{}>", rust_type(inner)),
+    }
+}
+
+fn rust(def: &Def) -> String {
+    const DERIVE: &str = "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]";
+    match def {
+        Def::Alias(name, alias) => format!("pub type {} = {};", name, rust_type(alias)),
+        Def::Struct(name, fields) => {
+            let mut lines = vec![DERIVE.to_string(), format!("pub struct {} {{", name)];
+            let names = distinct(fields.iter().map(|field| snake(&field.key)));
+            for (field, field_name) in fields.iter().zip(names) {
+                if field_name.trim_start_matches("r#") != field.key {
+                    lines.push(format!("    #[serde(rename = {})]", Value::String(field.key.clone())));
+                }
+                let field_kind = match &field.kind {
+                    _ if !field.optional => rust_type(&field.kind),
+                    Type::Nullable(_) => {
+                        lines.push("    #[serde(default, skip_serializing_if = \"Option::is_none\")]".to_string());
+                        rust_type(&field.kind)
+                    }
+                    inner => {
+                        lines.push("    #[serde(default, skip_serializing_if = \"Option::is_none\")]".to_string());
+                        format!("Option<{}>", rust_type(inner))
+                    }
+                };
+                lines.push(format!("    pub {}: {},", field_name, field_kind));
+            }
+            lines.push("}".to_string());
+            lines.join("\n")
+        }
+        Def::Enum(name, values) => {
+            let mut lines = vec!["#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]".to_string(), format!("pub enum {} {{", name)];
+            for (value, variant) in values.iter().zip(distinct(values.iter().map(|value| pascal(value, true)))) {
+                if variant != *value {
+                    lines.push(format!("    #[serde(rename = {})]", Value::String(value.clone())));
+                }
+                lines.push(format!("    {},", variant));
+            }
+            lines.push("}".to_string());
+            lines.join("\n")
+        }
+        Def::Union(name, members) => {
+            let mut lines = vec![DERIVE.to_string(), "#[serde(untagged)]".to_string(), format!("pub enum {} {{", name)];
+            for member in members {
+                let variant = match member {
+                    Type::String => "String".to_string(),
+                    Type::Number => "Number".to_string(),
+                    Type::Integer => "Integer".to_string(),
+                    Type::Boolean => "Bool".to_string(),
+                    Type::Array(_) => "Array".to_string(),
+                    Type::Named(named) => named.strip_prefix(name.as_str()).filter(|rest| !rest.is_empty()).unwrap_or(named).to_string(),
+                    _ => "Other".to_string(),
+                };
+                lines.push(format!("    {}({}),", variant, rust_type(member)));
+            }
+            lines.push("}".to_string());
+            lines.join("\n")
+        }
+    }
+}
+
+fn go_type(kind: &Type) -> String {
+    match kind {
+        Type::String => "string".to_string(),
+        Type::Number => "float64".to_string(),
+        Type::Integer => "int64".to_string(),
+        Type::Boolean => "bool".to_string(),
+        Type::Any => "any".to_string(),
+        Type::Named(name) | Type::Union(name) => name.clone(),
+        Type::Array(item) => format!("[]{}", go_type(item)),
+        Type::Nullable(inner) => go_pointer(inner),
+    }
+}
+
+// slices and interfaces are nil already
+fn go_pointer(inner: &Type) -> String {
+    match inner {
+        Type::Array(_) | Type::Any | Type::Union(_) | Type::Nullable(_) => go_type(inner),
+        inner => format!("*{}", go_type(inner)),
+    }
+}
+
+fn go(def: &Def) -> String {
+    match def {
+        Def::Alias(name, alias) => format!("type {} {}", name, go_type(alias)),
+        Def::Struct(name, fields) => {
+            let names = distinct(fields.iter().map(|field| pascal(&field.key, false)));
+            let rows: Vec<[String; 3]> = fields
+                .iter()
+                .zip(names)
+                .map(|(field, field_name)| {
+                    let field_kind = if field.optional { go_pointer(&field.kind) } else { go_type(&field.kind) };
+                    let tag = format!("`json:\"{}{}\"`", field.key.replace('"', "\\\""), if field.optional { ",omitempty" } else { "" });
+                    [field_name, field_kind, tag]
+                })
+                .collect();
+            let name_width = rows.iter().map(|row| row[0].len()).max().unwrap_or(0);
+            let kind_width = rows.iter().map(|row| row[1].len()).max().unwrap_or(0);
+            let mut lines = vec![format!("type {} struct {{", name)];
+            lines.extend(rows.iter().map(|[field, kind, tag]| format!("\t{:name_width$} {:kind_width$} {}", field, kind, tag)));
+            lines.push("}".to_string());
+            lines.join("\n")
+        }
+        Def::Enum(name, values) => {
+            let consts = distinct(values.iter().map(|value| format!("{}{}", name, pascal(value, false))));
+            let width = consts.iter().map(String::len).max().unwrap_or(0);
+            let mut lines = vec![format!("type {} string", name), String::new(), "const (".to_string()];
+            for (value, constant) in values.iter().zip(consts) {
+                lines.push(format!("\t{:width$} {} = {}", constant, name, Value::String(value.clone())));
+            }
+            lines.push(")".to_string());
+            lines.join("\n")
+        }
+        Def::Union(name, members) => {
+            let members: Vec<String> = members.iter().map(go_type).collect();
+            format!("// {} is one of {}.\ntype {} any", name, members.join(", "), name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample() -> Value {
+        json!([{
+            "dst": {"geo": {"lat": "number"}, "port": "integer"},
+            "id": "integer",
+            "ip_protocol": {"enum": ["TCP", "UDP"]},
+            "note?": "string|null",
+            "tags": ["number", "string"],
+            "type": "string",
+            "events": [{"at": "date-time"}, {"at": "date-time", "code": "number"}]
+        }])
+    }
+
+    #[test]
+    fn typescript_test() {
+        assert_eq!(
+            emit(&sample(), Language::TypeScript),
+            r#"export type Root = Item[];
+
+export interface Item {
+  dst: Dst;
+  events: EventsItem[];
+  id: number;
+  ip_protocol: IpProtocol;
+  note?: string | null;
+  tags: TagsItem[];
+  type: string;
+}
+
+export interface Dst {
+  geo: DstGeo;
+  port: number;
+}
+
+export interface DstGeo {
+  lat: number;
+}
+
+export interface EventsItem {
+  at: string;
+  code?: number;
+}
+
+export type IpProtocol = "TCP" | "UDP";
+
+export type TagsItem = number | string;"#
+        );
+        assert_eq!(emit(&json!({"a b": ["null"]}), Language::TypeScript), "export interface Root {\n  \"a b\": unknown[];\n}");
+    }
+
+    #[test]
+    fn rust_test() {
+        let emitted = emit(&sample(), Language::Rust);
+        assert!(emitted.starts_with("use serde::{Deserialize, Serialize};\n\npub type Root = Vec<Item>;\n\n"));
+        assert!(emitted.contains(
+            r#"pub struct Item {
+    pub dst: Dst,
+    pub events: Vec<EventsItem>,
+    pub id: i64,
+    pub ip_protocol: IpProtocol,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub tags: Vec<TagsItem>,
+    pub r#type: String,
+}"#
+        ));
+        assert!(emitted.contains("pub enum IpProtocol {\n    #[serde(rename = \"TCP\")]\n    Tcp,\n    #[serde(rename = \"UDP\")]\n    Udp,\n}"));
+        assert!(emitted.contains("#[serde(untagged)]\npub enum TagsItem {\n    Number(f64),\n    String(String),\n}"));
+        assert!(emitted.contains("    #[serde(default, skip_serializing_if = \"Option::is_none\")]\n    pub code: Option<f64>,"));
+        assert!(emit(&json!({"string": {"a": "number"}}), Language::Rust).contains("pub string: String2,"));
+        assert_eq!(snake("sensorUUID"), "sensor_uuid");
+        assert_eq!(snake("2nd-place"), "_2nd_place");
+    }
+
+    #[test]
+    fn go_test() {
+        let emitted = emit(&sample(), Language::Go);
+        assert!(emitted.starts_with("type Root []Item\n\n"));
+        assert!(emitted.contains(
+            "type Item struct {\n\
+             \tDst        Dst          `json:\"dst\"`\n\
+             \tEvents     []EventsItem `json:\"events\"`\n\
+             \tId         int64        `json:\"id\"`\n\
+             \tIpProtocol IpProtocol   `json:\"ip_protocol\"`\n\
+             \tNote       *string      `json:\"note,omitempty\"`\n\
+             \tTags       []TagsItem   `json:\"tags\"`\n\
+             \tType       string       `json:\"type\"`\n\
+             }"
+        ));
+        assert!(emitted.contains("type IpProtocol string\n\nconst (\n\tIpProtocolTCP IpProtocol = \"TCP\"\n\tIpProtocolUDP IpProtocol = \"UDP\"\n)"));
+        assert!(emitted.contains("// TagsItem is one of float64, string.\ntype TagsItem any"));
+    }
+}
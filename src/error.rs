@@ -0,0 +1,61 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+// Errors returned by the library API
+#[derive(Debug, Clone, PartialEq)]
+pub enum JistError {
+    // the search key matched nothing
+    NotFound,
+    // the input is not valid JSON, `offset` is the byte position the problem was found at when known
+    MalformedJson { offset: Option<u64>, message: String },
+    // the input could not be read
+    Io(String),
+    // the search key can't be parsed, or uses JSONPath features that aren't supported
+    InvalidPath(&'static str),
+    // the input is too large for the in-memory parser, the streaming parser still handles it
+    CapacityExceeded,
+    // the in-memory parser can't answer the search key, the streaming parser still handles it
+    UnsupportedPath,
+    // no data or no search key was given
+    InvalidInput(&'static str),
+}
+
+impl JistError {
+    pub(crate) fn malformed(offset: Option<u64>, message: impl fmt::Display) -> JistError {
+        JistError::MalformedJson { offset, message: message.to_string() }
+    }
+
+    // the error for a part of the input that starts at byte `pos`, e.g. a line of NDJSON
+    pub(crate) fn shifted(self, pos: u64) -> JistError {
+        match self {
+            JistError::MalformedJson { offset, message } => JistError::MalformedJson { offset: offset.map(|offset| offset + pos), message },
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for JistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JistError::NotFound => write!(f, "result not found"),
+            JistError::MalformedJson { offset: Some(offset), message } => {
+                write!(f, "malformed JSON at byte {}: {}", offset, message)
+            }
+            JistError::MalformedJson { offset: None, message } => write!(f, "malformed JSON: {}", message),
+            JistError::Io(message) => write!(f, "I/O error: {}", message),
+            JistError::InvalidPath(reason) => write!(f, "{}", reason),
+            JistError::CapacityExceeded => write!(f, "input is too large for the in-memory parser"),
+            JistError::UnsupportedPath => write!(f, "search key is not supported by the in-memory parser"),
+            JistError::InvalidInput(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl Error for JistError {}
+
+impl From<io::Error> for JistError {
+    fn from(error: io::Error) -> Self {
+        JistError::Io(error.to_string())
+    }
+}
@@ -0,0 +1,129 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+// Formats strings are checked for, named like the JSON Schema `format` keyword. The first one a
+// string has is its format.
+pub(crate) const FORMATS: [&str; 7] = ["date-time", "date", "uuid", "email", "ipv4", "ipv6", "uri"];
+
+// checks of each of FORMATS
+const CHECKS: [fn(&[u8]) -> bool; FORMATS.len()] = [is_date_time, is_date, is_uuid, is_email, is_ipv4, is_ipv6, is_uri];
+
+// index into FORMATS of the format of a string
+pub(crate) fn detect(s: &str) -> Option<usize> {
+    CHECKS.iter().position(|check| check(s.as_bytes()))
+}
+
+// whether a string has the format named `format`, one of FORMATS
+pub(crate) fn matches(format: &str, s: &str) -> bool {
+    FORMATS.iter().position(|name| *name == format).is_some_and(|i| CHECKS[i](s.as_bytes()))
+}
+
+fn digits(b: &[u8]) -> bool {
+    !b.is_empty() && b.iter().all(u8::is_ascii_digit)
+}
+
+// a two digit number up to `max`
+fn upto(b: &[u8], max: u8) -> bool {
+    digits(b) && b.len() == 2 && (b[0] - b'0') * 10 + (b[1] - b'0') <= max
+}
+
+// RFC 3339 full-date, e.g. 2024-06-01
+fn is_date(b: &[u8]) -> bool {
+    b.len() == 10
+        && digits(&b[..4])
+        && b[4] == b'-'
+        && upto(&b[5..7], 12)
+        && &b[5..7] != b"00"
+        && b[7] == b'-'
+        && upto(&b[8..10], 31)
+        && &b[8..10] != b"00"
+}
+
+// RFC 3339 date-time, e.g. 2024-06-01T12:30:00.5Z or 2024-06-01T12:30:00+02:00
+fn is_date_time(b: &[u8]) -> bool {
+    if b.len() < 20 || !is_date(&b[..10]) || !matches!(b[10], b'T' | b't' | b' ') {
+        return false;
+    }
+    let time = &b[11..];
+    if time.len() < 9 || !upto(&time[..2], 23) || time[2] != b':' || !upto(&time[3..5], 59) || time[5] != b':' || !upto(&time[6..8], 60) {
+        return false;
+    }
+    let mut rest = &time[8..];
+    if let Some(fraction) = rest.strip_prefix(b".") {
+        let len = fraction.iter().take_while(|c| c.is_ascii_digit()).count();
+        if len == 0 {
+            return false;
+        }
+        rest = &fraction[len..];
+    }
+    match rest {
+        [b'Z' | b'z'] => true,
+        [b'+' | b'-', h1, h2, b':', m1, m2] => upto(&[*h1, *h2], 23) && upto(&[*m1, *m2], 59),
+        _ => false,
+    }
+}
+
+// e.g. 123e4567-e89b-12d3-a456-426614174000
+fn is_uuid(b: &[u8]) -> bool {
+    b.len() == 36 && b.iter().enumerate().all(|(i, c)| if matches!(i, 8 | 13 | 18 | 23) { *c == b'-' } else { c.is_ascii_hexdigit() })
+}
+
+// local@domain.tld, without whitespace
+fn is_email(b: &[u8]) -> bool {
+    let Some(at) = b.iter().position(|c| *c == b'@') else {
+        return false;
+    };
+    let (local, domain) = (&b[..at], &b[at + 1..]);
+    !local.is_empty()
+        && domain.contains(&b'.')
+        && !domain.starts_with(b".")
+        && !domain.ends_with(b".")
+        && !b.iter().any(|c| c.is_ascii_whitespace() || *c == b'"')
+        && !domain.contains(&b'@')
+}
+
+fn is_ipv4(b: &[u8]) -> bool {
+    b.first().is_some_and(u8::is_ascii_digit) && std::str::from_utf8(b).is_ok_and(|s| s.parse::<Ipv4Addr>().is_ok())
+}
+
+fn is_ipv6(b: &[u8]) -> bool {
+    b.contains(&b':') && std::str::from_utf8(b).is_ok_and(|s| s.parse::<Ipv6Addr>().is_ok())
+}
+
+// scheme://anything without whitespace, e.g. https://example.com/a?b=c
+fn is_uri(b: &[u8]) -> bool {
+    let Some(colon) = b.windows(3).position(|w| w == b"://") else {
+        return false;
+    };
+    let scheme = &b[..colon];
+    scheme.first().is_some_and(u8::is_ascii_alphabetic)
+        && scheme.iter().all(|c| c.is_ascii_alphanumeric() || matches!(c, b'+' | b'-' | b'.'))
+        && b.len() > colon + 3
+        && !b.iter().any(u8::is_ascii_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_test() {
+        let format = |s| detect(s).map(|i| FORMATS[i]);
+        assert_eq!(format("2024-06-01T12:30:00Z"), Some("date-time"));
+        assert_eq!(format("2024-06-01t12:30:00.123+02:00"), Some("date-time"));
+        assert_eq!(format("2024-06-01T24:30:00Z"), None);
+        assert_eq!(format("2024-06-01T12:30:00"), None);
+        assert_eq!(format("2024-06-01"), Some("date"));
+        assert_eq!(format("2024-13-01"), None);
+        assert_eq!(format("123e4567-e89b-12d3-a456-426614174000"), Some("uuid"));
+        assert_eq!(format("123e4567-e89b-12d3-a456-42661417400g"), None);
+        assert_eq!(format("ops@example.com"), Some("email"));
+        assert_eq!(format("ops@localhost"), None);
+        assert_eq!(format("10.0.12.7"), Some("ipv4"));
+        assert_eq!(format("10.0.12.256"), None);
+        assert_eq!(format("fe80::1"), Some("ipv6"));
+        assert_eq!(format("https://example.com/a?b=c"), Some("uri"));
+        assert_eq!(format("see https://example.com"), None);
+        assert_eq!(format("UTC+2"), None);
+        assert!(matches("uri", "https://example.com") && !matches("email", "https://example.com"));
+    }
+}
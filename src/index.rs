@@ -0,0 +1,419 @@
+use crate::buf_parser;
+use crate::error::JistError;
+use crate::input::Mapped;
+use crate::model::json_path::JsonPath;
+use crate::model::path_segment::PathSegment;
+use crate::structural::Scan;
+use crate::utils::{self, json_str, numeric_key};
+use log::debug;
+use memchr::memchr;
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8; 4] = b"JIDX";
+const VERSION: u32 = 1;
+const HEADER_LEN: u64 = 64;
+
+// What the element offsets of an index point at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    // the elements of the top-level array
+    Array,
+    // a top-level object or scalar, there are no elements
+    Document,
+    // the lines of NDJSON
+    Records,
+}
+
+// The sidecar of `file` written by `build`, next to it as `file.jidx`. It starts with a header
+// holding the size and modification time of `file`, followed by the start offset of every element,
+// then the members recorded by path, sorted by path so they can be looked up with a binary search:
+//
+// magic, version u32, size u64, mtime seconds u64, mtime nanoseconds u32, kind u8, 3 bytes padding,
+// element count u64, end of the last element u64, member count u64, 8 bytes padding
+// element offsets, u64 each
+// member offsets from the first member, u64 each
+// members, each start u64, end u64, path length u32 and the path as a JSON array of keys and indices
+//
+// Numbers are little endian.
+pub struct Index {
+    index: File,
+    pub kind: Kind,
+    count: u64,
+    // position of the closing bracket of the array, or the size of the file for NDJSON
+    end: u64,
+    members: u64,
+}
+
+pub fn sidecar(file: &str) -> String {
+    format!("{}.jidx", file)
+}
+
+// size and modification time of `file`, an index built for other ones is out of date
+fn stamp(file: &str) -> Result<(u64, u64, u32), JistError> {
+    let metadata = fs::metadata(file)?;
+    let mtime = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok((metadata.len(), mtime.as_secs(), mtime.subsec_nanos()))
+}
+
+// Index `file`: the start of every top-level array element or NDJSON line, and the members of
+// objects at any of `depths` (1 for the members of the top-level object). Returns the number of
+// elements and members recorded.
+pub fn build(file: &str, depths: &[usize]) -> Result<(u64, u64), JistError> {
+    let map = Mapped::open(file)?.ok_or(JistError::InvalidInput("compressed files can't be indexed"))?;
+    let (size, secs, nanos) = stamp(file)?;
+    let path = sidecar(file);
+    let mut out = BufWriter::new(File::create(&path)?);
+    out.write_all(&[0; HEADER_LEN as usize])?;
+
+    let mut count: u64 = 0;
+    let mut element = |pos: u64| -> Result<(), JistError> {
+        count += 1;
+        Ok(out.write_all(&pos.to_le_bytes())?)
+    };
+    let mut members = Vec::new();
+    let (kind, end) = if utils::is_record_stream(&utils::record_head(&map[..])?) {
+        let mut pos = 0;
+        while pos < map.len() {
+            let line_end = memchr(b'\n', &map[pos..]).map_or(map.len(), |at| pos + at);
+            if let Some(at) = map[pos..line_end].iter().position(|b| !b.is_ascii_whitespace()) {
+                element((pos + at) as u64)?;
+            }
+            pos = line_end + 1;
+            map.release(pos);
+        }
+        (Kind::Records, map.len() as u64)
+    } else {
+        scan(&map, depths, &mut element, &mut members, |pos| map.release(pos))?
+    };
+    members.sort();
+
+    let mut out = out.into_inner().map_err(|e| e.into_error())?;
+    let mut tail = BufWriter::new(&mut out);
+    let mut offset: u64 = 0;
+    for (path, _, _) in &members {
+        tail.write_all(&offset.to_le_bytes())?;
+        offset += 20 + path.len() as u64;
+    }
+    for (path, start, end) in &members {
+        tail.write_all(&start.to_le_bytes())?;
+        tail.write_all(&end.to_le_bytes())?;
+        tail.write_all(&(path.len() as u32).to_le_bytes())?;
+        tail.write_all(path.as_bytes())?;
+    }
+    tail.flush()?;
+    drop(tail);
+
+    let mut header = Vec::with_capacity(HEADER_LEN as usize);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&size.to_le_bytes());
+    header.extend_from_slice(&secs.to_le_bytes());
+    header.extend_from_slice(&nanos.to_le_bytes());
+    header.extend_from_slice(&[kind as u8, 0, 0, 0]);
+    header.extend_from_slice(&count.to_le_bytes());
+    header.extend_from_slice(&end.to_le_bytes());
+    header.extend_from_slice(&(members.len() as u64).to_le_bytes());
+    header.resize(HEADER_LEN as usize, 0);
+    out.seek(SeekFrom::Start(0))?;
+    out.write_all(&header)?;
+    Ok((count, members.len() as u64))
+}
+
+// a container open while scanning
+struct Frame {
+    array: bool,
+    idx: u64,
+    // key of the current member, only decoded where paths are needed
+    key: Option<String>,
+    expect_key: bool,
+    // start of the value of the current member, when it is recorded
+    member: Option<u64>,
+}
+
+// A structural scan of a single document that only follows strings and nesting, passing the start
+// of every top-level array element to `element` and collecting (path, start, end) of the members at
+// `depths`. Returns the kind of document and where it ends.
+fn scan<E, R>(bytes: &[u8], depths: &[usize], element: &mut E, members: &mut Vec<(String, u64, u64)>, release: R) -> Result<(Kind, u64), JistError>
+where
+    E: FnMut(u64) -> Result<(), JistError>,
+    R: FnMut(usize),
+{
+    let max_depth = depths.iter().copied().max().unwrap_or(0);
+    let value_at = |pos: usize| pos + bytes[pos..].iter().take_while(|b| b.is_ascii_whitespace()).count();
+    let value_end = |pos: usize| pos - bytes[..pos].iter().rev().take_while(|b| b.is_ascii_whitespace()).count();
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut kind = Kind::Document;
+    let mut scan = Scan::new(bytes, value_at(0), 0, release);
+    while let Some(token) = scan.next() {
+        let (i, byte) = token?;
+        match byte {
+            b'"' => {
+                let depth = stack.len();
+                if let Some(frame) = stack.last_mut().filter(|frame| frame.expect_key) {
+                    frame.expect_key = false;
+                    if depth <= max_depth {
+                        frame.key = Some(json_str(&bytes[i..scan.pos]));
+                    }
+                }
+            }
+            b':' if depths.contains(&stack.len()) => {
+                if let Some(frame) = stack.last_mut() {
+                    frame.member = Some(value_at(i + 1) as u64);
+                }
+            }
+            b'[' | b'{' => {
+                let array = byte == b'[';
+                stack.push(Frame { array, idx: 0, key: None, expect_key: !array, member: None });
+                if array && stack.len() == 1 {
+                    kind = Kind::Array;
+                    let start = value_at(i + 1);
+                    if bytes.get(start) != Some(&b']') {
+                        element(start as u64)?;
+                    }
+                }
+            }
+            b',' | b']' | b'}' => {
+                let depth = stack.len();
+                let frame = stack.last_mut().ok_or_else(|| JistError::malformed(Some(i as u64), "unexpected character"))?;
+                if let Some(start) = frame.member.take() {
+                    let path = Value::Array(stack.iter().map(|frame| if frame.array { json!(frame.idx) } else { json!(frame.key) }).collect());
+                    members.push((path.to_string(), start, value_end(i) as u64));
+                }
+                let frame = stack.last_mut().unwrap();
+                if byte != b',' {
+                    stack.pop();
+                    if stack.is_empty() {
+                        return Ok((kind, i as u64));
+                    }
+                } else if frame.array {
+                    frame.idx += 1;
+                    let start = value_at(i + 1);
+                    if depth == 1 && bytes.get(start) != Some(&b']') {
+                        element(start as u64)?;
+                    }
+                } else {
+                    frame.expect_key = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    if stack.is_empty() {
+        return Ok((kind, bytes.len() as u64));
+    }
+    Err(JistError::malformed(Some(bytes.len() as u64), "unexpected end of input"))
+}
+
+impl Index {
+    // The index of `file`, None when there is none, it was built for an earlier version of the file
+    // or the sidecar isn't an index this version of jist can read, so searches scan the file instead
+    pub fn open(file: &str) -> Result<Option<Index>, JistError> {
+        let Ok(mut index) = File::open(sidecar(file)) else {
+            return Ok(None);
+        };
+        let mut header = [0; HEADER_LEN as usize];
+        if index.metadata()?.len() < HEADER_LEN {
+            debug!("{} is too short for an index", sidecar(file));
+            return Ok(None);
+        }
+        index.read_exact(&mut header)?;
+        let u64_at = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+        let nanos = u32::from_le_bytes(header[24..28].try_into().unwrap());
+        if &header[..4] != MAGIC || u32::from_le_bytes(header[4..8].try_into().unwrap()) != VERSION {
+            debug!("{} is not an index written by this version of jist", sidecar(file));
+            return Ok(None);
+        }
+        if (u64_at(8), u64_at(16), nanos) != stamp(file)? {
+            debug!("index of {} is out of date", file);
+            return Ok(None);
+        }
+        let kind = match header[28] {
+            0 => Kind::Array,
+            1 => Kind::Document,
+            _ => Kind::Records,
+        };
+        Ok(Some(Index { index, kind, count: u64_at(32), end: u64_at(40), members: u64_at(48) }))
+    }
+
+    // number of elements
+    pub fn len(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    // Start of element `n` and where the next one starts, or the end of the array. The element
+    // ends before the separator and whitespace in between.
+    pub fn element(&mut self, n: u64) -> Result<Option<(u64, u64)>, JistError> {
+        if n >= self.count {
+            return Ok(None);
+        }
+        self.index.seek(SeekFrom::Start(HEADER_LEN + 8 * n))?;
+        let mut offsets = [0; 16];
+        let len = if n + 1 < self.count { 16 } else { 8 };
+        self.index.read_exact(&mut offsets[..len])?;
+        let start = u64::from_le_bytes(offsets[..8].try_into().unwrap());
+        let next = if n + 1 < self.count { u64::from_le_bytes(offsets[8..].try_into().unwrap()) } else { self.end };
+        Ok(Some((start, next)))
+    }
+
+    // Start and end of the first member recorded at `path`, a JSON array of keys and indices
+    pub fn member(&mut self, path: &str) -> Result<Option<(u64, u64)>, JistError> {
+        // the first member whose path isn't before `path`
+        let (mut lo, mut hi) = (0, self.members);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.member_at(mid)?.0.as_slice() < path.as_bytes() {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        if lo == self.members {
+            return Ok(None);
+        }
+        let (member, start, end) = self.member_at(lo)?;
+        Ok((member == path.as_bytes()).then_some((start, end)))
+    }
+
+    // path, start and end of member `n`
+    fn member_at(&mut self, n: u64) -> Result<(Vec<u8>, u64, u64), JistError> {
+        let offsets = HEADER_LEN + 8 * self.count;
+        self.index.seek(SeekFrom::Start(offsets + 8 * n))?;
+        let mut offset = [0; 8];
+        self.index.read_exact(&mut offset)?;
+        self.index.seek(SeekFrom::Start(offsets + 8 * self.members + u64::from_le_bytes(offset)))?;
+        let mut entry = [0; 20];
+        self.index.read_exact(&mut entry)?;
+        let mut path = vec![0; u32::from_le_bytes(entry[16..].try_into().unwrap()) as usize];
+        self.index.read_exact(&mut path)?;
+        let start = u64::from_le_bytes(entry[..8].try_into().unwrap());
+        Ok((path, start, u64::from_le_bytes(entry[8..16].try_into().unwrap())))
+    }
+}
+
+// The search of `search_key` in `file` narrowed down by the index of `file`: a path into the
+// top-level array goes straight to its element, and one starting with an index like `[2]` into
+// NDJSON to that record, like `buf_parser::search_record`. Others go to the deepest recorded member
+// the path leads through, and the rest of the path is searched in that value alone. None when there
+// is no up to date index or it doesn't help with the path.
+pub fn search(file: &str, search_key: &str) -> Result<Option<String>, JistError> {
+    let Some(mut index) = Index::open(file)? else {
+        return Ok(None);
+    };
+    let Some(map) = Mapped::open(file)? else {
+        return Ok(None);
+    };
+    let query = JsonPath::parse(search_key).map_err(JistError::InvalidPath)?;
+    // the concrete paths of recursive descent matches would start inside the value
+    if query.with_paths() {
+        return Ok(None);
+    }
+
+    let head = match query.segments.first() {
+        Some(PathSegment::Index(i)) => Some(*i),
+        // records are objects, a numeric key is looked up in each of them
+        Some(PathSegment::Key(k)) if index.kind == Kind::Array => numeric_key(k),
+        _ => None,
+    };
+    if let Some(i) = head.filter(|_| index.kind != Kind::Document) {
+        let n = if i < 0 { i + index.len() as i64 } else { i };
+        let element = if n >= 0 { index.element(n as u64)? } else { None };
+        return match element {
+            Some((start, next)) => search_value(&map, start, next, &query, 1).map(Some),
+            None if query.is_definite() => Err(JistError::NotFound),
+            None => Ok(Some("[]".to_string())),
+        };
+    }
+
+    // the longest leading run of keys and indices with a recorded member
+    for len in (1..=query.segments.len()).rev() {
+        for path in member_paths(&query.segments[..len]) {
+            if let Some((start, end)) = index.member(&path)? {
+                return search_value(&map, start, end, &query, len).map(Some);
+            }
+        }
+    }
+    Ok(None)
+}
+
+// The recorded paths `segments` can stand for, a numeric key can be an object key or an index
+fn member_paths(segments: &[PathSegment]) -> Vec<String> {
+    let mut paths = vec![Vec::new()];
+    for segment in segments {
+        let options = match segment {
+            PathSegment::Key(k) => match numeric_key(k) {
+                Some(i) if i >= 0 => vec![json!(k), json!(i)],
+                _ => vec![json!(k)],
+            },
+            PathSegment::Name(k) => vec![json!(k)],
+            PathSegment::Index(i) if *i >= 0 => vec![json!(i)],
+            _ => return Vec::new(),
+        };
+        paths = paths
+            .into_iter()
+            .flat_map(|path: Vec<Value>| {
+                options.iter().map(move |option| {
+                    let mut path = path.clone();
+                    path.push(option.clone());
+                    path
+                })
+            })
+            .collect();
+    }
+    paths.into_iter().map(|path| Value::Array(path).to_string()).collect()
+}
+
+// `query` with its first `skip` segments resolved to the value starting at `start`, which ends
+// before the whitespace and separator preceding `bound`
+fn search_value(map: &Mapped, start: u64, bound: u64, query: &JsonPath, skip: usize) -> Result<String, JistError> {
+    let bytes = &map[start as usize..bound as usize];
+    let end = bytes.len() - bytes.iter().rev().take_while(|b| b.is_ascii_whitespace() || **b == b',').count();
+    buf_parser::search_value(&bytes[..end], start, query, skip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn member_paths_test() {
+        let segments = [PathSegment::Key("a".to_string()), PathSegment::Key("0".to_string()), PathSegment::Index(2)];
+        assert_eq!(member_paths(&segments), vec![r#"["a","0",2]"#, r#"["a",0,2]"#]);
+        assert_eq!(member_paths(&[PathSegment::Wildcard]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn scan_test() {
+        let sample = br#" [ {"a": 1, "b": {"c": [2, 3] } }, "x,]" ,[4] ] "#;
+        let (mut elements, mut members) = (Vec::new(), Vec::new());
+        let mut element = |pos| {
+            elements.push(pos);
+            Ok(())
+        };
+        assert_eq!(scan(sample, &[2, 3], &mut element, &mut members, |_| {}), Ok((Kind::Array, 46)));
+        assert_eq!(elements, vec![3, 35, 42]);
+        assert_eq!(
+            members,
+            vec![
+                (r#"[0,"a"]"#.to_string(), 9, 10),
+                (r#"[0,"b","c"]"#.to_string(), 23, 29),
+                (r#"[0,"b"]"#.to_string(), 17, 31),
+            ]
+        );
+        let (mut elements, mut members) = (Vec::new(), Vec::new());
+        let mut element = |pos| {
+            elements.push(pos);
+            Ok(())
+        };
+        assert_eq!(scan(br#"{"a": [1]}"#, &[1], &mut element, &mut members, |_| {}), Ok((Kind::Document, 9)));
+        assert_eq!((elements, members), (vec![], vec![(r#"["a"]"#.to_string(), 6, 9)]));
+        assert!(scan(b"[1, [2]", &[], &mut |_| Ok(()), &mut Vec::new(), |_| {}).is_err());
+        assert!(scan(br#"{"a": "b"#, &[1], &mut |_| Ok(()), &mut Vec::new(), |_| {}).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::bgzf::{is_bgzf, Bgzf};
+use crate::error::JistError;
+use crate::utils;
+use flate2::read::MultiGzDecoder;
+use memmap2::Mmap;
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::ops::Deref;
+
+// Compression of an input file, told apart by its first bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Plain,
+    Gzip,
+    // blocked gzip written by `bgzip`, seekable through its block index
+    Bgzf,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    pub fn detect(magic: &[u8]) -> Compression {
+        if is_bgzf(magic) {
+            Compression::Bgzf
+        } else if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else {
+            Compression::Plain
+        }
+    }
+
+    pub fn of_file(file: &str) -> Result<Compression, JistError> {
+        let mut magic = Vec::with_capacity(18);
+        File::open(file)?.take(18).read_to_end(&mut magic)?;
+        Ok(Compression::detect(&magic))
+    }
+}
+
+pub(crate) trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+// A plain file mapped into memory, so it can be lexed in place. Pages the lexer is done with are
+// dropped as it moves on, so resident memory stays about as low as with a read buffer.
+pub(crate) struct Mapped {
+    map: Mmap,
+    // the pages before this position were dropped
+    dropped: Cell<usize>,
+}
+
+impl Mapped {
+    // None for a compressed file
+    pub fn open(file: &str) -> Result<Option<Mapped>, JistError> {
+        if Compression::of_file(file)? != Compression::Plain {
+            return Ok(None);
+        }
+        // the file must not be truncated while it is searched, as with any other way of reading it
+        let map = unsafe { Mmap::map(&File::open(file)?)? };
+        #[cfg(unix)]
+        map.advise(memmap2::Advice::Sequential)?;
+        Ok(Some(Mapped { map, dropped: Cell::new(0) }))
+    }
+
+    // the lexer has moved on to `pos`, pages before it are read from the file again if a match needs them
+    pub fn release(&self, pos: usize) {
+        let dropped = self.dropped.get();
+        if pos <= dropped {
+            return;
+        }
+        // dropping pages of a read only file mapping doesn't change what they read
+        #[cfg(unix)]
+        let _ = unsafe { self.map.unchecked_advise_range(memmap2::UncheckedAdvice::DontNeed, dropped, pos - dropped) };
+        self.dropped.set(pos);
+    }
+}
+
+impl Deref for Mapped {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+// the uncompressed content of a file, read front to back
+pub(crate) fn reader(file: &str) -> Result<Box<dyn Read + Send>, JistError> {
+    let f = BufReader::new(File::open(file)?);
+    Ok(match Compression::of_file(file)? {
+        Compression::Plain => Box::new(f),
+        // a BGZF file is a series of gzip members
+        Compression::Gzip | Compression::Bgzf => Box::new(MultiGzDecoder::new(f)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(f.into_inner())?),
+        Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(f)),
+    })
+}
+
+// the uncompressed content of a file with random access, None when the compression only reads forward
+pub(crate) fn seeker(file: &str) -> Result<Option<Box<dyn ReadSeek>>, JistError> {
+    Ok(match Compression::of_file(file)? {
+        Compression::Plain => Some(Box::new(BufReader::new(File::open(file)?))),
+        Compression::Bgzf => Some(Box::new(Bgzf::open(file)?)),
+        _ => None,
+    })
+}
+
+// Up to `len` bytes of the uncompressed content of a file from `start`
+pub fn read_at(file: &str, start: u64, len: u64) -> Result<Vec<u8>, JistError> {
+    let mut bytes = Vec::new();
+    match seeker(file)? {
+        Some(mut seeker) => {
+            seeker.seek(SeekFrom::Start(start))?;
+            seeker.take(len).read_to_end(&mut bytes)?;
+        }
+        None => {
+            let mut reader = reader(file)?;
+            io::copy(&mut reader.by_ref().take(start), &mut io::sink())?;
+            reader.take(len).read_to_end(&mut bytes)?;
+        }
+    }
+    Ok(bytes)
+}
+
+// The start of the uncompressed content of a file that NDJSON is told apart by, see `utils::record_head`
+pub fn record_head(file: &str) -> Result<Vec<u8>, JistError> {
+    Ok(utils::record_head(BufReader::new(reader(file)?))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_compression() {
+        for (ext, compression) in [
+            ("", Compression::Plain),
+            (".gz", Compression::Gzip),
+            (".bgz", Compression::Bgzf),
+            (".zst", Compression::Zstd),
+            (".xz", Compression::Xz),
+        ] {
+            let file = format!("tests/fixtures/compressed/store.json{}", ext);
+            assert_eq!(Compression::of_file(&file), Ok(compression));
+            assert_eq!(read_at(&file, 4100, 8), Ok(read_at("tests/fixtures/compressed/store.json", 4100, 8).unwrap()));
+        }
+    }
+}
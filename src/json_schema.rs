@@ -0,0 +1,181 @@
+use crate::formats::FORMATS;
+use crate::stats::is_enum;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+// JSON Schema (draft 2020-12) of a document from its jist schema, as produced by
+// `schema_parser::parse` without unionizing. With `unionize` the element variants of a top-level
+// array are merged into a single schema for its items, a key is only required if every variant has it.
+pub fn document(schema: &Value, unionize: bool) -> Value {
+    let converted = match schema {
+        Value::Array(variants) if unionize => array(variants.iter().map(convert).reduce(union)),
+        _ => convert(schema),
+    };
+    with_draft(converted)
+}
+
+// JSON Schema of a single NDJSON record from the jist schemas of all records, merged like
+// `document` merges array elements
+pub fn records(schemas: &[Value]) -> Value {
+    with_draft(schemas.iter().map(convert).reduce(union).unwrap_or(json!({})))
+}
+
+fn with_draft(schema: Value) -> Value {
+    let mut document = Map::new();
+    document.insert("$schema".to_string(), json!(DRAFT));
+    if let Value::Object(schema) = schema {
+        document.extend(schema);
+    }
+    Value::Object(document)
+}
+
+// A jist schema node: a type name, an object of member schemas, or an array of the distinct
+// element schemas. Formats of strings become `format`, and {"enum": [...]} a string `enum`.
+fn convert(schema: &Value) -> Value {
+    match schema {
+        Value::Object(members) if is_enum(members) => json!({ "type": "string", "enum": members["enum"] }),
+        Value::Object(members) => object(members.iter().map(|(k, v)| (k.clone(), convert(v))).collect(), members.keys().cloned().collect()),
+        Value::Array(variants) => {
+            let mut variants: Vec<Value> = variants.iter().map(convert).collect();
+            array(match variants.len() {
+                0 => None,
+                1 => variants.pop(),
+                _ => Some(json!({ "anyOf": variants })),
+            })
+        }
+        Value::String(name) if name == "integer" => json!({ "type": "integer" }),
+        Value::String(name) if FORMATS.contains(&name.as_str()) => json!({ "type": "string", "format": name }),
+        Value::String(name) => json!({ "type": name }),
+        _ => json!({}),
+    }
+}
+
+fn object(properties: Map<String, Value>, required: Vec<String>) -> Value {
+    let mut schema = Map::new();
+    schema.insert("type".to_string(), json!("object"));
+    if !properties.is_empty() {
+        schema.insert("properties".to_string(), Value::Object(properties));
+    }
+    if !required.is_empty() {
+        schema.insert("required".to_string(), json!(required));
+    }
+    schema.insert("additionalProperties".to_string(), json!(false));
+    Value::Object(schema)
+}
+
+// an array whose elements match `items`, any elements when none were seen
+fn array(items: Option<Value>) -> Value {
+    match items {
+        Some(items) => json!({ "type": "array", "items": items }),
+        None => json!({ "type": "array" }),
+    }
+}
+
+// A schema matching whatever either of `a` and `b` matches. Objects are merged member by member
+// and arrays by their items, other types are kept side by side in `anyOf`.
+fn union(a: Value, b: Value) -> Value {
+    let mut variants = alternatives(a);
+    for variant in alternatives(b) {
+        match variants.iter().position(|v| v["type"] == variant["type"]) {
+            Some(i) if variants[i] == variant => {}
+            Some(i) if matches!(variant["type"].as_str(), Some("object" | "array")) => {
+                let merged = merge(variants.remove(i), variant);
+                variants.insert(i, merged);
+            }
+            _ => variants.push(variant),
+        }
+    }
+    if variants.len() == 1 {
+        return variants.remove(0);
+    }
+    json!({ "anyOf": variants })
+}
+
+fn alternatives(schema: Value) -> Vec<Value> {
+    match schema {
+        Value::Object(mut schema) if schema.contains_key("anyOf") => match schema.remove("anyOf") {
+            Some(Value::Array(variants)) => variants,
+            _ => Vec::new(),
+        },
+        schema => vec![schema],
+    }
+}
+
+// two object schemas or two array schemas merged
+fn merge(a: Value, b: Value) -> Value {
+    if a["type"] == "array" {
+        return array(match (a.get("items"), b.get("items")) {
+            (Some(x), Some(y)) => Some(union(x.clone(), y.clone())),
+            (x, y) => x.or(y).cloned(),
+        });
+    }
+    let members = |schema: &Value| schema.get("properties").and_then(Value::as_object).cloned().unwrap_or_default();
+    let required = |schema: &Value| schema.get("required").and_then(Value::as_array).cloned().unwrap_or_default();
+    let mut properties: BTreeMap<String, Value> = members(&a).into_iter().collect();
+    for (key, schema) in members(&b) {
+        let merged = match properties.remove(&key) {
+            Some(existing) => union(existing, schema),
+            None => schema,
+        };
+        properties.insert(key, merged);
+    }
+    let in_b = required(&b);
+    let keys = required(&a).into_iter().filter(|key| in_b.contains(key)).filter_map(|key| key.as_str().map(String::from)).collect();
+    object(Map::from_iter(properties), keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn document_test() {
+        let schema = json!({"a": "string", "e": ["number", {"f": "boolean"}], "g": []});
+        assert_eq!(
+            document(&schema, false),
+            json!({
+                "$schema": DRAFT,
+                "type": "object",
+                "properties": {
+                    "a": {"type": "string"},
+                    "e": {"type": "array", "items": {"anyOf": [
+                        {"type": "number"},
+                        {"type": "object", "properties": {"f": {"type": "boolean"}}, "required": ["f"], "additionalProperties": false}
+                    ]}},
+                    "g": {"type": "array"}
+                },
+                "required": ["a", "e", "g"],
+                "additionalProperties": false
+            })
+        );
+
+        let variants = json!([{"a": "string", "b": {"c": "number"}}, {"a": "number", "b": {"c": "number", "d": "string"}}, "string"]);
+        assert_eq!(
+            document(&variants, true),
+            json!({
+                "$schema": DRAFT,
+                "type": "array",
+                "items": {"anyOf": [
+                    {
+                        "type": "object",
+                        "properties": {
+                            "a": {"anyOf": [{"type": "string"}, {"type": "number"}]},
+                            "b": {"type": "object", "properties": {"c": {"type": "number"}, "d": {"type": "string"}}, "required": ["c"], "additionalProperties": false}
+                        },
+                        "required": ["a", "b"],
+                        "additionalProperties": false
+                    },
+                    {"type": "string"}
+                ]}
+            })
+        );
+        assert_eq!(records(&[json!({"a": ["number"]}), json!({"a": [], "b": "string"})])["required"], json!(["a"]));
+        assert_eq!(records(&[]), json!({"$schema": DRAFT}));
+        assert_eq!(
+            document(&json!({"at": "date-time", "n": "integer"}), false)["properties"],
+            json!({"at": {"type": "string", "format": "date-time"}, "n": {"type": "integer"}})
+        );
+    }
+}
@@ -1,16 +1,33 @@
 use std::io::Read;
 
 pub mod buf_parser;
+pub mod error;
+pub mod index;
+pub mod input;
+pub mod json_schema;
+mod bgzf;
 pub mod simd_parser;
 pub mod utils;
 pub mod schema_parser;
 mod model;
 pub mod schema_stream_parser;
+mod windows;
+mod parallel;
+mod structural;
+mod presence;
+mod stats;
+mod formats;
+pub mod schema_diff;
+pub mod validate;
+pub mod codegen;
+
+pub use error::JistError;
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    static PARSERS: &[fn(Option<&str>, Option<&str>, &str) -> Result<String, &'static str>] =
+    use std::io::Cursor;
+    static PARSERS: &[fn(Option<&str>, Option<&str>, &str) -> Result<String, JistError>] =
         &[simd_parser::search, buf_parser::search];
 
     #[test]
@@ -286,4 +303,546 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn wildcard_search() {
+        let sample = r#"[
+            {"bar": {"baz": "a", "poo": "true"}, "foo": 45},
+            {"bar": {"baz": "b", "poo": "false"}, "foo": 12},
+            {"bar": {"baz": {"x": [1, 2]}}, "foo": 99}
+        ]"#;
+        for search_fn in PARSERS {
+            assert_eq!(
+                search_fn(Some(sample), None, "[*].bar.baz"),
+                Ok(r#"["a","b",{"x":[1,2]}]"#.to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "[*].foo"),
+                Ok("[45,12,99]".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "[0].bar.*"),
+                Ok(r#"["a","true"]"#.to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "[*].*.poo"),
+                Ok(r#"["true","false"]"#.to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "[2].bar.baz.x[*]"),
+                Ok("[1,2]".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "[*].missing"),
+                Ok("[]".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(r#"{"a": [[1, 2], [3]], "b": [[4]]}"#), None, "*[*][0]"),
+                Ok("[1,3,4]".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn negative_and_slice_search() {
+        let sample = r#"[
+            {"bar": {"baz": "a"}, "foo": 45},
+            {"bar": {"baz": "b"}, "foo": 12},
+            {"bar": {"baz": "c"}, "foo": 99},
+            {"bar": {"baz": "d"}, "foo": 7}
+        ]"#;
+        for search_fn in PARSERS {
+            assert_eq!(
+                search_fn(Some(sample), None, "[-1].bar.baz"),
+                Ok("d".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "[-4].foo"),
+                Ok("45".to_string())
+            );
+            assert!(search_fn(Some(sample), None, "[-5].foo").is_err());
+            assert_eq!(
+                search_fn(Some(sample), None, "[-2]"),
+                Ok(r#"{"bar":{"baz":"c"},"foo":99}"#.to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "[1:3].foo"),
+                Ok("[12,99]".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "[::2].bar.baz"),
+                Ok(r#"["a","c"]"#.to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "[-2:].foo"),
+                Ok("[99,7]".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "[1:-1].foo"),
+                Ok("[12,99]".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "[::-1].foo"),
+                Ok("[7,99,12,45]".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "[-1:].bar.*"),
+                Ok(r#"["d"]"#.to_string())
+            );
+            assert_eq!(
+                search_fn(Some(r#"{"a": [[1, 2, 3], [4], []]}"#), None, "a[*][-1]"),
+                Ok("[3,4]".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(r#"{"a": [1, 2, 3]}"#), None, "a[5:]"),
+                Ok("[]".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn quoted_key_search() {
+        let sample = r#"{
+            "metadata": {
+                "labels": {"k8s.io/name": "web", "a[b]": [1, 2], "it's": true, "*": "star"}
+            },
+            "caf\u00e9": {"x y": "z"},
+            "quo\"te": 1
+        }"#;
+        for search_fn in PARSERS {
+            assert_eq!(
+                search_fn(Some(sample), None, "metadata.labels['k8s.io/name']"),
+                Ok("web".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, r#"metadata.labels["a[b]"][1]"#),
+                Ok("2".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, r#"metadata.labels['it\'s']"#),
+                Ok("true".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "metadata.labels['*']"),
+                Ok("star".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "café['x y']"),
+                Ok("z".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, r#"["quo\"te"]"#),
+                Ok("1".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn json_pointer_search() {
+        let sample = r#"{
+            "items": [{"name": "a"}, {"name": "b", "tags": ["x", "y"]}],
+            "responses": {"200": {"description": "OK"}},
+            "a/b": {"m~n": 1},
+            "k8s.io/name": "web"
+        }"#;
+        for search_fn in PARSERS {
+            assert_eq!(
+                search_fn(Some(sample), None, "/items/1/name"),
+                Ok("b".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "/items/1/tags/0"),
+                Ok("x".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "/responses/200/description"),
+                Ok("OK".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "/a~1b/m~0n"),
+                Ok("1".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "/k8s.io~1name"),
+                Ok("web".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "items.1.name"),
+                Ok("b".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn filter_search() {
+        let sample = r#"{
+            "records": [
+                {"id": 1, "foo": 12, "bar": {"poo": "false"}},
+                {"id": 2, "foo": 41, "bar": {"poo": "true"}},
+                {"id": 3, "foo": 99, "tags": ["x"]}
+            ],
+            "by_name": {"a": {"foo": 50}, "b": {"foo": 5}}
+        }"#;
+        for search_fn in PARSERS {
+            assert_eq!(
+                search_fn(Some(sample), None, "records[?(@.foo > 40)].id"),
+                Ok("[2,3]".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, r#"records[?(@.bar.poo == "true")].id"#),
+                Ok("[2]".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "records[?(@.foo < 20 || @.tags)].id"),
+                Ok("[1,3]".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "records[?(@.foo > 40 && !@.tags)]"),
+                Ok(r#"[{"id":2,"foo":41,"bar":{"poo":"true"}}]"#.to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "by_name[?@.foo >= 50].foo"),
+                Ok("[50]".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "records[?(@.foo > 1000)]"),
+                Ok("[]".to_string())
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, "records[?(@.foo >)]"),
+                Err(JistError::InvalidPath("invalid filter expression"))
+            );
+            // ids above 2^53 are told apart
+            assert_eq!(
+                search_fn(Some(r#"[{"id": 9007199254740992}, {"id": 9007199254740993}]"#), None, "[?(@.id == 9007199254740993)].id"),
+                Ok("[9007199254740993]".to_string())
+            );
+        }
+        assert_eq!(
+            buf_parser::search(Some(sample), None, "..[?(@.foo > 45)].foo"),
+            Ok(r#"{"records[2].foo":99,"by_name.a.foo":50}"#.to_string())
+        );
+    }
+
+    // cases in the format of the JSONPath compliance test suite, see tests/fixtures
+    #[test]
+    fn rfc9535_compliance() {
+        let suite: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/rfc9535_cts.json")).unwrap();
+        for case in suite["tests"].as_array().unwrap() {
+            let selector = case["selector"].as_str().unwrap();
+            let document = case["document"].to_string();
+            let unsupported = case["tags"].as_array().is_some_and(|tags| !tags.is_empty());
+            for search_fn in PARSERS {
+                let result = search_fn(Some(&document), None, selector);
+                if case["invalid_selector"] == true {
+                    assert!(result.is_err(), "{}: expected an error, got {:?}", case["name"], result);
+                } else if unsupported {
+                    assert!(
+                        matches!(result, Err(JistError::InvalidPath(reason)) if reason.starts_with("unsupported")),
+                        "{}: expected unsupported", case["name"]
+                    );
+                } else {
+                    let nodes: serde_json::Value = serde_json::from_str(&result.unwrap()).unwrap();
+                    match case.get("results") {
+                        Some(results) => assert!(
+                            results.as_array().unwrap().contains(&nodes),
+                            "{}: got {}", case["name"], nodes
+                        ),
+                        None => assert_eq!(nodes, case["result"], "{}", case["name"]),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn multi_search() {
+        let sample = r#"{
+            "auth": {"token": "abc", "expires_at": 1700000000},
+            "region": "eu-west-1",
+            "zones": [{"id": "a"}, {"id": "b"}]
+        }"#;
+        for search_fn in [simd_parser::search_many, buf_parser::search_many] {
+            assert_eq!(
+                search_fn(Some(sample), None, &["auth.token", "region", "auth.expires_at", "zones[*].id", "zones[-1]"]),
+                Ok(vec![
+                    serde_json::json!("abc"),
+                    serde_json::json!("eu-west-1"),
+                    serde_json::json!(1700000000),
+                    serde_json::json!(["a", "b"]),
+                    serde_json::json!({"id": "b"}),
+                ])
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, &["region", "missing"]),
+                Err(JistError::NotFound)
+            );
+            assert_eq!(
+                search_fn(Some(sample), None, &["region", "zones[?(@.id == 'c')]"]),
+                Ok(vec![serde_json::json!("eu-west-1"), serde_json::json!([])])
+            );
+        }
+        assert_eq!(
+            buf_parser::search_many(Some(sample), None, &["region", "..id"]),
+            Ok(vec![serde_json::json!("eu-west-1"), serde_json::json!({"zones[0].id": "a", "zones[1].id": "b"})])
+        );
+    }
+
+    #[test]
+    fn compressed_search() {
+        let plain = "tests/fixtures/compressed/store.json";
+        let keys = ["store.name", "store.books[-1].title", "store.books[?(@.price > 100)].id", "..since", "$.store.books[3:5].tags[0]"];
+        assert_eq!(buf_parser::search(None, Some(plain), "store.name"), Ok("Corner Books".to_string()));
+        assert!(buf_parser::search_many(None, Some(plain), &keys).is_ok());
+        for ext in [".gz", ".bgz", ".zst", ".xz"] {
+            let file = format!("{}{}", plain, ext);
+            for key in keys {
+                assert_eq!(buf_parser::search(None, Some(&file), key), buf_parser::search(None, Some(plain), key), "{} {}", file, key);
+            }
+            assert_eq!(buf_parser::search_many(None, Some(&file), &keys), buf_parser::search_many(None, Some(plain), &keys));
+            assert_eq!(schema_stream_parser::parse(None, Some(&file)), schema_stream_parser::parse(None, Some(plain)));
+            assert_eq!(schema_parser::summarize_file(&file, false), schema_parser::summarize_file(plain, false));
+        }
+        let events = "tests/fixtures/compressed/events.ndjson";
+        let schema = schema_parser::summarize(&std::fs::read_to_string(events).unwrap(), false);
+        assert!(schema.as_ref().is_ok_and(|schema| schema.contains("user")));
+        for ext in ["", ".gz", ".zst"] {
+            assert_eq!(schema_parser::summarize_file(&format!("{}{}", events, ext), false), schema);
+        }
+    }
+
+    #[test]
+    fn search_without_seeking() {
+        // matches spanning several chunks are kept in memory until they are complete
+        let items: Vec<String> = (0..40000).map(|i| format!(r#"{{"id": {}, "pad": "{}"}}"#, i, "x".repeat(40))).collect();
+        let sample = format!("{{\"items\": [\n{}\n], \"last\": {{\"n\": 1}}}}", items.join(",\n"));
+        let queries: Vec<model::json_path::JsonPath> = ["items[-1].id", "items[?(@.id == 20000)]", "last", "items"]
+            .iter()
+            .map(|key| model::json_path::JsonPath::parse(key).unwrap())
+            .collect();
+        let seeking = buf_parser::_search_many(Cursor::new(&sample), Some(Cursor::new(&sample)), &queries).unwrap();
+        let forward = buf_parser::_search_many(Cursor::new(&sample), None::<Cursor<&String>>, &queries).unwrap();
+        assert_eq!(forward, seeking);
+        assert_eq!(forward[0][0].2, "39999");
+        assert_eq!(forward[3][0].2.len(), sample.len() - r#"{"items": , "last": {"n": 1}}"#.len());
+
+        // minified input has no line breaks to cut chunks at
+        let minified = sample.replace('\n', "");
+        assert_eq!(
+            buf_parser::search_many_reader(minified.as_bytes(), &["items[-1].id", "last.n", "items[20000].id"]),
+            Ok(vec![serde_json::json!(39999), serde_json::json!(1), serde_json::json!(20000)])
+        );
+        assert_eq!(buf_parser::search_reader(minified.as_bytes(), "items[?(@.id == 7)].id"), Ok("[7]".to_string()));
+        assert_eq!(buf_parser::search_reader(&b""[..], "a"), Err(JistError::NotFound));
+    }
+
+    #[test]
+    fn ndjson_records() {
+        use serde_json::json;
+        let sample = "{\"a\": 1, \"b\": [{\"c\": 2}, {\"c\": 3}]}\n{\"b\": []}\n\n{\"a\": [], \"b\": [{\"c\": 4}]}\n{\"a\": \"x\"}\n";
+        let events = "tests/fixtures/compressed/events.ndjson";
+        for search_fn in [simd_parser::search_records, buf_parser::search_records] {
+            assert_eq!(search_fn(Some(sample), None, "a"), Ok(vec![json!(1), json!([]), json!("x")]));
+            assert_eq!(search_fn(Some(sample), None, "b[*].c"), Ok(vec![json!([2, 3]), json!([4])]));
+            assert_eq!(search_fn(Some(sample), None, "b[?(@.c > 2)].c"), Ok(vec![json!([3]), json!([4])]));
+            assert_eq!(search_fn(Some(sample), None, "b[*].z"), Ok(vec![]));
+            assert_eq!(search_fn(Some(sample), None, "z"), Err(JistError::NotFound));
+            assert!(matches!(
+                search_fn(Some("{\"a\": 1}\n{\"a\": [1, }\n"), None, "a"),
+                Err(JistError::MalformedJson { offset: Some(9..=19), .. })
+            ));
+            let levels = search_fn(None, Some(events), "level").unwrap();
+            assert_eq!(levels.len(), 30);
+            assert_eq!(levels[2], json!("error"));
+            assert_eq!(search_fn(None, Some(events), "user.name").map(|names| names[1].clone()), Ok(json!("u4")));
+        }
+        assert_eq!(
+            buf_parser::search_records(Some(sample), None, "..c"),
+            Ok(vec![json!({"b[0].c": 2, "b[1].c": 3}), json!({"b[0].c": 4})])
+        );
+        assert_eq!(simd_parser::search_records(Some(sample), None, "..c"), Err(JistError::UnsupportedPath));
+        assert_eq!(
+            buf_parser::search_records_many(Some(sample), None, &["a", "b[0].c"]),
+            Ok(vec![json!({"a": 1, "b[0].c": 2}), json!({"a": [], "b[0].c": 4}), json!({"a": "x"})])
+        );
+        assert_eq!(buf_parser::search_records_many(Some(sample), None, &["z", "b[5]"]), Err(JistError::NotFound));
+        // a leading index picks a single record
+        assert_eq!(buf_parser::search_record(Some(sample), None, "[2].b[0]"), Ok(Some(r#"{"c":4}"#.to_string())));
+        assert_eq!(buf_parser::search_record(Some(sample), None, "[-3].b"), Ok(Some("[]".to_string())));
+        assert_eq!(buf_parser::search_record(Some(sample), None, "[-1]"), Ok(Some(r#"{"a":"x"}"#.to_string())));
+        assert_eq!(buf_parser::search_record(Some(sample), None, "[*].a"), Ok(None));
+        assert_eq!(buf_parser::search_record(Some(sample), None, "[-5].a"), Err(JistError::NotFound));
+        assert_eq!(buf_parser::search_record(Some(sample), None, "[4][*]"), Ok(Some("[]".to_string())));
+        assert_eq!(buf_parser::search_record(None, Some(&format!("{}.gz", events)), "[4].user.name"), Ok(Some("u4".to_string())));
+        // text handed to simdjson a few lines at a time
+        for window in [1, 12, 40] {
+            assert_eq!(simd_parser::search_record_windows(Some(sample), None, "b[*].c", window), Ok(vec![json!([2, 3]), json!([4])]));
+            assert!(matches!(
+                simd_parser::search_record_windows(Some("{\"a\": 1}\n{\"a\": [1, }\n"), None, "a", window),
+                Err(JistError::MalformedJson { offset: Some(9..=19), .. })
+            ));
+        }
+        assert_eq!(
+            schema_parser::summarize("{\"a\": 1, \"b\": [{\"c\": 2}]}\n\n{\"b\": [], \"d\": true}\n", false),
+            Ok(r#"[{"a?":"number","b":[{"c":"number"}],"d?":"boolean"}]"#.to_string())
+        );
+    }
+
+    #[test]
+    fn windowed_search() {
+        let file = "tests/fixtures/windows.json";
+        let windowed = |key: &str, size| simd_parser::search_windows(file, &model::json_path::JsonPath::parse(key).unwrap(), size);
+        for key in ["[0].id", "[299].name", "[-1].id", "[150].tags[1].k", "42.nested", "$[3].name", "[*].id", "[*].tags[0]", "[10:250:7].id", "[-5:].nested.v[0]", "[::40]"] {
+            assert_eq!(windowed(key, 1000), simd_parser::search(None, Some(file), key), "{}", key);
+            assert_eq!(windowed(key, 1000), buf_parser::search(None, Some(file), key), "{}", key);
+        }
+        assert_eq!(windowed("[*].id", 1_000_000), windowed("[*].id", 200));
+        assert_eq!(windowed("[300]", 1000), Err(JistError::NotFound));
+        assert_eq!(windowed("[?(@.id == 3)]", 1000), Err(JistError::UnsupportedPath));
+        assert_eq!(windowed("[*].id", 10), Err(JistError::CapacityExceeded));
+        assert_eq!(simd_parser::search_windows("tests/fixtures/compressed/store.json", &model::json_path::JsonPath::parse("[0]").unwrap(), 1000), Err(JistError::CapacityExceeded));
+    }
+
+    #[test]
+    fn parallel_search() {
+        let windows = std::fs::read_to_string("tests/fixtures/windows.json").unwrap();
+        let split = |haystack: &str, key, size| {
+            buf_parser::search_split(haystack.as_bytes(), &model::json_path::JsonPath::parse(key).unwrap(), 3, size).transpose()
+        };
+        for key in ["[0].id", "[299].name", "[-1].id", "[150].tags[1].k", "42.nested", "/7/id", "$[3].name", "[*].id", "[10:250:7].id", "[-5:].nested.v[0]", "[?(@.id > 295)].id", "[300]"] {
+            for size in [50, 333, 4096, 1 << 20] {
+                assert_eq!(split(&windows, key, size), Some(buf_parser::search(Some(&windows), None, key)), "{} {}", key, size);
+            }
+        }
+
+        let nested = r#"{"meta": {"n": [1, 2]}, "store": {"la\"bel": "[{,:\\", "book": [{"t": "a,]", "p": 8}, 3, {"t": "c\"{", "p": 12}, "x"]}}"#;
+        for key in ["store.book[0].t", "store.book[*]", "store.book[?(@.p > 10)].t", "$['store'].book[1:]", "store.book[-1]"] {
+            for size in 1..nested.len() {
+                assert_eq!(split(nested, key, size), Some(buf_parser::search(Some(nested), None, key)), "{} {}", key, size);
+            }
+        }
+        // not split, left to a single pass
+        for key in ["meta.n", "meta.n.x[0]", "..t", "store.book[0,1]", "missing[0]"] {
+            assert_eq!(split(nested, key, 8), None, "{}", key);
+        }
+        assert_eq!(buf_parser::search_parallel(Some(nested), None, "store.book[2].p", 4), Ok("12".to_string()));
+        assert_eq!(buf_parser::search_parallel(Some(nested), None, "meta.n[1]", 4), Ok("2".to_string()));
+    }
+
+    #[test]
+    fn indexed_search() {
+        let dir = std::env::temp_dir().join(format!("jist-index-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let copy = |name: &str, text: &str| {
+            let file = dir.join(name).to_string_lossy().into_owned();
+            std::fs::write(&file, text).unwrap();
+            file
+        };
+        let windows = copy("windows.json", &std::fs::read_to_string("tests/fixtures/windows.json").unwrap());
+        assert_eq!(index::search(&windows, "[3]"), Ok(None));
+        assert_eq!(index::build(&windows, &[2]).map(|(elements, _)| elements), Ok(300));
+        for key in ["[0].id", "[299]", "[-1].id", "[150].tags[1].k", "42.nested", "/7/id", "$[3].name", "[300]", "[-301].id", "$[400]"] {
+            assert_eq!(index::search(&windows, key).map(Option::unwrap), buf_parser::search(None, Some(&windows), key), "{}", key);
+        }
+        assert_eq!(index::search(&windows, "[*].id"), Ok(None));
+
+        let nested = copy("nested.json", r#"{"meta": {"n": [1, 2], "a.b": {"c": "d"}}, "items": [{"t": "x"}, 3]}"#);
+        index::build(&nested, &[1, 2]).unwrap();
+        for key in ["meta.n[1]", "meta['a.b'].c", "/meta/a.b", "$.items[*].t", "meta.n"] {
+            assert_eq!(index::search(&nested, key).map(Option::unwrap), buf_parser::search(None, Some(&nested), key), "{}", key);
+        }
+        let mut index = index::Index::open(&nested).unwrap().unwrap();
+        assert_eq!(index.member(r#"["meta","a.b"]"#), Ok(Some((30, 40))));
+        assert_eq!(index.member(r#"["meta","x"]"#), Ok(None));
+        assert_eq!(index::search(&nested, "missing[0]"), Ok(None));
+
+        // a changed file is searched without its index
+        std::fs::write(&nested, r#"{"meta": {"n": [5, 6]}}"#).unwrap();
+        assert!(index::Index::open(&nested).unwrap().is_none());
+        let records = copy("records.ndjson", "{\"a\": 1}\n\n{\"a\": 2}\n");
+        assert_eq!(index::build(&records, &[]), Ok((2, 0)));
+        let mut index = index::Index::open(&records).unwrap().unwrap();
+        assert_eq!((index.kind, index.element(1)), (index::Kind::Records, Ok(Some((10, 19)))));
+        // a leading index picks a record, read from the index or found line by line
+        for key in ["[1].a", "[-2]", "[0]['a', 'b']", "[2].a", "[-3].a", "[5][*]"] {
+            let found = buf_parser::search_record(None, Some(&records), key);
+            assert_eq!(index::search(&records, key), found, "{}", key);
+            assert!(found.is_err() || found.as_ref().unwrap().is_some(), "{}", key);
+        }
+        assert_eq!(index::search(&records, "[1].a"), Ok(Some("2".to_string())));
+        assert_eq!(index::search(&records, "[-2]"), Ok(Some(r#"{"a":1}"#.to_string())));
+        assert_eq!(index::search(&records, "[2].a"), Err(JistError::NotFound));
+        assert_eq!(index::search(&records, "a"), Ok(None));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn broken_index() {
+        let dir = std::env::temp_dir().join(format!("jist-broken-index-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("doc.json").to_string_lossy().into_owned();
+        std::fs::write(&file, r#"[{"id": 1}, {"id": 2}]"#).unwrap();
+        index::build(&file, &[]).unwrap();
+        let built = std::fs::read(index::sidecar(&file)).unwrap();
+        // garbage, a truncated header and an index of another version are ignored
+        let mut other_version = built.clone();
+        other_version[4] += 1;
+        for sidecar in [b"not an index at all, just some text that is long enough to fill a header".to_vec(), built[..20].to_vec(), other_version] {
+            std::fs::write(index::sidecar(&file), sidecar).unwrap();
+            assert!(index::Index::open(&file).unwrap().is_none());
+            assert_eq!(index::search(&file, "[1].id"), Ok(None));
+            assert_eq!(buf_parser::search(None, Some(&file), "[1].id"), Ok("2".to_string()));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn schema_stats() {
+        let options = schema_parser::SchemaOptions { stats: true, ..Default::default() };
+        let store = "tests/fixtures/compressed/store.json";
+        let stats = schema_parser::summarize_file_as(store, &options).unwrap();
+        assert_eq!(schema_stream_parser::parse_as(None, Some(store), &options), Ok(stats.clone()));
+        assert_eq!(schema_parser::summarize_file_as(&format!("{}.zst", store), &options), Ok(stats.clone()));
+        let stats: serde_json::Value = serde_json::from_str(&stats).unwrap();
+        assert_eq!(stats["schema"], serde_json::from_str::<serde_json::Value>(&schema_parser::summarize_file(store, false).unwrap()).unwrap());
+        assert_eq!(stats["stats"]["records"], 1);
+
+        let events = "tests/fixtures/compressed/events.ndjson";
+        let stats: serde_json::Value = serde_json::from_str(&schema_parser::summarize_file_as(events, &options).unwrap()).unwrap();
+        assert_eq!(stats["stats"]["records"], 30);
+        assert_eq!(stats["stats"]["paths"]["level"]["count"], 30);
+        assert_eq!(stats["schema"].to_string(), schema_parser::summarize_file(events, false).unwrap());
+    }
+
+    #[test]
+    fn error_kinds() {
+        for search_fn in PARSERS {
+            assert_eq!(search_fn(Some(r#"{"a": 1}"#), None, "b"), Err(JistError::NotFound));
+            assert_eq!(search_fn(Some(r#"{"a": 1}"#), None, "a["), Err(JistError::InvalidPath("invalid search key")));
+            assert!(matches!(search_fn(None, Some("no/such/file.json"), "a"), Err(JistError::Io(_))));
+            assert!(matches!(
+                search_fn(Some(r#"{"a": [1, }"#), None, "a"),
+                Err(JistError::MalformedJson { .. })
+            ));
+        }
+        assert_eq!(
+            buf_parser::search(Some(r#"{"b": 2, "a": [1, }"#), None, "a"),
+            Err(JistError::MalformedJson { offset: Some(18), message: "expected closing bracket".to_string() })
+        );
+        // structurally broken input is malformed rather than missing the key
+        for (input, offset) in [(r#"{"a": tru}"#, 6), (r#"{"a":[1,2}"#, 9), (r#"{"b": 1, "a": [1"#, 16), (r#"{"a": 1}}"#, 8)] {
+            let malformed = |result| matches!(result, Err(JistError::MalformedJson { offset: Some(at), .. }) if at == offset);
+            assert!(malformed(buf_parser::search(Some(input), None, "c")), "{}", input);
+            assert!(malformed(buf_parser::search_reader(input.as_bytes(), "$..c")), "{}", input);
+        }
+        assert!(matches!(
+            schema_stream_parser::parse(Some(r#"{"a": 1]"#), None),
+            Err(JistError::MalformedJson { offset: Some(7), .. })
+        ));
+        // the in-memory parser hands recursive descent over to the streaming one
+        assert_eq!(simd_parser::search(Some(r#"{"a": {"b": 1}}"#), None, "..b"), Err(JistError::UnsupportedPath));
+        assert_eq!(buf_parser::search(Some(r#"{"a": {"b": 1}}"#), None, "..b"), Ok(r#"{"a.b":1}"#.to_string()));
+        assert_eq!(JistError::NotFound.to_string(), "result not found");
+    }
 }
@@ -1,136 +1,611 @@
-use clap::Parser;
-use jist::{buf_parser, schema_parser, schema_stream_parser, simd_parser, utils};
+use clap::{Parser, Subcommand, ValueEnum};
+use jist::input::{self, Compression};
+use jist::codegen::Language;
+use jist::schema_parser::{SchemaFormat, SchemaOptions};
+use jist::{buf_parser, index, schema_diff, schema_parser, schema_stream_parser, simd_parser, utils, validate, JistError};
 use log::debug;
-use std::fs::File;
+use miette::{Diagnostic, LabeledSpan, NamedSource, Report, SourceCode};
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::fmt;
+use std::process::ExitCode;
 use std::{fs, io};
-use std::io::{BufReader, Cursor, Read};
+use std::io::{BufRead, Read};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(short, long)]
     data: Option<String>,
 
     #[arg(short, long)]
     file: Option<String>,
 
+    /// Search key, repeat it to look up several keys in a single pass over the input
     #[arg(short, long)]
-    path: Option<String>,
+    path: Vec<String>,
+
+    /// File with one search key per line, looked up along with any `--path` keys
+    #[arg(long)]
+    paths_file: Option<String>,
 
     #[arg(short, long)]
     streaming: bool,
 
     #[arg(short, long)]
     unionize: bool,
+
+    /// Format of the schema printed when there's no search key
+    #[arg(long, value_enum, default_value_t = Format::Jist)]
+    schema_format: Format,
+
+    /// Print the schema as {"schema": ..., "fields": ...}, with whether each key is required or
+    /// nullable and the share of objects that have it
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Print the schema as {"schema": ..., "stats": ...}, with the number of records and for every
+    /// key path value counts, numeric and length ranges, distinct values and the most common values
+    #[arg(long)]
+    stats: bool,
+
+    /// Tell integers from other numbers and name the format of strings in the schema: date-time,
+    /// date, uuid, email, ipv4, ipv6 or uri
+    #[arg(long)]
+    formats: bool,
+
+    /// Share of the sampled strings at a key path that must have a format for `--formats` to name it
+    #[arg(long, default_value_t = 0.9)]
+    format_threshold: f64,
+
+    /// Name the values of strings in the schema, as {"enum": [...]} in place of "string", at key
+    /// paths with at most `--enum-cap` distinct strings
+    #[arg(long)]
+    enums: bool,
+
+    /// Most distinct strings at a key path for `--enums` to list them
+    #[arg(long, default_value_t = 20)]
+    enum_cap: usize,
+
+    /// Print type definitions of the schema instead, with the elements of a top-level array merged
+    /// as with `--unionize`
+    #[arg(long, value_enum, conflicts_with_all = ["schema_format", "verbose", "stats"])]
+    emit: Option<Emit>,
+
+    /// Print each match of a wildcard path on its own line instead of a JSON array, or with several
+    /// search keys one tab separated `key<TAB>value` line per key instead of a JSON object
+    #[arg(short, long)]
+    lines: bool,
+
+    /// Split the search over this many threads when the input is streamed (`--streaming`, or files
+    /// simdjson can't take)
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    /// jist's shorthand, e.g. {"a":"string","e":["number"]}
+    Jist,
+    /// JSON Schema draft 2020-12, describing a single record of NDJSON input
+    JsonSchema,
+}
+
+impl From<Format> for SchemaFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Jist => SchemaFormat::Jist,
+            Format::JsonSchema => SchemaFormat::JsonSchema,
+        }
+    }
 }
 
-fn main() {
-    let args = Args::parse();
-    if args.file.is_some() {
-        if args.path.is_none() {
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Emit {
+    /// TypeScript interfaces and type aliases
+    Ts,
+    /// Rust structs and enums deriving serde's Serialize and Deserialize
+    Rust,
+    /// Go structs with json tags
+    Go,
+}
+
+impl From<Emit> for Language {
+    fn from(emit: Emit) -> Self {
+        match emit {
+            Emit::Ts => Language::TypeScript,
+            Emit::Rust => Language::Rust,
+            Emit::Go => Language::Go,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Sidecar index `FILE.jidx`, used by searches of FILE for as long as FILE doesn't change
+    Index {
+        #[command(subcommand)]
+        action: IndexAction,
+    },
+    /// Paths added, removed or changed in type from schema A to schema B. A and B are JSON or
+    /// NDJSON files, or schemas jist printed earlier
+    SchemaDiff {
+        a: String,
+        b: String,
+
+        /// Print the changes as {"breaking": ..., "added": [...], "removed": [...], "changed": [...]}
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check every record of FILE (NDJSON line, element of the top-level array or the document)
+    /// against a schema in one streaming pass, printing each violation
+    Validate {
+        /// A schema jist printed, e.g. with `-u`, or a JSON Schema
+        #[arg(long)]
+        schema: String,
+
+        file: String,
+
+        /// Print each violation as {"record": ..., "path": ..., "offset": ..., "message": ...}
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum IndexAction {
+    /// Record where each element of the top-level array (or each NDJSON line) of FILE starts
+    Build {
+        file: String,
+
+        /// Also record the members of objects at this depth (1 for the top-level object), repeat
+        /// it for several depths
+        #[arg(long)]
+        key_depth: Vec<usize>,
+    },
+}
+
+// exit codes, so scripts can tell failures apart
+const EXIT_NOT_FOUND: u8 = 1;
+const EXIT_MALFORMED_JSON: u8 = 2;
+const EXIT_IO: u8 = 3;
+const EXIT_BAD_ARGUMENTS: u8 = 4;
+const EXIT_BREAKING_CHANGES: u8 = 5;
+const EXIT_INVALID: u8 = 6;
+// an error searches fall back on, which should never get this far
+const EXIT_INTERNAL: u8 = 7;
+
+// input bytes shown on either side of malformed JSON
+const SNIPPET_RADIUS: u64 = 120;
+
+fn main() -> ExitCode {
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        // --help and --version
+        Err(error) if !error.use_stderr() => error.exit(),
+        Err(error) => {
+            let _ = error.print();
+            return ExitCode::from(EXIT_BAD_ARGUMENTS);
+        }
+    };
+    match run(args) {
+        Ok(code) => ExitCode::from(code),
+        Err(error) => {
+            let code = error.exit_code();
+            eprintln!("{:?}", Report::new(error));
+            ExitCode::from(code)
+        }
+    }
+}
+
+// the exit code, 0 unless schema changes were breaking or data didn't match its schema
+fn run(mut args: Args) -> Result<u8, CliError> {
+    if let Some(Command::Index { action: IndexAction::Build { file, key_depth } }) = &args.command {
+        let (elements, members) = index::build(file, key_depth).map_err(|error| CliError::new(error, file, None))?;
+        println!("{}", serde_json::json!({"index": index::sidecar(file), "elements": elements, "members": members}));
+        return Ok(0);
+    }
+    if let Some(Command::SchemaDiff { a, b, json }) = &args.command {
+        let load = |file: &String| schema_diff::load(file).map_err(|error| CliError::new(error, file, None));
+        let diff = schema_diff::diff(&load(a)?, &load(b)?);
+        if *json {
+            println!("{}", diff.report());
+        } else {
+            print!("{}", diff);
+        }
+        return Ok(if diff.breaking() { EXIT_BREAKING_CHANGES } else { 0 });
+    }
+    if let Some(Command::Validate { schema, file, json }) = &args.command {
+        let text = read_file(schema)?;
+        let parsed = serde_json::from_str(&text)
+            .map_err(|e| JistError::MalformedJson { offset: None, message: e.to_string() })
+            .and_then(|schema| validate::Schema::parse(&schema));
+        let schema = parsed.map_err(|error| CliError::new(error, schema, None))?;
+        let mut valid = true;
+        validate::validate(&schema, None, Some(file), |violation| {
+            valid = false;
+            match json {
+                true => println!("{}", violation.report()),
+                false => println!("{}", violation),
+            }
+        })
+        .map_err(|error| CliError::new(error, file, None))?;
+        return Ok(if valid { 0 } else { EXIT_INVALID });
+    }
+    if let Some(paths_file) = &args.paths_file {
+        let keys = read_file(paths_file)?;
+        args.path.extend(keys.lines().map(str::trim).filter(|key| !key.is_empty()).map(String::from));
+    }
+    let options = SchemaOptions {
+        unionize: args.unionize,
+        format: args.schema_format.into(),
+        verbose: args.verbose,
+        stats: args.stats,
+        formats: args.formats.then_some(args.format_threshold),
+        enums: args.enums.then_some(args.enum_cap),
+        emit: args.emit.map(Language::from),
+    };
+    if let Some(file) = args.file {
+        let in_file = |error| CliError::new(error, &file, None);
+        if args.path.is_empty() {
             if args.streaming {
-                match schema_stream_parser::parse(None, Some(args.file.unwrap().as_str())) {
-                    Ok(result) => println!("{}", result),
-                    Err(error) => panic!("{}", error),
-                }
+                println!("{}", schema_stream_parser::parse_as(None, Some(file.as_str()), &options).map_err(in_file)?);
             } else {
-                match schema_parser::summarize(fs::read_to_string(args.file.unwrap()).unwrap().as_str(), args.unionize) {
-                    Ok(result) => println!("{}", result),
-                    Err(error) => panic!("{}", error),
-                }
+                println!("{}", schema_parser::summarize_file_as(&file, &options).map_err(in_file)?);
             }
-
         } else {
-            assert!(args.path.is_some());
-            match search(
-                None,
-                Some(args.file.unwrap().as_str()),
-                args.path.unwrap().as_str(),
-                args.streaming,
-            ) {
-                Ok(result) => println!("{}", result),
-                Err(error) => panic!("{}", error),
-            }
+            run_search(None, Some(file.as_str()), &args.path, args.streaming, args.threads, args.lines).map_err(in_file)?;
+        }
+    } else if args.data.is_none() && !args.path.is_empty() {
+        // stdin is searched as it streams past instead of being read into memory first
+        let mut stdin = io::stdin().lock();
+        let head = utils::record_head(&mut stdin).map_err(JistError::from)?;
+        if head.is_empty() {
+            return Err(JistError::InvalidInput("No data provided").into());
         }
+        let records = utils::is_record_stream(&head);
+        run_search_reader(io::Cursor::new(head).chain(stdin), &args.path, records, args.lines)?;
     } else {
-        let haystack = if let Some(text) = args.data {
-            text
+        let (haystack, name) = if let Some(text) = args.data {
+            (text, "<data>")
         } else {
             let mut buffer = String::new();
-            io::stdin()
-                .read_to_string(&mut buffer)
-                .expect("data not provided");
-            buffer
+            io::stdin().read_to_string(&mut buffer).map_err(JistError::from)?;
+            (buffer, "<stdin>")
         };
+        let in_data = |error| CliError::new(error, name, Some(&haystack));
         if !haystack.is_empty() {
-            if args.path.is_none() {
+            if args.path.is_empty() {
                 if args.streaming {
-                    match schema_stream_parser::parse(Some(&haystack), None) {
-                        Ok(result) => println!("{}", result),
-                        Err(error) => panic!("{}", error),
-                    }
+                    println!("{}", schema_stream_parser::parse_as(Some(&haystack), None, &options).map_err(in_data)?);
                 } else {
-                    match schema_parser::summarize(&haystack, args.unionize) {
-                        Ok(result) => println!("{}", result),
-                        Err(error) => panic!("{}", error),
-                    }
+                    println!("{}", schema_parser::summarize_as(&haystack, &options).map_err(in_data)?);
                 }
             } else {
-                assert!(args.path.is_some());
-                match search(
-                    Some(haystack.as_str()),
-                    None,
-                    args.path.unwrap().as_str(),
-                    args.streaming,
-                ) {
-                    Ok(result) => println!("{}", result),
-                    Err(error) => panic!("{}", error),
-                }
+                run_search(Some(haystack.as_str()), None, &args.path, args.streaming, args.threads, args.lines).map_err(in_data)?;
             }
         } else {
-            panic!("No data provided");
+            return Err(JistError::InvalidInput("No data provided").into());
         }
     }
+    Ok(0)
 }
 
-pub fn search(
+fn read_file(path: &str) -> Result<String, CliError> {
+    fs::read_to_string(path).map_err(|error| CliError::new(error.into(), path, None))
+}
+
+// An error reported on stderr. Malformed JSON is shown along with the input around it.
+#[derive(Debug)]
+struct CliError {
+    error: JistError,
+    file: Option<String>,
+    // the input around the error and the position of the error in it
+    snippet: Option<Box<(NamedSource<String>, usize)>>,
+}
+
+impl CliError {
+    // `haystack` is the input text, or None when the input is the file `name`
+    fn new(error: JistError, name: &str, haystack: Option<&str>) -> CliError {
+        let snippet = match error {
+            JistError::MalformedJson { offset: Some(offset), .. } => snippet(name, haystack, offset).map(Box::new),
+            _ => None,
+        };
+        let file = haystack.is_none().then(|| name.to_string());
+        CliError { error, file, snippet }
+    }
+
+    fn exit_code(&self) -> u8 {
+        match self.error {
+            JistError::NotFound => EXIT_NOT_FOUND,
+            JistError::MalformedJson { .. } => EXIT_MALFORMED_JSON,
+            JistError::Io(_) => EXIT_IO,
+            JistError::CapacityExceeded | JistError::UnsupportedPath => EXIT_INTERNAL,
+            JistError::InvalidPath(_) | JistError::InvalidInput(_) => EXIT_BAD_ARGUMENTS,
+        }
+    }
+}
+
+impl From<JistError> for CliError {
+    fn from(error: JistError) -> Self {
+        CliError { error, file: None, snippet: None }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl Error for CliError {}
+
+impl Diagnostic for CliError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        let code = match self.error {
+            JistError::NotFound => "jist::not_found",
+            JistError::MalformedJson { .. } => "jist::malformed_json",
+            JistError::Io(_) => "jist::io",
+            JistError::InvalidPath(_) => "jist::invalid_path",
+            JistError::InvalidInput(_) => "jist::invalid_input",
+            JistError::CapacityExceeded => "jist::capacity_exceeded",
+            JistError::UnsupportedPath => "jist::unsupported_path",
+        };
+        Some(Box::new(code))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        match (&self.error, &self.file) {
+            (JistError::NotFound, _) => Some(Box::new("run without --path to print the schema of the input")),
+            (JistError::InvalidPath(_), _) => Some(Box::new("search keys look like `a.b[0]`, `/a/b/0` or `$.a[?@.b > 1]`")),
+            (JistError::Io(_), Some(file)) => Some(Box::new(format!("check that `{}` exists and is readable", file))),
+            _ => None,
+        }
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        self.snippet.as_ref().map(|snippet| &snippet.0 as &dyn SourceCode)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let at = self.snippet.as_ref()?.1;
+        Some(Box::new(std::iter::once(LabeledSpan::at_offset(at, "here"))))
+    }
+}
+
+// The input around `offset`, read from the file `name` unless the input text is given
+fn snippet(name: &str, haystack: Option<&str>, offset: u64) -> Option<(NamedSource<String>, usize)> {
+    let start = offset.saturating_sub(SNIPPET_RADIUS);
+    let mut bytes = Vec::new();
+    match haystack {
+        Some(haystack) => bytes.extend(haystack.as_bytes().iter().skip(start as usize).take(2 * SNIPPET_RADIUS as usize)),
+        None => bytes = input::read_at(name, start, 2 * SNIPPET_RADIUS).ok()?,
+    }
+    // don't start in the middle of a multibyte character
+    let skip = bytes.iter().take_while(|b| **b & 0xC0 == 0x80).count();
+    let at = ((offset - start) as usize).saturating_sub(skip);
+    Some((NamedSource::new(name, String::from_utf8_lossy(&bytes[skip..]).into_owned()), at))
+}
+
+fn run_search(
     haystack: Option<&str>,
     file: Option<&str>,
-    search_key: &str,
+    search_keys: &[String],
     streaming: bool,
-) -> Result<String, &'static str> {
-    if (haystack.is_none() && file.is_none()) || search_key.is_empty() {
-        return Err("Invalid input - no object found");
+    threads: usize,
+    lines: bool,
+) -> Result<(), JistError> {
+    let keys: Vec<&str> = search_keys.iter().map(|key| key.as_str()).collect();
+    if is_record_stream(haystack, file)? {
+        if let [search_key] = search_keys {
+            if let Some(result) = search_record(haystack, file, search_key)? {
+                print_search_result(&result, search_key, lines);
+                return Ok(());
+            }
+        }
+        let records = match search_keys {
+            [search_key] => search_records(haystack, file, search_key, streaming)?,
+            _ => buf_parser::search_records_many(haystack, file, &keys)?,
+        };
+        print_records(records, lines);
+        return Ok(());
+    }
+    if let [search_key] = search_keys {
+        let result = search(haystack, file, search_key, streaming, threads)?;
+        print_search_result(&result, search_key, lines);
+        return Ok(());
+    }
+    let values = search_many(haystack, file, &keys, streaming)?;
+    print_search_results(&keys, values, lines);
+    Ok(())
+}
+
+fn run_search_reader<R: BufRead>(mut reader: R, search_keys: &[String], records: bool, lines: bool) -> Result<(), JistError> {
+    let keys: Vec<&str> = search_keys.iter().map(|key| key.as_str()).collect();
+    if records {
+        if let [search_key] = search_keys {
+            if let Some(result) = buf_parser::search_record_reader(&mut reader, search_key)? {
+                print_search_result(&result, search_key, lines);
+                return Ok(());
+            }
+        }
+        let records = match search_keys {
+            [search_key] => buf_parser::search_records_reader(reader, search_key)?,
+            _ => buf_parser::search_records_many_reader(reader, &keys)?,
+        };
+        print_records(records, lines);
+        return Ok(());
+    }
+    if let [search_key] = search_keys {
+        let result = buf_parser::search_reader(reader, search_key)?;
+        print_search_result(&result, search_key, lines);
+        return Ok(());
+    }
+    let values = buf_parser::search_many_reader(reader, &keys)?;
+    print_search_results(&keys, values, lines);
+    Ok(())
+}
+
+fn print_search_result(result: &str, search_key: &str, lines: bool) {
+    if lines && !utils::is_definite(search_key) {
+        if let Ok(Value::Array(values)) = serde_json::from_str::<Value>(result) {
+            for value in values {
+                match value {
+                    Value::String(s) => println!("{}", s),
+                    _ => println!("{}", value),
+                }
+            }
+            return;
+        }
+    }
+    println!("{}", result);
+}
+
+// the value found in each NDJSON record, or the object of the values of several search keys, as a
+// JSON array or one line per record
+fn print_records(values: Vec<Value>, lines: bool) {
+    if lines {
+        for value in values {
+            match value {
+                Value::String(s) => println!("{}", s),
+                _ => println!("{}", value),
+            }
+        }
+        return;
+    }
+    println!("{}", Value::Array(values));
+}
+
+// a JSON object keyed by search key, or one `key<TAB>value` line per key
+fn print_search_results(search_keys: &[&str], values: Vec<Value>, lines: bool) {
+    if lines {
+        for (search_key, value) in search_keys.iter().zip(values) {
+            match value {
+                Value::String(s) => println!("{}\t{}", search_key, s),
+                _ => println!("{}\t{}", search_key, value),
+            }
+        }
+        return;
+    }
+    let keyed: Map<String, Value> = search_keys.iter().map(|key| key.to_string()).zip(values).collect();
+    println!("{}", Value::Object(keyed));
+}
+
+// validates the input and decides whether it has to be streamed
+fn stream_only(haystack: Option<&str>, file: Option<&str>, streaming: bool) -> Result<bool, JistError> {
+    if haystack.is_none() && file.is_none() {
+        return Err(JistError::InvalidInput("Invalid input - no object found"));
     }
     if file.is_none() && haystack.unwrap().is_empty() {
-        return Err("Invalid input - empty data");
+        return Err(JistError::InvalidInput("Invalid input - empty data"));
     }
     if haystack.is_none() && file.unwrap().is_empty() {
-        return Err("Invalid input - empty file path");
+        return Err(JistError::InvalidInput("Invalid input - empty file path"));
     }
 
-    // If input file size is greater than 4.2GB, fallback to buffered search
-    let mut stream_only = streaming;
-    if file.is_some() {
-        let f = File::open(file.unwrap()).unwrap();
-        if f.metadata().unwrap().len() >= u32::MAX as u64 {
-            debug!("file too large - fallback to char lexer");
-            stream_only = true;
+    // files larger than 4.2GB are split into windows by simd_parser, which falls back to buffered
+    // search when that isn't possible
+    if let Some(file) = file {
+        // only the streaming parser decompresses
+        if Compression::of_file(file)? != Compression::Plain {
+            debug!("compressed file - fallback to char lexer");
+            return Ok(true);
+        }
+    }
+    Ok(streaming)
+}
+
+pub fn search(
+    haystack: Option<&str>,
+    file: Option<&str>,
+    search_key: &str,
+    streaming: bool,
+    threads: usize,
+) -> Result<String, JistError> {
+    if search_key.is_empty() {
+        return Err(JistError::InvalidInput("Invalid input - no object found"));
+    }
+    if let Some(file) = file.filter(|file| !file.is_empty()) {
+        if let Some(result) = index::search(file, search_key)? {
+            debug!("answered from the index");
+            return Ok(result);
         }
     }
-    if stream_only {
+    if stream_only(haystack, file, streaming)? {
         debug!("stream only");
-        return buf_parser::search(haystack, file, search_key);
+        return buf_parser::search_parallel(haystack, file, search_key, threads);
     }
 
-    match simd_parser::search(haystack, file, search_key) {
-        Ok(result) => Ok(result),
-        Err(code) => {
-            if code.eq("JIST_ERROR_FILE_TOO_LARGE") {
-                debug!("fallback to char lexer");
-                return buf_parser::search(haystack, file, search_key);
-            }
-            Err(code)
+    or_stream(simd_parser::search(haystack, file, search_key), || buf_parser::search_parallel(haystack, file, search_key, threads))
+}
+
+// The result of the in-memory parser, unless it can't take the input or the search key and the
+// streaming parser has to answer instead. These two errors never make it out of a search.
+fn or_stream<T, F>(simd: Result<T, JistError>, stream: F) -> Result<T, JistError>
+where
+    F: FnOnce() -> Result<T, JistError>,
+{
+    match simd {
+        Err(JistError::CapacityExceeded | JistError::UnsupportedPath) => {
+            debug!("fallback to char lexer");
+            stream()
         }
+        result => result,
+    }
+}
+
+// NDJSON input is searched record by record
+fn is_record_stream(haystack: Option<&str>, file: Option<&str>) -> Result<bool, JistError> {
+    match (haystack, file) {
+        (Some(haystack), _) => Ok(utils::is_record_stream(&utils::record_head(haystack.as_bytes())?)),
+        (None, Some(file)) if !file.is_empty() => Ok(utils::is_record_stream(&input::record_head(file)?)),
+        _ => Ok(false),
     }
 }
+
+// a search key starting with an index like `[2]` picks a single NDJSON record, looked up in the
+// index of `file` when there is one
+fn search_record(haystack: Option<&str>, file: Option<&str>, search_key: &str) -> Result<Option<String>, JistError> {
+    if let Some(file) = file.filter(|file| !file.is_empty()) {
+        if let Some(result) = index::search(file, search_key)? {
+            debug!("answered from the index");
+            return Ok(Some(result));
+        }
+    }
+    buf_parser::search_record(haystack, file, search_key)
+}
+
+pub fn search_records(
+    haystack: Option<&str>,
+    file: Option<&str>,
+    search_key: &str,
+    streaming: bool,
+) -> Result<Vec<Value>, JistError> {
+    if stream_only(haystack, file, streaming)? {
+        debug!("stream only");
+        return buf_parser::search_records(haystack, file, search_key);
+    }
+
+    or_stream(simd_parser::search_records(haystack, file, search_key), || buf_parser::search_records(haystack, file, search_key))
+}
+
+// several search keys answered by one pass over the input
+pub fn search_many(
+    haystack: Option<&str>,
+    file: Option<&str>,
+    search_keys: &[&str],
+    streaming: bool,
+) -> Result<Vec<Value>, JistError> {
+    if search_keys.iter().any(|key| key.is_empty()) {
+        return Err(JistError::InvalidInput("Invalid input - no object found"));
+    }
+    if stream_only(haystack, file, streaming)? {
+        debug!("stream only");
+        return buf_parser::search_many(haystack, file, search_keys);
+    }
+
+    or_stream(simd_parser::search_many(haystack, file, search_keys), || buf_parser::search_many(haystack, file, search_keys))
+}
@@ -0,0 +1,149 @@
+use crate::model::path_segment::{select, PathSegment};
+use serde_json::{Number, Value};
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Operand {
+    // relative path from the current element (`@.a.b`), an empty path is the element itself
+    Path(Vec<PathSegment>),
+    Literal(Value),
+}
+
+// the expression of a `[?(...)]` filter segment
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Predicate {
+    Exists(Vec<PathSegment>),
+    Compare(Operand, CmpOp, Operand),
+    Not(Box<Predicate>),
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+}
+
+impl Predicate {
+    pub fn test(&self, value: &Value) -> bool {
+        match self {
+            Predicate::Exists(path) => resolve(value, path).is_some(),
+            Predicate::Compare(left, op, right) => {
+                let left = operand(value, left);
+                let right = operand(value, right);
+                match op {
+                    CmpOp::Eq => equal(left, right),
+                    CmpOp::Ne => !equal(left, right),
+                    CmpOp::Lt => less(left, right),
+                    CmpOp::Le => less(left, right) || equal(left, right),
+                    CmpOp::Gt => less(right, left),
+                    CmpOp::Ge => less(right, left) || equal(left, right),
+                }
+            }
+            Predicate::Not(inner) => !inner.test(value),
+            Predicate::And(left, right) => left.test(value) && right.test(value),
+            Predicate::Or(left, right) => left.test(value) || right.test(value),
+        }
+    }
+}
+
+fn resolve<'a>(value: &'a Value, path: &[PathSegment]) -> Option<&'a Value> {
+    let mut selected = Vec::new();
+    select(value, path, "", &mut selected);
+    selected.first().map(|(_, v)| *v)
+}
+
+fn operand<'a>(value: &'a Value, operand: &'a Operand) -> Option<&'a Value> {
+    match operand {
+        Operand::Path(path) => resolve(value, path),
+        Operand::Literal(literal) => Some(literal),
+    }
+}
+
+// a missing value only equals another missing value, numbers compare by value (1 == 1.0)
+fn equal(left: Option<&Value>, right: Option<&Value>) -> bool {
+    match (left, right) {
+        (Some(Value::Number(l)), Some(Value::Number(r))) => compare(l, r) == Some(Ordering::Equal),
+        (l, r) => l == r,
+    }
+}
+
+// only numbers and strings are ordered, anything else is never less than anything
+fn less(left: Option<&Value>, right: Option<&Value>) -> bool {
+    match (left, right) {
+        (Some(Value::Number(l)), Some(Value::Number(r))) => compare(l, r) == Some(Ordering::Less),
+        (Some(Value::String(l)), Some(Value::String(r))) => l < r,
+        _ => false,
+    }
+}
+
+// Two integers compare exactly, ids above 2^53 would round to the same f64. Numbers are only
+// compared as f64 when one of them is a float.
+fn compare(left: &Number, right: &Number) -> Option<Ordering> {
+    let integer = |n: &Number| n.as_i64().map(i128::from).or_else(|| n.as_u64().map(i128::from));
+    match (integer(left), integer(right)) {
+        (Some(l), Some(r)) => Some(l.cmp(&r)),
+        _ => left.as_f64().partial_cmp(&right.as_f64()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::json_path::JsonPath;
+
+    fn predicate(expr: &str) -> Result<Predicate, &'static str> {
+        match JsonPath::parse(&format!("[?{}]", expr))?.segments.remove(0) {
+            PathSegment::Filter(predicate) => Ok(predicate),
+            _ => Err("not a filter"),
+        }
+    }
+
+    #[test]
+    fn predicate_test() {
+        let value: Value = serde_json::from_str(r#"{"foo": 42, "bar": {"poo": "true"}, "tags": ["a"], "n": null}"#).unwrap();
+        let test = |expr: &str| predicate(expr).unwrap().test(&value);
+        assert!(test("@.foo > 40"));
+        assert!(!test("@.foo < 40"));
+        assert!(test("@.foo == 42.0"));
+        assert!(test(r#"@.bar.poo == "true""#));
+        assert!(test("@['bar']['poo'] == 'true'"));
+        assert!(!test("@.bar.poo == true"));
+        assert!(test("@.tags[0] == 'a'"));
+        assert!(test("@.n == null"));
+        assert!(test("@.n"));
+        assert!(!test("@.missing"));
+        assert!(test("!@.missing"));
+        assert!(test("@.missing != 1"));
+        assert!(!test("@.missing < 1"));
+        assert!(test("@.foo > 50 || @.bar.poo == 'true' && @.foo >= 42"));
+        assert!(!test("(@.foo > 50 || @.bar.poo == 'true') && @.foo <= 41"));
+        assert!(test("40 < @.foo"));
+        assert!(predicate("@.foo >").is_err());
+        assert!(predicate("@.foo == 'x").is_err());
+        assert!(predicate("(@.foo").is_err());
+        assert!(predicate("42").is_err());
+    }
+
+    #[test]
+    fn large_integers() {
+        let value: Value = serde_json::from_str(r#"{"id": 9007199254740992, "big": 18446744073709551615, "neg": -9223372036854775807}"#).unwrap();
+        let test = |expr: &str| predicate(expr).unwrap().test(&value);
+        assert!(!test("@.id == 9007199254740993"));
+        assert!(test("@.id != 9007199254740993"));
+        assert!(test("@.id == 9007199254740992"));
+        assert!(test("@.id < 9007199254740993"));
+        assert!(!test("@.id > 9007199254740993"));
+        assert!(test("@.big > 18446744073709551614"));
+        assert!(test("@.neg < @.big"));
+        assert!(test("@.neg < -9223372036854775806"));
+        // a float on either side compares as f64
+        assert!(test("@.id == 9007199254740992.0"));
+        assert!(test("@.id < 9007199254740994.5"));
+    }
+}
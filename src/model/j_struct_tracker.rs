@@ -1,67 +1,483 @@
+use std::collections::VecDeque;
 use json_tools::TokenType;
 use log::debug;
-use crate::utils::{array_ind, checkpoint_depth};
+use crate::error::JistError;
+use crate::model::json_path::JsonPath;
+use crate::model::path_segment::PathSegment;
+use crate::utils::{json_str, push_index, push_key};
 
-pub(crate) struct JStructTracker {
-    // tuple of (depth, arr_depth, obj_depth)
-    pub depth_curr: (i32, i32, i32),
+// array elements held back for a segment that needs the array length (negative indices / slices)
+pub(crate) struct TailWindow {
+    pub state: usize,
+    pub limit: Option<usize>,
+    // (index, start, end)
+    pub elements: VecDeque<(i64, u64, u64)>,
+}
 
+// a completed value - the remainder of the search path from `state` onwards still has to be applied
+// to it, and a filtered value has to pass the filter at `state - 1` first
+#[derive(Debug)]
+pub(crate) struct Span {
+    pub start: u64,
+    pub end: u64,
+    pub state: usize,
+    pub path: String,
+    pub filtered: bool,
+}
+
+// a value whose closing token has not been seen yet - either a full match, a value for a buffered
+// segment or a candidate for a tail window or filter (deferred holds the path state waiting on it)
+#[derive(Debug)]
+pub(crate) struct Capture {
+    pub start: u64,
+    pub depth: usize,
+    pub deferred: Option<usize>,
+    pub path: String,
+}
+
+pub(crate) struct JStructTracker {
     // keep track of array indices if currently inside array
     pub arr_idx: Vec<i64>,
     pub last_open: Vec<TokenType>,
-    pub last_open_pin: Vec<(TokenType, u64, usize)>,
-    pub checkpoint_start: Vec<u64>,
     pub last_token_key_delimiter: bool,
 
-    // build checkpoints that must pass
-    pub checkpoints: Vec<(i32, i32, i32)>,
-    pub search_keys: Vec<String>,
-    pub arr_tgt: Vec<i64>,
-    pub arr_tgt_size: usize,
+    // keep track of the last key seen if currently inside object
+    pub obj_key: Vec<String>,
+
+    // for every open container, the number of search path segments it has matched
+    // (more than one entry once wildcards or recursive descent let several members reach the same container)
+    pub path_states: Vec<Vec<usize>>,
+    pub root_seen: bool,
+
+    pub captures: Vec<Capture>,
+    pub tail_windows: Vec<Vec<TailWindow>>,
+    pub search_path: Vec<PathSegment>,
+    // concrete paths are only tracked for recursive descent, where they are part of the output
+    pub with_paths: bool,
+    pub rfc: bool,
+    // Path state of the RFC 9535 recursive descent that is streamed. Its matches are put in RFC order:
+    // by the start of the container they were selected from, then by their own start.
+    pub ordered: Option<usize>,
+    // start of every open container, and of the member a singular selector of the streamed
+    // descendant picked from it
+    pub open_starts: Vec<u64>,
+    pub selected: Vec<Option<u64>>,
+
+    pub spans: Vec<Span>,
+    // matches completed inside another match, released right after it to keep document order
+    pub nested_spans: Vec<Span>,
+    // (parent start, member start, span) of RFC ordered matches an open container can still precede
+    pub held: Vec<(u64, u64, Span)>,
 }
 impl JStructTracker {
 
     pub fn init() -> JStructTracker {
         JStructTracker {
-            depth_curr: (-1, -1, -1),
             arr_idx: Vec::new(),
             last_open: Vec::new(),
-            last_open_pin: Vec::new(),
-            checkpoint_start: Vec::new(),
             last_token_key_delimiter: false,
-            checkpoints: Vec::new(),
-            search_keys: Vec::new(),
-            arr_tgt: Vec::new(),
-            arr_tgt_size: 0,
+            obj_key: Vec::new(),
+            path_states: Vec::new(),
+            root_seen: false,
+            captures: Vec::new(),
+            tail_windows: Vec::new(),
+            search_path: Vec::new(),
+            with_paths: false,
+            rfc: false,
+            ordered: None,
+            open_starts: Vec::new(),
+            selected: Vec::new(),
+            spans: Vec::new(),
+            nested_spans: Vec::new(),
+            held: Vec::new(),
         }
     }
-    pub fn new(search_path: &[String]) -> JStructTracker {
+    pub fn new(query: &JsonPath) -> JStructTracker {
         let mut struct_tracker = Self::init();
+        struct_tracker.search_path = query.segments.clone();
+        struct_tracker.with_paths = query.with_paths();
+        struct_tracker.rfc = query.rfc;
+        // only the first descendant streams, values reaching a later one are buffered
+        let first = struct_tracker.search_path.iter().position(|x| matches!(x, PathSegment::Descendant(_)));
+        struct_tracker.ordered = first.filter(|state| query.rfc && !struct_tracker.buffered(*state));
+        debug!("search_path: {:?}", struct_tracker.search_path);
+        struct_tracker
+    }
+
+    // path states reached by a value starting at the current position, along with the path
+    // states that need to see the whole array before deciding on it
+    fn value_states(&self) -> (Vec<usize>, Vec<usize>) {
+        let parent_states = match self.path_states.last() {
+            Some(states) => states,
+            None if self.root_seen => return (Vec::new(), Vec::new()),
+            None => return (vec![0], Vec::new()),
+        };
+        let (key, idx) = match self.last_open.last() {
+            Some(TokenType::CurlyOpen) => (self.obj_key.last().map(|k| k.as_str()), None),
+            _ => (None, self.arr_idx.last().copied()),
+        };
+        let mut states = Vec::new();
+        let mut deferred = Vec::new();
+        for state in parent_states {
+            if self.buffered(*state) {
+                continue;
+            }
+            let segment = &self.search_path[*state];
+            // recursive descent stays active for everything nested below
+            if matches!(segment, PathSegment::Descendant(_)) && !states.contains(state) {
+                states.push(*state);
+            }
+            if segment.is_filter() {
+                deferred.push(*state);
+            } else if segment.tail_window().is_some() {
+                if idx.is_some() {
+                    deferred.push(*state);
+                }
+            } else if segment.matches(key, idx) && !states.contains(&(state + 1)) {
+                states.push(state + 1);
+            }
+        }
+        (states, deferred)
+    }
+
+    // Segments applied in memory to the whole value reaching them: unions keep selector order, which
+    // needs every sibling, and so does an RFC 9535 descendant below the streamed one
+    fn buffered(&self, state: usize) -> bool {
+        match &self.search_path[state] {
+            PathSegment::Union(_) => true,
+            PathSegment::Descendant(inner) => {
+                matches!(**inner, PathSegment::Union(_)) || (self.rfc && self.ordered.is_some_and(|first| first < state))
+            }
+            _ => false,
+        }
+    }
+
+    // whether the open container at `level` can still select a member for the streamed descendant
+    fn may_select(&self, level: usize, descendant: usize) -> bool {
+        let PathSegment::Descendant(inner) = &self.search_path[descendant] else {
+            return false;
+        };
+        let object = self.last_open[level] == TokenType::CurlyOpen;
+        let kind = match **inner {
+            PathSegment::Name(_) => object,
+            PathSegment::Index(_) | PathSegment::Slice(..) => !object,
+            _ => true,
+        };
+        // a singular selector is done with the container once the member it picked is complete
+        let pending = self.selected[level].is_none_or(|start| self.open_starts.get(level + 1) == Some(&start));
+        kind && pending && self.path_states[level].contains(&descendant)
+    }
+
+    // Output a completed value. Matches of the streamed descendant wait until no open container
+    // can select a member that comes before them in RFC order. `member` overrides the start the
+    // value is ordered by, elements of a tail window share one to keep selection order.
+    fn emit(&mut self, span: Span, member: Option<u64>) {
+        let Some(descendant) = self.ordered.filter(|descendant| span.state > *descendant) else {
+            self.spans.push(span);
+            return;
+        };
+        // the member selected by the descendant is `up` containers above the value
+        let (n, up) = (self.open_starts.len(), span.state - descendant - 1);
+        let key = match up {
+            0 => (self.open_starts.get(n.wrapping_sub(1)), Some(member.as_ref().unwrap_or(&span.start))),
+            _ => (self.open_starts.get(n.wrapping_sub(up + 1)), self.open_starts.get(n.wrapping_sub(up))),
+        };
+        let key = (key.0.copied().unwrap_or(0), key.1.copied().unwrap_or(0));
+        let at = self.held.partition_point(|(parent, member, _)| (*parent, *member) <= key);
+        self.held.insert(at, (key.0, key.1, span));
+    }
+
+    // a singular selector of the streamed descendant picked the value starting at `start`
+    fn select(&mut self, start: u64, states: &[usize]) {
+        let singular = |descendant: &usize| match &self.search_path[*descendant] {
+            PathSegment::Descendant(inner) => inner.is_singular(),
+            _ => false,
+        };
+        if self.ordered.filter(singular).is_some_and(|descendant| states.contains(&(descendant + 1))) {
+            if let Some(selected) = self.selected.last_mut() {
+                *selected = Some(start);
+            }
+        }
+    }
+
+    fn release(&mut self) {
+        let Some(descendant) = self.ordered else {
+            return;
+        };
+        let released = self.held.iter().take_while(|(parent, _, _)| {
+            !(0..self.last_open.len()).any(|level| self.open_starts[level] < *parent && self.may_select(level, descendant))
+        });
+        let released = released.count();
+        self.spans.extend(self.held.drain(..released).map(|(_, _, span)| span));
+    }
+
+    // concrete path of the value starting at the current position
+    fn concrete_path(&self) -> String {
+        let mut path = String::new();
+        let (mut obj_depth, mut arr_depth) = (0, 0);
+        for open in &self.last_open {
+            if *open == TokenType::CurlyOpen {
+                path = push_key(&path, &self.obj_key[obj_depth]);
+                obj_depth += 1;
+            } else {
+                path = push_index(&path, self.arr_idx[arr_depth] as usize);
+                arr_depth += 1;
+            }
+        }
+        path
+    }
+
+    // path of an element of the innermost open array
+    fn element_path(&self, idx: i64) -> String {
+        if !self.with_paths {
+            return String::new();
+        }
+        let mut path = self.concrete_path();
+        // concrete_path ends at the current element, swap it for the requested one
+        if let Some(pos) = path.rfind('[') {
+            path.truncate(pos);
+        }
+        push_index(&path, idx as usize)
+    }
 
-        for search_idx in 0..search_path.len() {
-            struct_tracker
-                .checkpoints
-                .push(checkpoint_depth(search_path, search_idx));
-        }
-        struct_tracker.checkpoints.reverse();
-
-        struct_tracker.arr_tgt = search_path
-            .iter()
-            .filter(|x| x.starts_with('['))
-            .map(|x| array_ind(x.as_str()))
-            .rev()
-            .collect();
-        struct_tracker.arr_tgt_size = struct_tracker.arr_tgt.len();
-        struct_tracker.search_keys = search_path
-            .iter()
-            .filter(|x| !x.starts_with('['))
-            .cloned()
-            .rev()
-            .collect::<Vec<String>>();
-        debug!(
-            "checkpoints: {:?}, arr_tgt: {:?}, search_keys: {:?}",
-            struct_tracker.checkpoints, struct_tracker.arr_tgt, struct_tracker.search_keys
+    fn begin(&self, start: u64, states: &[usize], deferred: &[usize]) -> Vec<Capture> {
+        let path = if self.with_paths { self.concrete_path() } else { String::new() };
+        let depth = self.last_open.len();
+        let mut captures = Vec::new();
+        if states.contains(&self.search_path.len()) {
+            debug!("[capture started] at {}", start);
+            captures.push(Capture { start, depth, deferred: None, path: path.clone() });
+        }
+        for state in states.iter().filter(|s| **s < self.search_path.len() && self.buffered(**s)) {
+            captures.push(Capture { start, depth, deferred: Some(*state), path: path.clone() });
+        }
+        for state in deferred {
+            captures.push(Capture { start, depth, deferred: Some(*state), path: path.clone() });
+        }
+        captures
+    }
+
+    // full matches, buffered values and filter candidates are output directly, anything else
+    // waits in a tail window
+    fn is_output(&self, capture: &Capture) -> bool {
+        capture.deferred.is_none_or(|state| self.buffered(state) || self.search_path[state].is_filter())
+    }
+
+    fn complete(&mut self, capture: Capture, end: u64) {
+        if self.is_output(&capture) {
+            let (state, filtered) = match capture.deferred {
+                None => (self.search_path.len(), false),
+                Some(state) if self.buffered(state) => (state, false),
+                Some(state) => (state + 1, true),
+            };
+            let span = Span { start: capture.start, end, state, path: capture.path, filtered };
+            if self.ordered.is_some() {
+                self.emit(span, None);
+            } else if self.captures.iter().any(|c| self.is_output(c)) {
+                self.nested_spans.push(span);
+            } else {
+                self.spans.push(span);
+                self.nested_spans.sort_by_key(|span| span.start);
+                self.spans.append(&mut self.nested_spans);
+            }
+            return;
+        }
+        let state = capture.deferred.unwrap();
+        let idx = *self.arr_idx.last().unwrap();
+        let limit = self.search_path[state].tail_window().unwrap();
+        let windows = self.tail_windows.last_mut().unwrap();
+        let window = match windows.iter().position(|w| w.state == state) {
+            Some(pos) => &mut windows[pos],
+            None => {
+                windows.push(TailWindow { state, limit, elements: VecDeque::new() });
+                windows.last_mut().unwrap()
+            }
+        };
+        window.elements.push_back((idx, capture.start, end));
+        if window.limit.is_some_and(|limit| window.elements.len() > limit) {
+            let (evicted_idx, evicted_start, evicted_end) = window.elements.pop_front().unwrap();
+            if self.search_path[state].selects_evicted(evicted_idx) {
+                let path = self.element_path(evicted_idx);
+                self.emit(Span { start: evicted_start, end: evicted_end, state: state + 1, path, filtered: false }, None);
+            }
+        }
+    }
+
+    // the array is closed so its length is known
+    fn resolve(&mut self, window: TailWindow) {
+        let len = window.elements.back().map_or(0, |(idx, _, _)| *idx as usize + 1);
+        let first = window.elements.front().map(|(_, start, _)| *start);
+        for idx in self.search_path[window.state].indices(len) {
+            if let Some((_, start, end)) = window.elements.iter().find(|(i, _, _)| *i as usize == idx) {
+                let path = self.element_path(idx as i64);
+                self.emit(Span { start: *start, end: *end, state: window.state + 1, path, filtered: false }, first);
+            }
+        }
+    }
+
+    // earliest input position a value still to be output can start at
+    pub fn retain_from(&self) -> Option<u64> {
+        let captures = self.captures.iter().map(|c| c.start);
+        let windows = self.tail_windows.iter().flatten().flat_map(|w| w.elements.iter().map(|(_, start, _)| *start));
+        let nested = self.nested_spans.iter().chain(self.held.iter().map(|(_, _, span)| span)).map(|span| span.start);
+        captures.chain(windows).chain(nested).min()
+    }
+
+    // Feed the next lexer token at absolute position [first, end). `raw` holds the token bytes
+    // and is only read for object keys, which are compared with their escapes decoded. Completed values are added to `spans`.
+    // Invalid tokens and closing tokens that don't match the innermost open container are malformed.
+    pub fn advance(&mut self, kind: &TokenType, first: u64, end: u64, raw: &[u8]) -> Result<(), JistError> {
+        match kind {
+            TokenType::CurlyOpen | TokenType::BracketOpen => {
+                let (states, deferred) = self.value_states();
+                self.select(first, &states);
+                let mut captures = self.begin(first, &states, &deferred);
+                self.captures.append(&mut captures);
+                self.root_seen = true;
+                self.open_starts.push(first);
+                self.selected.push(None);
+                if *kind == TokenType::CurlyOpen {
+                    self.obj_key.push(String::new());
+                    self.last_token_key_delimiter = true;
+                } else {
+                    self.arr_idx.push(0);
+                }
+                self.path_states.push(states.into_iter().filter(|s| *s < self.search_path.len()).collect());
+                self.tail_windows.push(Vec::new());
+                self.last_open.push(kind.clone());
+            }
+            TokenType::CurlyClose | TokenType::BracketClose => {
+                match (self.last_open.last(), kind) {
+                    (Some(TokenType::CurlyOpen), TokenType::CurlyClose) | (Some(TokenType::BracketOpen), TokenType::BracketClose) => {}
+                    (Some(TokenType::CurlyOpen), _) => return Err(JistError::malformed(Some(first), "expected closing curly brace")),
+                    (Some(_), _) => return Err(JistError::malformed(Some(first), "expected closing bracket")),
+                    (None, TokenType::CurlyClose) => return Err(JistError::malformed(Some(first), "missing opening curly brace")),
+                    (None, _) => return Err(JistError::malformed(Some(first), "missing opening bracket")),
+                }
+                for window in self.tail_windows.pop().unwrap_or_default() {
+                    self.resolve(window);
+                }
+                if *kind == TokenType::CurlyClose {
+                    self.obj_key.pop();
+                } else {
+                    self.arr_idx.pop();
+                }
+                self.path_states.pop();
+                self.last_open.pop();
+                self.open_starts.pop();
+                self.selected.pop();
+                let depth = self.last_open.len();
+                self.last_token_key_delimiter = false;
+                while self.captures.last().is_some_and(|c| c.depth == depth) {
+                    debug!("[capture ended] at {}", end);
+                    let capture = self.captures.pop().unwrap();
+                    self.complete(capture, end);
+                }
+            }
+            TokenType::Comma => {
+                match self.last_open.last() {
+                    Some(TokenType::BracketOpen) => {
+                        if let Some(idx) = self.arr_idx.last_mut() {
+                            *idx += 1;
+                        }
+                    }
+                    Some(TokenType::CurlyOpen) => self.last_token_key_delimiter = true,
+                    _ => {}
+                }
+            }
+            TokenType::String if self.last_token_key_delimiter => {
+                if let Some(key) = self.obj_key.last_mut() {
+                    *key = json_str(raw);
+                }
+                self.last_token_key_delimiter = false;
+            }
+            TokenType::String
+            | TokenType::Number
+            | TokenType::BooleanTrue
+            | TokenType::BooleanFalse
+            | TokenType::Null => {
+                let (states, deferred) = self.value_states();
+                self.select(first, &states);
+                self.root_seen = true;
+                for capture in self.begin(first, &states, &deferred) {
+                    self.complete(capture, end);
+                }
+            }
+            TokenType::Invalid => return Err(JistError::malformed(Some(first), "invalid token")),
+            _ => {}
+        }
+        if !self.held.is_empty() {
+            self.release();
+        }
+        Ok(())
+    }
+
+    // the input ended at `end`, which is malformed while a container is still open
+    pub fn finish(&self, end: u64) -> Result<(), JistError> {
+        if self.last_open.is_empty() {
+            return Ok(());
+        }
+        Err(JistError::malformed(Some(end), "unexpected end of input"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::token_pos;
+    use json_tools::{BufferType, Lexer};
+
+    // `path=text` of the values the tracker completes for `search_key`, with the path state of
+    // values the rest of the path is applied to in memory
+    fn spans(input: &str, search_key: &str) -> Vec<String> {
+        let mut tracker = JStructTracker::new(&JsonPath::parse(search_key).unwrap());
+        let mut found = Vec::new();
+        for token in Lexer::new(input.bytes().chain(Some(b'\n')), BufferType::Span) {
+            let (first, end) = token_pos(&token.buf).unwrap();
+            tracker.advance(&token.kind, first, end, &input.as_bytes()[first as usize..end as usize]).unwrap();
+            for span in std::mem::take(&mut tracker.spans) {
+                let text = &input[span.start as usize..span.end as usize];
+                if span.state == tracker.search_path.len() {
+                    found.push(format!("{}={}", span.path, text));
+                } else {
+                    found.push(format!("{}={} at {}", span.path, text, span.state));
+                }
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn descendant_paths() {
+        let sample = r#"{"store": {"book": [{"title": "A", "price": 8}, {"title": "B", "price": 12}], "bicycle": {"price": 20}}, "price": 1, "meta": {"price": {"price": 2}}}"#;
+        assert_eq!(
+            spans(sample, "..price"),
+            vec!["store.book[0].price=8", "store.book[1].price=12", "store.bicycle.price=20", "price=1", r#"meta.price={"price": 2}"#, "meta.price.price=2"]
         );
-        struct_tracker
+        assert_eq!(spans(sample, "store..title"), vec![r#"store.book[0].title="A""#, r#"store.book[1].title="B""#]);
+        // the last element is only known once the array is closed, its title is picked in memory
+        assert_eq!(spans(sample, "..book[-1].title"), vec![r#"store.book[1]={"title": "B", "price": 12} at 2"#]);
+        assert!(spans(sample, "..missing").is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn rfc_descendant_streams() {
+        let records: Vec<String> = (0..10_000).map(|i| format!(r#"{{"id": {}, "x": {{"n": {}}}}}"#, i, i)).collect();
+        let input = format!("[{}]", records.join(", "));
+        let mut tracker = JStructTracker::new(&JsonPath::parse("$..x").unwrap());
+        assert_eq!(tracker.ordered, Some(0));
+        let (mut found, mut retained) = (0, 0);
+        for token in Lexer::new(input.bytes(), BufferType::Span) {
+            let (first, end) = token_pos(&token.buf).unwrap();
+            tracker.advance(&token.kind, first, end, &input.as_bytes()[first as usize..end as usize]).unwrap();
+            found += std::mem::take(&mut tracker.spans).len();
+            // only the `x` being read is kept, not the document around it
+            retained = retained.max(tracker.retain_from().map_or(0, |from| end - from));
+            assert!(tracker.held.is_empty());
+        }
+        assert_eq!(found, 10_000);
+        assert!(retained < 16, "retained {} bytes", retained);
+        // unions still need every sibling
+        assert_eq!(JStructTracker::new(&JsonPath::parse("$..['x','id']").unwrap()).ordered, None);
+    }
+}
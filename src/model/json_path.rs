@@ -0,0 +1,583 @@
+use crate::model::filter::{CmpOp, Operand, Predicate};
+use crate::model::path_segment::PathSegment;
+use crate::utils::unescape;
+
+const INVALID_PATH: &str = "invalid search key";
+const INVALID_FILTER: &str = "invalid filter expression";
+const UNSUPPORTED_FUNCTIONS: &str = "unsupported JSONPath: function extensions";
+const UNSUPPORTED_ROOT_QUERY: &str = "unsupported JSONPath: absolute queries inside filters";
+const FUNCTIONS: [&str; 5] = ["length", "count", "match", "search", "value"];
+
+// I-JSON range of integers, RFC 9535 rejects indices outside of it
+const MAX_INT: i64 = (1 << 53) - 1;
+
+// A parsed search key. Three syntaxes are accepted:
+// - `$`-rooted RFC 9535 JSONPath, which always returns the matches as a nodelist (JSON array)
+// - RFC 6901 JSON pointers starting with `/`
+// - jist's dotted paths (`a.b[0]`, `..key`, `[?(@.foo > 1)]`), which return a single value when definite
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct JsonPath {
+    pub segments: Vec<PathSegment>,
+    pub rfc: bool,
+}
+
+impl JsonPath {
+    pub fn parse(search_key: &str) -> Result<JsonPath, &'static str> {
+        if search_key.starts_with('/') {
+            return Ok(JsonPath { segments: parse_json_pointer(search_key), rfc: false });
+        }
+        let mut parser = Parser { chars: search_key.chars().collect(), pos: 0, rfc: search_key.starts_with('$') };
+        let segments = if parser.rfc {
+            parser.pos = 1;
+            parser.segments(false)?
+        } else {
+            parser.dotted_segments()?
+        };
+        Ok(JsonPath { segments, rfc: parser.rfc })
+    }
+
+    // a definite path addresses at most one value, anything else returns a JSON array of matches
+    pub fn is_definite(&self) -> bool {
+        !self.rfc && self.segments.iter().all(|x| x.is_singular())
+    }
+
+    // recursive descent matches of dotted paths are returned keyed by their concrete path
+    pub fn with_paths(&self) -> bool {
+        !self.rfc && self.segments.iter().any(|x| matches!(x, PathSegment::Descendant(_)))
+    }
+}
+
+// RFC 6901 pointer (`/items/3/name`) to path segments. Numeric tokens stay keys, which also
+// address array elements.
+fn parse_json_pointer(pointer: &str) -> Vec<PathSegment> {
+    pointer
+        .split('/')
+        .skip(1)
+        .map(|token| PathSegment::Key(token.replace("~1", "/").replace("~0", "~")))
+        .collect()
+}
+
+fn is_name_first(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    // RFC 9535 grammar is strict, dotted paths are read leniently
+    rfc: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn skip_blank(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn name(&self, name: String) -> PathSegment {
+        if self.rfc {
+            PathSegment::Name(name)
+        } else {
+            PathSegment::Key(name)
+        }
+    }
+
+    // `a.b[0]`, `..key`, `*` - names run up to the next `.` or `[`
+    fn dotted_segments(&mut self) -> Result<Vec<PathSegment>, &'static str> {
+        let mut segments = Vec::new();
+        while self.peek().is_some() {
+            if self.starts_with("..") {
+                self.pos += 2;
+                let inner = match self.peek() {
+                    Some('[') => self.bracket()?,
+                    _ => self.dotted_name()?,
+                };
+                segments.push(PathSegment::Descendant(Box::new(inner)));
+            } else if self.peek() == Some('[') {
+                segments.push(self.bracket()?);
+            } else {
+                self.eat('.');
+                if matches!(self.peek(), None | Some('[')) {
+                    continue;
+                }
+                segments.push(self.dotted_name()?);
+            }
+        }
+        Ok(segments)
+    }
+
+    fn dotted_name(&mut self) -> Result<PathSegment, &'static str> {
+        let start = self.pos;
+        while !matches!(self.peek(), None | Some('.' | '[')) {
+            if self.peek() == Some(']') {
+                return Err(INVALID_PATH);
+            }
+            self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        match name.as_str() {
+            "" => Err(INVALID_PATH),
+            "*" => Ok(PathSegment::Wildcard),
+            _ => Ok(PathSegment::Key(name)),
+        }
+    }
+
+    // RFC 9535 segments: `.name`, `.*`, `..name`, `..*`, `..[...]` and `[...]`, optionally separated
+    // by blanks. Relative queries inside filters stop at the first thing that isn't a segment.
+    fn segments(&mut self, relative: bool) -> Result<Vec<PathSegment>, &'static str> {
+        let mut segments = Vec::new();
+        loop {
+            let before_blank = self.pos;
+            self.skip_blank();
+            let segment = if self.starts_with("..") {
+                self.pos += 2;
+                let inner = match self.peek() {
+                    Some('[') => self.bracket()?,
+                    Some('*') => {
+                        self.pos += 1;
+                        PathSegment::Wildcard
+                    }
+                    _ => self.member_name()?,
+                };
+                PathSegment::Descendant(Box::new(inner))
+            } else if self.eat('.') {
+                if self.eat('*') {
+                    PathSegment::Wildcard
+                } else {
+                    self.member_name()?
+                }
+            } else if self.peek() == Some('[') {
+                self.bracket()?
+            } else if relative || (self.peek().is_none() && self.pos == before_blank) {
+                self.pos = before_blank;
+                return Ok(segments);
+            } else {
+                return Err(INVALID_PATH);
+            };
+            segments.push(segment);
+        }
+    }
+
+    // member-name-shorthand: a letter, `_` or non-ASCII character followed by those or digits
+    fn member_name(&mut self) -> Result<PathSegment, &'static str> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| is_name_first(c) || (c.is_ascii_digit() && self.pos > start)) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(INVALID_PATH);
+        }
+        Ok(self.name(self.chars[start..self.pos].iter().collect()))
+    }
+
+    // `[selector, ...]`, more than one selector makes a union
+    fn bracket(&mut self) -> Result<PathSegment, &'static str> {
+        self.eat('[');
+        let mut selectors = Vec::new();
+        loop {
+            self.skip_blank();
+            selectors.push(self.selector()?);
+            self.skip_blank();
+            if self.eat(']') {
+                break;
+            }
+            if !self.eat(',') {
+                return Err(INVALID_PATH);
+            }
+        }
+        if selectors.len() == 1 {
+            return Ok(selectors.remove(0));
+        }
+        Ok(PathSegment::Union(selectors))
+    }
+
+    fn selector(&mut self) -> Result<PathSegment, &'static str> {
+        match self.peek() {
+            Some('\'' | '"') => {
+                let name = self.string_literal()?;
+                Ok(self.name(name))
+            }
+            Some('*') => {
+                self.pos += 1;
+                Ok(PathSegment::Wildcard)
+            }
+            Some('?') => {
+                self.pos += 1;
+                Ok(PathSegment::Filter(self.logical_or()?))
+            }
+            _ => {
+                let start = self.int()?;
+                self.skip_blank();
+                if !self.eat(':') {
+                    return start.map(PathSegment::Index).ok_or(INVALID_PATH);
+                }
+                self.skip_blank();
+                let end = self.int()?;
+                self.skip_blank();
+                let mut step = None;
+                if self.eat(':') {
+                    self.skip_blank();
+                    step = self.int()?;
+                }
+                Ok(PathSegment::Slice(start, end, step.unwrap_or(1)))
+            }
+        }
+    }
+
+    // an optional integer - RFC 9535 forbids leading zeros, `-0` and values outside the I-JSON range
+    fn int(&mut self) -> Result<Option<i64>, &'static str> {
+        let start = self.pos;
+        self.eat('-');
+        let digits = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Ok(None);
+        }
+        if self.pos == digits {
+            return Err(INVALID_PATH);
+        }
+        let leading_zero = self.chars[digits] == '0' && (self.pos - digits > 1 || digits > start);
+        if self.rfc && leading_zero {
+            return Err(INVALID_PATH);
+        }
+        let raw: String = self.chars[start..self.pos].iter().collect();
+        match raw.parse::<i64>() {
+            Ok(int) if !self.rfc || int.abs() <= MAX_INT => Ok(Some(int)),
+            _ => Err(INVALID_PATH),
+        }
+    }
+
+    // single or double quoted string with JSON style escapes
+    fn string_literal(&mut self) -> Result<String, &'static str> {
+        let quote = self.peek().ok_or(INVALID_PATH)?;
+        self.pos += 1;
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                None => return Err(INVALID_PATH),
+                Some(c) if c == quote => break,
+                Some('\\') => {
+                    self.pos += 1;
+                    if self.rfc {
+                        self.check_escape(quote)?;
+                    } else {
+                        self.pos += 1;
+                    }
+                }
+                Some(c) if self.rfc && c < ' ' => return Err(INVALID_PATH),
+                Some(_) => self.pos += 1,
+            }
+        }
+        let raw: String = self.chars[start..self.pos].iter().collect();
+        self.pos += 1;
+        Ok(unescape(&raw))
+    }
+
+    // the escape following a backslash, only the enclosing quote may be escaped
+    fn check_escape(&mut self, quote: char) -> Result<(), &'static str> {
+        match self.peek() {
+            Some('b' | 'f' | 'n' | 'r' | 't' | '/' | '\\') => self.pos += 1,
+            Some(c) if c == quote => self.pos += 1,
+            Some('u') => {
+                let high = self.hex4()?;
+                if (0xDC00..0xE000).contains(&high) {
+                    return Err(INVALID_PATH);
+                }
+                if (0xD800..0xDC00).contains(&high) {
+                    if !self.starts_with("\\u") {
+                        return Err(INVALID_PATH);
+                    }
+                    self.pos += 1;
+                    if !(0xDC00..0xE000).contains(&self.hex4()?) {
+                        return Err(INVALID_PATH);
+                    }
+                }
+            }
+            _ => return Err(INVALID_PATH),
+        }
+        Ok(())
+    }
+
+    // `uXXXX`, returns the code unit
+    fn hex4(&mut self) -> Result<u32, &'static str> {
+        let hex: String = self.chars.iter().skip(self.pos + 1).take(4).collect();
+        if hex.len() != 4 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(INVALID_PATH);
+        }
+        self.pos += 5;
+        u32::from_str_radix(&hex, 16).map_err(|_| INVALID_PATH)
+    }
+
+    fn logical_or(&mut self) -> Result<Predicate, &'static str> {
+        let mut left = self.logical_and()?;
+        loop {
+            self.skip_blank();
+            if !self.starts_with("||") {
+                return Ok(left);
+            }
+            self.pos += 2;
+            left = Predicate::Or(Box::new(left), Box::new(self.logical_and()?));
+        }
+    }
+
+    fn logical_and(&mut self) -> Result<Predicate, &'static str> {
+        let mut left = self.basic_expr()?;
+        loop {
+            self.skip_blank();
+            if !self.starts_with("&&") {
+                return Ok(left);
+            }
+            self.pos += 2;
+            left = Predicate::And(Box::new(left), Box::new(self.basic_expr()?));
+        }
+    }
+
+    // parenthesized expression, negation, comparison or existence test
+    fn basic_expr(&mut self) -> Result<Predicate, &'static str> {
+        self.skip_blank();
+        if self.eat('!') {
+            self.skip_blank();
+            if self.peek() == Some('(') {
+                return Ok(Predicate::Not(Box::new(self.paren_expr()?)));
+            }
+            return match self.comparable()? {
+                Operand::Path(path) => Ok(Predicate::Not(Box::new(Predicate::Exists(path)))),
+                Operand::Literal(_) => Err(INVALID_FILTER),
+            };
+        }
+        if self.peek() == Some('(') {
+            return self.paren_expr();
+        }
+        let left = self.comparable()?;
+        let before_blank = self.pos;
+        self.skip_blank();
+        let Some(op) = self.cmp_op() else {
+            self.pos = before_blank;
+            return match left {
+                Operand::Path(path) => Ok(Predicate::Exists(path)),
+                Operand::Literal(_) => Err(INVALID_FILTER),
+            };
+        };
+        self.skip_blank();
+        let right = self.comparable()?;
+        // comparisons are only defined on single values
+        for operand in [&left, &right] {
+            if let Operand::Path(path) = operand {
+                if !path.iter().all(|x| x.is_singular()) {
+                    return Err(INVALID_FILTER);
+                }
+            }
+        }
+        Ok(Predicate::Compare(left, op, right))
+    }
+
+    fn paren_expr(&mut self) -> Result<Predicate, &'static str> {
+        self.eat('(');
+        let inner = self.logical_or()?;
+        self.skip_blank();
+        if !self.eat(')') {
+            return Err(INVALID_FILTER);
+        }
+        Ok(inner)
+    }
+
+    fn cmp_op(&mut self) -> Option<CmpOp> {
+        let (op, len) = match (self.peek()?, self.chars.get(self.pos + 1)) {
+            ('=', Some('=')) => (CmpOp::Eq, 2),
+            ('!', Some('=')) => (CmpOp::Ne, 2),
+            ('<', Some('=')) => (CmpOp::Le, 2),
+            ('>', Some('=')) => (CmpOp::Ge, 2),
+            ('<', _) => (CmpOp::Lt, 1),
+            ('>', _) => (CmpOp::Gt, 1),
+            _ => return None,
+        };
+        self.pos += len;
+        Some(op)
+    }
+
+    // relative query (`@.a.b`) or literal
+    fn comparable(&mut self) -> Result<Operand, &'static str> {
+        match self.peek() {
+            Some('@') => {
+                self.pos += 1;
+                Ok(Operand::Path(self.segments(true)?))
+            }
+            Some('$') => Err(UNSUPPORTED_ROOT_QUERY),
+            Some('\'' | '"') => Ok(Operand::Literal(self.string_literal().map_err(|_| INVALID_FILTER)?.into())),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) if c.is_ascii_lowercase() => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    self.pos += 1;
+                }
+                let word: String = self.chars[start..self.pos].iter().collect();
+                if self.peek() == Some('(') {
+                    if FUNCTIONS.contains(&word.as_str()) {
+                        return Err(UNSUPPORTED_FUNCTIONS);
+                    }
+                    return Err(INVALID_FILTER);
+                }
+                match word.as_str() {
+                    "true" => Ok(Operand::Literal(true.into())),
+                    "false" => Ok(Operand::Literal(false.into())),
+                    "null" => Ok(Operand::Literal(serde_json::Value::Null)),
+                    _ => Err(INVALID_FILTER),
+                }
+            }
+            _ => Err(INVALID_FILTER),
+        }
+    }
+
+    // JSON number, `-0` included
+    fn number(&mut self) -> Result<Operand, &'static str> {
+        let start = self.pos;
+        self.eat('-');
+        let digits = |parser: &mut Parser| {
+            let start = parser.pos;
+            while parser.peek().is_some_and(|c| c.is_ascii_digit()) {
+                parser.pos += 1;
+            }
+            parser.pos - start
+        };
+        let int_start = self.pos;
+        let int_len = digits(self);
+        if int_len == 0 || (int_len > 1 && self.chars[int_start] == '0') {
+            return Err(INVALID_FILTER);
+        }
+        if self.eat('.') && digits(self) == 0 {
+            return Err(INVALID_FILTER);
+        }
+        if self.eat('e') || self.eat('E') {
+            if !self.eat('+') {
+                self.eat('-');
+            }
+            if digits(self) == 0 {
+                return Err(INVALID_FILTER);
+            }
+        }
+        let raw: String = self.chars[start..self.pos].iter().collect();
+        serde_json::from_str(&raw).map(Operand::Literal).map_err(|_| INVALID_FILTER)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PathSegment::*;
+
+    fn parse(search_key: &str) -> Vec<PathSegment> {
+        JsonPath::parse(search_key).unwrap().segments
+    }
+
+    fn key(k: &str) -> PathSegment {
+        Key(k.to_string())
+    }
+
+    fn name(k: &str) -> PathSegment {
+        Name(k.to_string())
+    }
+
+    #[test]
+    fn dotted_path_test() {
+        assert_eq!(parse("myroot"), vec![key("myroot")]);
+        assert_eq!(parse("myroot.child1"), vec![key("myroot"), key("child1")]);
+        assert_eq!(parse("myroot.child1.grandchild1"), vec![key("myroot"), key("child1"), key("grandchild1")]);
+        assert_eq!(parse("myroot.child1[0]"), vec![key("myroot"), key("child1"), Index(0)]);
+        assert_eq!(parse("myroot.child1[0].arr1"), vec![key("myroot"), key("child1"), Index(0), key("arr1")]);
+        assert_eq!(parse("[2].child1[0].arr1"), vec![Index(2), key("child1"), Index(0), key("arr1")]);
+        assert_eq!(parse("[1][1][1].b"), vec![Index(1), Index(1), Index(1), key("b")]);
+        assert_eq!(
+            parse("x.y[1][1][1].b[1222][439834]"),
+            vec![key("x"), key("y"), Index(1), Index(1), Index(1), key("b"), Index(1222), Index(439834)]
+        );
+        assert_eq!(parse("[*].bar.baz"), vec![Wildcard, key("bar"), key("baz")]);
+        assert_eq!(parse("a.*.c"), vec![key("a"), Wildcard, key("c")]);
+        assert_eq!(parse("[-1]"), vec![Index(-1)]);
+        assert_eq!(parse("[10:20]"), vec![Slice(Some(10), Some(20), 1)]);
+        assert_eq!(parse("[::5]"), vec![Slice(None, None, 5)]);
+        assert_eq!(parse("[-3:]"), vec![Slice(Some(-3), None, 1)]);
+        assert_eq!(parse("[:-1:-2]"), vec![Slice(None, Some(-1), -2)]);
+        assert_eq!(parse("[0,2]"), vec![Union(vec![Index(0), Index(2)])]);
+        assert_eq!(parse("..token"), vec![Descendant(Box::new(key("token")))]);
+        assert_eq!(parse("..book[-1].title"), vec![Descendant(Box::new(key("book"))), Index(-1), key("title")]);
+        assert_eq!(parse("store..title"), vec![key("store"), Descendant(Box::new(key("title")))]);
+        assert_eq!(
+            parse("a..['b.c'].d..[0]"),
+            vec![key("a"), Descendant(Box::new(key("b.c"))), key("d"), Descendant(Box::new(Index(0)))]
+        );
+        assert!(JsonPath::parse("a[b]").is_err());
+        assert!(JsonPath::parse("a]").is_err());
+    }
+
+    #[test]
+    fn quoted_key_test() {
+        assert_eq!(parse("metadata.labels['k8s.io/name']"), vec![key("metadata"), key("labels"), key("k8s.io/name")]);
+        assert_eq!(parse(r#"["a[b]"].c["x \"y\" z"]"#), vec![key("a[b]"), key("c"), key(r#"x "y" z"#)]);
+        assert_eq!(parse(r#"['it\'s']"#), vec![key("it's")]);
+        assert_eq!(parse(r#"["caf\u00e9"]"#), vec![key("café")]);
+        assert_eq!(parse(r#"["\ud83d\ude00"]"#), vec![key("😀")]);
+        assert_eq!(parse("['*']"), vec![key("*")]);
+    }
+
+    #[test]
+    fn json_pointer_test() {
+        assert_eq!(parse("/items/3/name"), vec![key("items"), key("3"), key("name")]);
+        assert_eq!(parse("/a~1b/m~0n"), vec![key("a/b"), key("m~n")]);
+        assert_eq!(parse("/metadata/labels/k8s.io~1name"), vec![key("metadata"), key("labels"), key("k8s.io/name")]);
+        assert_eq!(parse("/*/it's"), vec![key("*"), key("it's")]);
+        assert_eq!(parse("/"), vec![key("")]);
+    }
+
+    #[test]
+    fn filter_test() {
+        let segments = parse(r#"items[?(@.foo > 40 && @['a]'][0] == "x]")].name"#);
+        assert_eq!(segments.len(), 3);
+        assert!(matches!(segments[1], Filter(_)));
+        assert!(!JsonPath::parse("items[?@.foo]").unwrap().is_definite());
+        assert!(JsonPath::parse("items[?(@.foo >)]").is_err());
+        assert!(JsonPath::parse("items[?(@.foo == 'x)]").is_err());
+        assert!(JsonPath::parse("items[?(@.foo]").is_err());
+        assert!(JsonPath::parse("items[?42]").is_err());
+        assert!(JsonPath::parse("$[?@.* == 1]").is_err());
+        assert_eq!(JsonPath::parse("$[?length(@) > 1]"), Err(UNSUPPORTED_FUNCTIONS));
+        assert_eq!(JsonPath::parse("$[?@.a == $.b]"), Err(UNSUPPORTED_ROOT_QUERY));
+    }
+
+    #[test]
+    fn rfc9535_test() {
+        assert_eq!(parse("$"), vec![]);
+        assert_eq!(parse("$.a['b'][0]"), vec![name("a"), name("b"), Index(0)]);
+        assert_eq!(parse("$ .a [ 'b' , 1 ]"), vec![name("a"), Union(vec![name("b"), Index(1)])]);
+        assert_eq!(parse("$..*"), vec![Descendant(Box::new(Wildcard))]);
+        assert_eq!(parse("$[1 : 5 : 2]"), vec![Slice(Some(1), Some(5), 2)]);
+        for invalid in ["$.", "$.1a", "$[01]", "$[-0]", "$[9007199254740992]", "$['\\x']", "$[\"'\\'\"]", "$.a ", "$a", "$[]"] {
+            assert!(JsonPath::parse(invalid).is_err(), "{}", invalid);
+        }
+        assert!(JsonPath::parse("$.a").unwrap().rfc);
+        assert!(!JsonPath::parse("$.a").unwrap().is_definite());
+        assert!(JsonPath::parse("a.b[1]").unwrap().is_definite());
+        assert!(!JsonPath::parse("a[*].b").unwrap().is_definite());
+        assert!(!JsonPath::parse("a[1:3]").unwrap().is_definite());
+        assert!(!JsonPath::parse("a..b").unwrap().is_definite());
+        assert!(JsonPath::parse("a..b").unwrap().with_paths());
+    }
+}
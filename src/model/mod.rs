@@ -1,2 +1,5 @@
 pub mod stream_tracker;
-pub mod j_struct_tracker;
\ No newline at end of file
+pub mod j_struct_tracker;
+pub mod path_segment;
+pub mod filter;
+pub mod json_path;
\ No newline at end of file
@@ -0,0 +1,267 @@
+use crate::model::filter::Predicate;
+use crate::utils::{numeric_key, push_index, push_key};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PathSegment {
+    Key(String),
+    // RFC 9535 name selector, unlike a key it never addresses array elements
+    Name(String),
+    // negative indices count back from the end of the array
+    Index(i64),
+    // `[*]` or `*` - every member of an array or object
+    Wildcard,
+    // python style `[start:end:step]`
+    Slice(Option<i64>, Option<i64>, i64),
+    // `..name` - the inner segment applied at any depth
+    Descendant(Box<PathSegment>),
+    // `[?(@.price > 10)]` - members for which the predicate holds
+    Filter(Predicate),
+    // `[0, 'a', ?@.b]` - several selectors, results are concatenated in selector order
+    Union(Vec<PathSegment>),
+}
+impl PathSegment {
+    // member is either an object key or an array index, depending on the enclosing container.
+    // Numeric keys also address array elements, like they do in javascript and JSON pointers.
+    // Filters match every member here, the value itself is checked with `test`.
+    pub fn matches(&self, key: Option<&str>, idx: Option<i64>) -> bool {
+        match self {
+            PathSegment::Key(k) => match key {
+                Some(key) => key == k,
+                None => idx.is_some_and(|idx| numeric_key(k) == Some(idx)),
+            },
+            PathSegment::Name(k) => key == Some(k.as_str()),
+            PathSegment::Index(i) => idx.is_some_and(|idx| idx == *i),
+            PathSegment::Wildcard | PathSegment::Filter(_) => true,
+            PathSegment::Slice(start, end, step) => idx.is_some_and(|idx| {
+                let start = start.unwrap_or(0);
+                *step > 0 && idx >= start && end.is_none_or(|end| idx < end) && (idx - start) % step == 0
+            }),
+            PathSegment::Descendant(inner) => inner.matches(key, idx),
+            PathSegment::Union(selectors) => selectors.iter().any(|x| x.matches(key, idx)),
+        }
+    }
+
+    // selects at most one member
+    pub fn is_singular(&self) -> bool {
+        matches!(self, PathSegment::Key(_) | PathSegment::Name(_) | PathSegment::Index(_))
+    }
+
+    // whether a matched member value passes the segment's filter, if it has one
+    pub fn test(&self, value: &Value) -> bool {
+        match self {
+            PathSegment::Filter(predicate) => predicate.test(value),
+            PathSegment::Descendant(inner) => inner.test(value),
+            _ => true,
+        }
+    }
+
+    // filters can only be decided once the member value has been read
+    pub fn is_filter(&self) -> bool {
+        match self {
+            PathSegment::Filter(_) => true,
+            PathSegment::Descendant(inner) => inner.is_filter(),
+            _ => false,
+        }
+    }
+
+    // Segments that can only be resolved once the array length is known. Returns how many trailing
+    // elements have to be held back (None holds back every element).
+    pub fn tail_window(&self) -> Option<Option<usize>> {
+        match self {
+            PathSegment::Index(i) if *i < 0 => Some(Some(i.unsigned_abs() as usize)),
+            PathSegment::Slice(_, _, step) if *step < 0 => Some(None),
+            PathSegment::Slice(start, end, _) => {
+                let limit = [*start, *end]
+                    .iter()
+                    .flatten()
+                    .filter(|bound| **bound < 0)
+                    .map(|bound| bound.unsigned_abs() as usize)
+                    .max();
+                limit.map(Some)
+            }
+            PathSegment::Descendant(inner) => inner.tail_window(),
+            _ => None,
+        }
+    }
+
+    // whether an element pushed out of a full tail window is selected - it is known to be
+    // further than the window size from the end of the array
+    pub fn selects_evicted(&self, idx: i64) -> bool {
+        match self {
+            PathSegment::Slice(start, end, step) if *step > 0 => {
+                let start = start.unwrap_or(0);
+                start >= 0 && idx >= start && end.is_none_or(|end| end < 0 || idx < end) && (idx - start) % step == 0
+            }
+            PathSegment::Descendant(inner) => inner.selects_evicted(idx),
+            _ => false,
+        }
+    }
+
+    // indices selected from an array of length `len`, in selection order
+    pub fn indices(&self, len: usize) -> Vec<usize> {
+        let len = len as i64;
+        match self {
+            PathSegment::Index(i) => {
+                let idx = if *i < 0 { len + i } else { *i };
+                if idx >= 0 && idx < len {
+                    return vec![idx as usize];
+                }
+                Vec::new()
+            }
+            PathSegment::Wildcard | PathSegment::Filter(_) => (0..len as usize).collect(),
+            PathSegment::Slice(start, end, step) => {
+                let bound = |b: i64, lower: i64, upper: i64| {
+                    let b = if b < 0 { b + len } else { b };
+                    b.clamp(lower, upper)
+                };
+                let mut indices = Vec::new();
+                if *step > 0 {
+                    let mut i = start.map_or(0, |s| bound(s, 0, len));
+                    let stop = end.map_or(len, |e| bound(e, 0, len));
+                    while i < stop {
+                        indices.push(i as usize);
+                        i += step;
+                    }
+                } else if *step < 0 {
+                    let mut i = start.map_or(len - 1, |s| bound(s, -1, len - 1));
+                    let stop = end.map_or(-1, |e| bound(e, -1, len - 1));
+                    while i > stop {
+                        indices.push(i as usize);
+                        i += step;
+                    }
+                }
+                indices
+            }
+            PathSegment::Key(k) => match numeric_key(k) {
+                Some(idx) if idx < len => vec![idx as usize],
+                _ => Vec::new(),
+            },
+            PathSegment::Descendant(inner) => inner.indices(len as usize),
+            PathSegment::Union(selectors) => selectors.iter().flat_map(|x| x.indices(len as usize)).collect(),
+            PathSegment::Name(_) => Vec::new(),
+        }
+    }
+}
+
+// Evaluate a search path against a value that is already in memory, collecting the concrete
+// path of every match (relative to `path`) along with the value
+pub(crate) fn select<'a>(value: &'a Value, search_path: &[PathSegment], path: &str, out: &mut Vec<(String, &'a Value)>) {
+    let Some((segment, rest)) = search_path.split_first() else {
+        out.push((path.to_string(), value));
+        return;
+    };
+    let mut selected = Vec::new();
+    match segment {
+        // RFC 9535 order: the members selected from this value, then the same for every
+        // descendant in document order
+        PathSegment::Descendant(inner) => {
+            children(value, inner, path, &mut selected);
+            for (child_path, child) in selected {
+                select(child, rest, &child_path, out);
+            }
+            let mut members = Vec::new();
+            children(value, &PathSegment::Wildcard, path, &mut members);
+            for (child_path, child) in members {
+                select(child, search_path, &child_path, out);
+            }
+        }
+        _ => {
+            children(value, segment, path, &mut selected);
+            for (child_path, child) in selected {
+                select(child, rest, &child_path, out);
+            }
+        }
+    }
+}
+
+// members of a value selected by a single segment, in selection order
+fn children<'a>(value: &'a Value, segment: &PathSegment, path: &str, out: &mut Vec<(String, &'a Value)>) {
+    match (segment, value) {
+        (PathSegment::Union(selectors), _) => {
+            for selector in selectors {
+                children(value, selector, path, out);
+            }
+        }
+        (PathSegment::Key(k) | PathSegment::Name(k), Value::Object(map)) => {
+            if let Some(child) = map.get(k) {
+                out.push((push_key(path, k), child));
+            }
+        }
+        (PathSegment::Wildcard | PathSegment::Filter(_), Value::Object(map)) => {
+            for (key, child) in map {
+                if segment.test(child) {
+                    out.push((push_key(path, key), child));
+                }
+            }
+        }
+        (_, Value::Array(arr)) => {
+            for idx in segment.indices(arr.len()) {
+                if segment.test(&arr[idx]) {
+                    out.push((push_index(path, idx), &arr[idx]));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::json_path::JsonPath;
+
+    fn parse(segment: &str) -> PathSegment {
+        JsonPath::parse(segment).unwrap().segments.remove(0)
+    }
+
+    #[test]
+    fn indices_test() {
+        assert_eq!(parse("[-1]").indices(5), vec![4]);
+        assert_eq!(parse("[-6]").indices(5), Vec::<usize>::new());
+        assert_eq!(parse("[1:3]").indices(5), vec![1, 2]);
+        assert_eq!(parse("[::2]").indices(5), vec![0, 2, 4]);
+        assert_eq!(parse("[-2:]").indices(5), vec![3, 4]);
+        assert_eq!(parse("[:-3]").indices(5), vec![0, 1]);
+        assert_eq!(parse("[::-1]").indices(3), vec![2, 1, 0]);
+        assert_eq!(parse("[3:0:-2]").indices(5), vec![3, 1]);
+        assert_eq!(parse("[::0]").indices(5), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn tail_window_test() {
+        assert_eq!(parse("[2]").tail_window(), None);
+        assert_eq!(parse("[1:4]").tail_window(), None);
+        assert_eq!(parse("[-3]").tail_window(), Some(Some(3)));
+        assert_eq!(parse("[-2:-5]").tail_window(), Some(Some(5)));
+        assert_eq!(parse("[::-1]").tail_window(), Some(None));
+        assert!(parse("[2:-1]").selects_evicted(4));
+        assert!(!parse("[2:-1]").selects_evicted(1));
+        assert!(!parse("[-2:]").selects_evicted(4));
+        assert_eq!(parse("..[-2]").tail_window(), Some(Some(2)));
+    }
+
+    #[test]
+    fn descendant_select_test() {
+        let value: Value = serde_json::from_str(r#"{"x": {"a": 1}, "a": {"a": 2}, "y": [{"a": 3}]}"#).unwrap();
+        let mut out = Vec::new();
+        select(&value, &[parse("..a")], "", &mut out);
+        assert_eq!(
+            out.iter().map(|(p, v)| format!("{}={}", p, v)).collect::<Vec<String>>(),
+            vec![r#"a={"a":2}"#, "x.a=1", "a.a=2", "y[0].a=3"]
+        );
+    }
+
+    #[test]
+    fn filter_select_test() {
+        let value: Value = serde_json::from_str(r#"{"items": [{"p": 5}, {"p": 50}, {"q": 1}], "m": {"x": {"p": 60}}}"#).unwrap();
+        let mut out = Vec::new();
+        select(&value, &[parse("items"), parse("[?(@.p > 10)]")], "", &mut out);
+        select(&value, &[parse("m"), parse("[?@.p]"), parse("p")], "", &mut out);
+        assert_eq!(
+            out.iter().map(|(p, v)| format!("{}={}", p, v)).collect::<Vec<String>>(),
+            vec![r#"items[1]={"p":50}"#, "m.x.p=60"]
+        );
+        assert!(parse("..[?(@.p)]").is_filter());
+    }
+}
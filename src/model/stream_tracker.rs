@@ -2,7 +2,9 @@ pub struct StreamTracker {
     pub last_stream_pos: u64,
     pub last_chunk_len: usize,
     pub buffer: Vec<u8>,
-    pub chunk: Vec<u8>,
+    // input still needed for values being matched, when it can't be read back, starting at stream position `retained_pos`
+    pub retained: Vec<u8>,
+    pub retained_pos: u64,
 }
 impl StreamTracker {
     pub fn new(chunk_size: usize) -> StreamTracker {
@@ -10,7 +12,15 @@ impl StreamTracker {
             last_stream_pos: 0,
             last_chunk_len: 0,
             buffer: Vec::with_capacity(chunk_size * 2), // Extra space for overflow,
-            chunk: Vec::new(),
+            retained: Vec::new(),
+            retained_pos: 0,
         }
     }
-}
\ No newline at end of file
+
+    // drop retained input before stream position `pos`
+    pub fn release(&mut self, pos: u64) {
+        let count = (pos.saturating_sub(self.retained_pos) as usize).min(self.retained.len());
+        self.retained.drain(..count);
+        self.retained_pos += count as u64;
+    }
+}
@@ -0,0 +1,310 @@
+use crate::model::path_segment::PathSegment;
+use crate::windows::Window;
+use crate::structural::{closing_quote, last_element, Scan, STRUCTURAL};
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+// `work` done for 0..count by `threads` workers, each taking the next item once it's done with one.
+// The results are in item order.
+pub(crate) fn map_parallel<T, F>(count: usize, threads: usize, work: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, T)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(count).max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= count {
+                            return done;
+                        }
+                        done.push((i, work(i)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+            .collect()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+// The elements of the array at `prefix`, a path of object keys, split into windows that start in
+// successive ranges of about `range_size` bytes. The ranges are scanned by `threads` workers in two
+// passes: the first works out what each range does to the string state and depth for both states
+// it can start in, the second, with the actual state at its start stitched together from the ranges
+// before it, lists what happens at the depth of the array and above. None when there is no such
+// array, or the input isn't a single well-formed value.
+pub(crate) fn array_windows(bytes: &[u8], prefix: &[PathSegment], threads: usize, range_size: usize) -> Option<Vec<Window>> {
+    let ranges = ranges(bytes, range_size);
+    let scans = map_parallel(ranges.len(), threads, |i| speculate(&bytes[ranges[i].0..ranges[i].1]));
+
+    let mut starts = Vec::with_capacity(ranges.len());
+    let (mut in_string, mut depth) = (false, 0);
+    for scan in scans {
+        starts.push((in_string, depth));
+        depth += scan.delta[in_string as usize];
+        in_string ^= scan.quotes_odd;
+        if depth < 0 {
+            return None;
+        }
+    }
+    if in_string || depth != 0 {
+        return None;
+    }
+
+    let target = prefix.len() as i64;
+    let events = map_parallel(ranges.len(), threads, |i| {
+        let (start, end) = ranges[i];
+        shallow_events(bytes, start, end, starts[i], target)
+    });
+    walk(bytes, prefix, events.into_iter().collect::<Option<Vec<_>>>()?)
+}
+
+// `bytes` cut into ranges of about `size` bytes. A range never ends on a backslash, so the first
+// byte of the next one isn't escaped.
+fn ranges(bytes: &[u8], size: usize) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let mut end = (start + size.max(1)).min(bytes.len());
+        while end < bytes.len() && bytes[end - 1] == b'\\' {
+            end += 1;
+        }
+        ranges.push((start, end));
+        start = end;
+    }
+    ranges
+}
+
+// What a range does to the string state and depth, for both states it can start in
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Speculative {
+    // an odd number of quotes flips the string state
+    quotes_odd: bool,
+    // change of depth when the range starts outside a string, and inside one
+    delta: [i64; 2],
+}
+
+fn speculate(bytes: &[u8]) -> Speculative {
+    let mut scan = Speculative::default();
+    let mut i = 0;
+    while i < bytes.len() {
+        if !STRUCTURAL[bytes[i] as usize] {
+            i += 1;
+            continue;
+        }
+        match bytes[i] {
+            // backslashes only occur in strings, they escape the next byte whatever the start state
+            b'\\' => i += 1,
+            b'"' => scan.quotes_odd = !scan.quotes_odd,
+            // brackets count for the start state that has them outside a string
+            b'[' | b'{' => scan.delta[scan.quotes_odd as usize] += 1,
+            b']' | b'}' => scan.delta[scan.quotes_odd as usize] -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    scan
+}
+
+// Structure at a depth of at most `target` + 1, as seen from inside the containers open there
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Event {
+    Open { pos: usize, array: bool },
+    Close { pos: usize },
+    Colon,
+    // a string starting at `pos`, an object key if a colon follows
+    Str { pos: usize },
+    // `count` commas one level below `target`, the first at `first` and the last at `last`
+    Commas { first: usize, last: usize, count: u64 },
+}
+
+// the events of the range [start, end) starting in the string state and at the depth of `state`
+fn shallow_events(bytes: &[u8], start: usize, end: usize, state: (bool, i64), target: i64) -> Option<Vec<Event>> {
+    let (in_string, depth) = state;
+    let bytes = &bytes[..end];
+    let mut events = Vec::new();
+    let mut start = start;
+    if in_string {
+        match closing_quote(bytes, start) {
+            Some(at) => start = at + 1,
+            None => return Some(events),
+        }
+    }
+    let mut scan = Scan::new(bytes, start, depth, |_| {});
+    // a string that goes on into the next range ends the scan after its opening quote
+    while let Some(Ok((i, byte))) = scan.next() {
+        let depth = scan.depth;
+        match byte {
+            b'"' if depth >= 1 && depth <= target => events.push(Event::Str { pos: i }),
+            b'[' | b'{' if depth <= target + 1 => events.push(Event::Open { pos: i, array: byte == b'[' }),
+            b']' | b'}' if depth < 0 => return None,
+            b']' | b'}' if depth <= target => events.push(Event::Close { pos: i }),
+            b',' if depth == target + 1 => match events.last_mut() {
+                Some(Event::Commas { last, count, .. }) => {
+                    *last = i;
+                    *count += 1;
+                }
+                _ => events.push(Event::Commas { first: i, last: i, count: 1 }),
+            },
+            b':' if depth >= 1 && depth <= target => events.push(Event::Colon),
+            _ => {}
+        }
+    }
+    Some(events)
+}
+
+// a container open while walking the events
+struct Frame {
+    array: bool,
+    // reached through the keys of the prefix
+    on_path: bool,
+    last_str: Option<usize>,
+    // the string before the last colon
+    key: Option<usize>,
+}
+
+// The array being split and its current window
+struct Target {
+    window: Window,
+    // the separator after the last element so far
+    last_sep: usize,
+}
+
+// Follows the keys of `prefix` through the events of every range to the array and splits it, a new
+// window starting at its first separator in each range
+fn walk(bytes: &[u8], prefix: &[PathSegment], events: Vec<Vec<Event>>) -> Option<Vec<Window>> {
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut roots = 0;
+    let mut target: Option<Target> = None;
+    let mut windows: Option<Vec<Window>> = None;
+    for events in events {
+        let mut split = true;
+        for event in events {
+            match event {
+                Event::Open { pos, array } => {
+                    let on_path = match stack.last() {
+                        None => {
+                            roots += 1;
+                            if roots > 1 {
+                                return None;
+                            }
+                            true
+                        }
+                        // the keys lead into an array
+                        Some(parent) if parent.on_path && parent.array => return None,
+                        Some(parent) => parent.on_path && parent.key.is_some_and(|key| key_matches(bytes, key, &prefix[stack.len() - 1])),
+                    };
+                    if on_path && stack.len() == prefix.len() {
+                        if !array || target.is_some() || windows.is_some() {
+                            return None;
+                        }
+                        target = Some(Target { window: Window { start: pos as u64, end: 0, first: 0, count: 0 }, last_sep: pos });
+                    }
+                    stack.push(Frame { array, on_path, last_str: None, key: None });
+                }
+                Event::Close { pos } => {
+                    let frame = stack.pop()?;
+                    if frame.on_path && stack.len() == prefix.len() {
+                        let mut target = target.take()?;
+                        let mut done = std::mem::take(&mut windows).unwrap_or_default();
+                        if last_element(bytes, target.last_sep, pos) {
+                            target.window.count += 1;
+                        }
+                        if target.window.count > 0 {
+                            target.window.end = pos as u64;
+                            done.push(target.window);
+                        }
+                        windows = Some(done);
+                    }
+                }
+                Event::Str { pos } => stack.last_mut()?.last_str = Some(pos),
+                Event::Colon => {
+                    let frame = stack.last_mut()?;
+                    frame.key = frame.last_str;
+                }
+                Event::Commas { first, last, count } => {
+                    let Some(target) = target.as_mut().filter(|_| stack.len() == prefix.len() + 1) else {
+                        continue;
+                    };
+                    let window = &mut target.window;
+                    if split && window.count > 0 {
+                        // the element ended by the first comma is the last of the window
+                        window.count += 1;
+                        window.end = first as u64;
+                        windows.get_or_insert_with(Vec::new).push(*window);
+                        *window = Window { start: first as u64, end: 0, first: window.first + window.count, count: count - 1 };
+                    } else {
+                        window.count += count;
+                    }
+                    target.last_sep = last;
+                    split = false;
+                }
+            }
+        }
+    }
+    if !stack.is_empty() || target.is_some() {
+        return None;
+    }
+    windows
+}
+
+// whether the string at `pos` is the key of `segment`
+fn key_matches(bytes: &[u8], pos: usize, segment: &PathSegment) -> bool {
+    let Some(end) = closing_quote(bytes, pos + 1) else {
+        return false;
+    };
+    match (serde_json::from_slice::<String>(&bytes[pos..=end]), segment) {
+        (Ok(key), PathSegment::Key(k) | PathSegment::Name(k)) => key == *k,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::windows;
+
+    #[test]
+    fn speculate_test() {
+        // out of `{"k": "v[", "a": [1, {"b": "}"}]}`
+        let scan = speculate(br#": "v[", "a": [1, {"b"#);
+        assert_eq!(scan, Speculative { quotes_odd: true, delta: [2, 1] });
+        assert_eq!(ranges(br#"ab\\cd\"e"#, 3), vec![(0, 5), (5, 8), (8, 9)]);
+    }
+
+    #[test]
+    fn array_windows_test() {
+        let sample = br#" [1, "a,]\"", {"b": [2, 3]} , [4],5 ] "#;
+        let all = windows::array_windows(sample, usize::MAX, |_| {}).unwrap();
+        for size in 1..sample.len() + 1 {
+            let split = array_windows(sample, &[], 3, size).unwrap();
+            assert_eq!(split.first().map(|w| (w.start, w.first)), Some((all[0].start, 0)));
+            assert_eq!(split.last().map(|w| (w.end, w.first + w.count)), Some((all[0].end, 5)));
+            assert!(split.windows(2).all(|w| w[0].end == w[1].start && w[0].first + w[0].count == w[1].first));
+        }
+        assert_eq!(array_windows(sample, &[], 2, 10).unwrap().len(), 3);
+
+        let nested = br#"{"a": [1], "b\"": {"c": "[x", "items": [{"d": [5, 6]}, 7, "y,"]}}"#;
+        let prefix = [PathSegment::Key("b\"".to_string()), PathSegment::Key("items".to_string())];
+        for size in 1..nested.len() + 1 {
+            let split = array_windows(nested, &prefix, 4, size).unwrap();
+            assert_eq!(split.iter().map(|w| w.count).sum::<u64>(), 3);
+            assert_eq!(split.first().map(|w| w.start), Some(39));
+        }
+        assert_eq!(array_windows(nested, &prefix[..1], 2, 8), None);
+        assert_eq!(array_windows(nested, &[PathSegment::Key("x".to_string())], 2, 8), None);
+        assert_eq!(array_windows(b"[1, [2]", &[], 2, 3), None);
+        assert_eq!(array_windows(b"[1] [2]", &[], 2, 3), None);
+        assert_eq!(array_windows(b" [ ] ", &[], 2, 2), Some(vec![]));
+    }
+}
@@ -0,0 +1,181 @@
+use crate::stats::is_enum;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+// How often each key path occurs in schema tapes, e.g. `items[*].id`, compared to the number of
+// objects it could have occurred in. Tapes of whole documents count every array element, NDJSON
+// record schemas only have the distinct elements of each array, so below arrays those count once
+// per shape and record.
+#[derive(Debug, Default)]
+pub(crate) struct Presence {
+    paths: BTreeMap<String, Count>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Count {
+    // objects found at the path
+    objects: u64,
+    // times the path was a key, and a key with a null value
+    present: u64,
+    nulls: u64,
+    // length of the path of the object holding the key
+    parent: usize,
+}
+
+impl Presence {
+    // presence in the raw schema tape of a document
+    pub fn document(tape: &Value) -> Presence {
+        let mut presence = Presence::default();
+        presence.add(tape, &mut String::new(), 1);
+        presence
+    }
+
+    // presence in the distinct schemas of NDJSON records and their number of records
+    pub fn records(schemas: &[(Value, u64)]) -> Presence {
+        let mut presence = Presence::default();
+        for (schema, records) in schemas {
+            presence.add(schema, &mut String::new(), *records);
+        }
+        presence
+    }
+
+    fn add(&mut self, value: &Value, path: &mut String, weight: u64) {
+        match value {
+            Value::Object(members) => {
+                self.count(path, 0).objects += weight;
+                let parent = path.len();
+                for (key, member) in members {
+                    if parent > 0 {
+                        path.push('.');
+                    }
+                    path.push_str(key);
+                    let count = self.count(path, parent);
+                    count.present += weight;
+                    if member == "null" {
+                        count.nulls += weight;
+                    }
+                    self.add(member, path, weight);
+                    path.truncate(parent);
+                }
+            }
+            Value::Array(elements) => {
+                let parent = path.len();
+                path.push_str("[*]");
+                for element in elements {
+                    self.add(element, path, weight);
+                }
+                path.truncate(parent);
+            }
+            _ => {}
+        }
+    }
+
+    fn count(&mut self, path: &str, parent: usize) -> &mut Count {
+        if !self.paths.contains_key(path) {
+            self.paths.insert(path.to_string(), Count { parent, ..Count::default() });
+        }
+        self.paths.get_mut(path).unwrap()
+    }
+
+    // Marks the keys of a merged object schema at `path` and of the objects merged into it: keys
+    // some objects don't have get a `?` suffix, type names of keys that are sometimes null a `|null` one
+    // and enums of them a null value.
+    // Arrays hold distinct element schemas and only have their keys written by `schema_key`.
+    pub fn mark(&self, schema: Value, path: &str) -> Value {
+        let Value::Object(members) = schema else {
+            return escape_keys(schema);
+        };
+        let objects = self.paths.get(path).map_or(0, |count| count.objects);
+        let marked = members.into_iter().map(|(key, member)| {
+            let key_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+            let count = self.paths.get(&key_path).copied().unwrap_or_default();
+            let member = match member {
+                Value::String(kind) if count.nulls > 0 && kind != "null" => Value::String(format!("{}|null", kind)),
+                Value::Object(mut values) if is_enum(&values) => {
+                    if let Some(Value::Array(values)) = values.get_mut("enum").filter(|_| count.nulls > 0) {
+                        values.push(Value::Null);
+                    }
+                    Value::Object(values)
+                }
+                member => self.mark(member, &key_path),
+            };
+            (schema_key(&key, count.present < objects), member)
+        });
+        Value::Object(marked.collect())
+    }
+
+    // every key path with whether all objects have it, whether it's ever null and the share of
+    // objects that have it
+    pub fn report(&self) -> Value {
+        let mut fields = Map::new();
+        for (path, count) in self.paths.iter().filter(|(_, count)| count.present > 0) {
+            let objects = self.paths.get(&path[..count.parent]).map_or(count.present, |parent| parent.objects);
+            let presence = (count.present as f64 / objects as f64 * 10000.0).round() / 10000.0;
+            fields.insert(path.clone(), json!({"required": count.present == objects, "nullable": count.nulls > 0, "presence": presence}));
+        }
+        Value::Object(fields)
+    }
+}
+
+// A key as written in a jist schema, where a `?` suffix marks keys some objects don't have. The
+// trailing `?`s of the key itself are doubled, `ok?` is written `ok??`, or `ok???` when optional.
+pub(crate) fn schema_key(key: &str, optional: bool) -> String {
+    let marks = key.len() - key.trim_end_matches('?').len() + optional as usize;
+    format!("{}{}", key, "?".repeat(marks))
+}
+
+// the key and whether it's optional, from a key written by `schema_key`
+pub(crate) fn parse_key(written: &str) -> (&str, bool) {
+    let name = written.trim_end_matches('?').len();
+    let marks = written.len() - name;
+    (&written[..name + marks / 2], marks % 2 == 1)
+}
+
+// `schema` with the keys of its objects written by `schema_key`, none of them optional
+pub(crate) fn escape_keys(schema: Value) -> Value {
+    match schema {
+        Value::Object(members) if is_enum(&members) => Value::Object(members),
+        Value::Object(members) => Value::Object(members.into_iter().map(|(key, member)| (schema_key(&key, false), escape_keys(member))).collect()),
+        Value::Array(elements) => Value::Array(elements.into_iter().map(escape_keys).collect()),
+        schema => schema,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presence_test() {
+        let tape = json!([{"a": "number", "b": {"c": "null"}}, {"a": "null", "b": {"c": "string", "d": "boolean"}}, {"b": "null"}]);
+        let presence = Presence::document(&tape);
+        assert_eq!(
+            presence.report(),
+            json!({
+                "[*].a": {"required": false, "nullable": true, "presence": 0.6667},
+                "[*].b": {"required": true, "nullable": true, "presence": 1.0},
+                "[*].b.c": {"required": true, "nullable": true, "presence": 1.0},
+                "[*].b.d": {"required": false, "nullable": false, "presence": 0.5}
+            })
+        );
+        assert_eq!(
+            presence.mark(json!({"a": "number", "b": {"c": "string", "d": "boolean"}}), "[*]"),
+            json!({"a?": "number|null", "b": {"c": "string|null", "d?": "boolean"}})
+        );
+
+        let records = Presence::records(&[(json!({"id": "number", "tags": ["string"]}), 97), (json!({"id": "number"}), 3)]);
+        assert_eq!(records.report()["tags"]["presence"], json!(0.97));
+        assert_eq!(records.mark(json!({"id": "number", "tags": ["string"]}), ""), json!({"id": "number", "tags?": ["string"]}));
+    }
+
+    #[test]
+    fn schema_key_test() {
+        let tape = json!([{"ok?": "boolean", "a": [{"b?": "number"}]}, {"ok": "boolean", "a": []}]);
+        let marked = Presence::document(&tape).mark(json!({"ok?": "boolean", "ok": "boolean", "a": [{"b?": "number"}]}), "[*]");
+        assert_eq!(marked, json!({"ok???": "boolean", "ok?": "boolean", "a": [{"b??": "number"}]}));
+        for (key, optional) in [("ok?", true), ("ok", true), ("ok??", false), ("", false), ("?", true)] {
+            assert_eq!(parse_key(&schema_key(key, optional)), (key, optional));
+        }
+        assert_eq!(escape_keys(json!([{"x?": {"enum": ["a"]}}])), json!([{"x??": {"enum": ["a"]}}]));
+    }
+}
@@ -0,0 +1,280 @@
+use crate::error::JistError;
+use crate::formats::FORMATS;
+use crate::presence::parse_key;
+use crate::schema_parser::{self, deduplicate_arrays, sort_serde_json, SchemaOptions};
+use crate::stats::is_enum;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+
+// files up to this size are looked at as saved schemas before being summarized
+const SAVED_MAX: u64 = 16 << 20;
+
+// type names of jist schemas, besides the formats of strings
+const TYPES: [&str; 5] = ["string", "number", "integer", "boolean", "null"];
+
+// How a key path changed between two schemas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    // e.g. `[*].geo.lat`, `$` for the top-level value
+    pub path: String,
+    // types at the path, e.g. "string|null" or "optional number", None where it doesn't exist
+    pub from: Option<String>,
+    pub to: Option<String>,
+    // data of the old schema may not match the new one: the path is gone, has a new type or
+    // became optional
+    pub breaking: bool,
+}
+
+// Changes from one schema to another, by path. Below an added or removed path only that path is listed.
+#[derive(Debug, Default, PartialEq)]
+pub struct Diff {
+    pub changes: Vec<Change>,
+}
+
+// the types found at a key path and whether some objects lack the key
+#[derive(Debug, Default, PartialEq)]
+struct Node {
+    types: BTreeSet<String>,
+    optional: bool,
+}
+
+// The schema of `file`: a saved jist schema, also inside the {"schema": ...} of `-v` or `--stats`,
+// is taken as it is, anything else is summarized with the elements of a top-level array merged like
+// `jist -u` does. Either way the schema is normalized by `sort_serde_json` and `deduplicate_arrays`.
+pub fn load(file: &str) -> Result<Value, JistError> {
+    if fs::metadata(file)?.len() <= SAVED_MAX {
+        let saved = fs::read(file).ok().and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok()).and_then(saved);
+        if let Some(schema) = saved {
+            return Ok(sort_serde_json(&deduplicate_arrays(schema)));
+        }
+    }
+    let options = SchemaOptions { unionize: true, ..SchemaOptions::default() };
+    let schema = schema_parser::summarize_file_as(file, &options)?;
+    let schema = serde_json::from_str(&schema).map_err(|e| JistError::malformed(None, e))?;
+    Ok(sort_serde_json(&deduplicate_arrays(schema)))
+}
+
+// the schema in `value` if it is one
+pub(crate) fn saved(value: Value) -> Option<Value> {
+    let schema = match value {
+        Value::Object(mut members) if members.contains_key("schema") && members.keys().all(|key| matches!(key.as_str(), "schema" | "fields" | "stats")) => {
+            members.remove("schema")?
+        }
+        value => value,
+    };
+    is_schema(&schema).then_some(schema)
+}
+
+// whether every value is a type name, e.g. "string|null" or "date-time", or an enum of strings
+fn is_schema(value: &Value) -> bool {
+    match value {
+        Value::String(kind) => kind.split('|').all(|kind| TYPES.contains(&kind) || FORMATS.contains(&kind)),
+        Value::Object(members) => is_enum(members) || members.values().all(is_schema),
+        Value::Array(variants) => variants.iter().all(is_schema),
+        _ => false,
+    }
+}
+
+// what changed from schema `a` to schema `b`
+pub fn diff(a: &Value, b: &Value) -> Diff {
+    let (mut old, mut new) = (BTreeMap::new(), BTreeMap::new());
+    flatten(a, &mut String::new(), false, &mut old);
+    flatten(b, &mut String::new(), false, &mut new);
+    let paths: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    let mut changes: Vec<Change> = Vec::new();
+    for path in paths {
+        let change = match (old.get(path), new.get(path)) {
+            (Some(from), Some(to)) if from == to => continue,
+            (Some(from), Some(to)) => {
+                let widened = to.types.iter().any(|kind| !covers(&from.types, kind));
+                Change { kind: ChangeKind::Changed, path: path.clone(), from: Some(describe(from)), to: Some(describe(to)), breaking: widened || (to.optional && !from.optional) }
+            }
+            (Some(from), None) => Change { kind: ChangeKind::Removed, path: path.clone(), from: Some(describe(from)), to: None, breaking: true },
+            (None, Some(to)) => Change { kind: ChangeKind::Added, path: path.clone(), from: None, to: Some(describe(to)), breaking: false },
+            (None, None) => continue,
+        };
+        let below = |parent: &Change| parent.kind == change.kind && change.kind != ChangeKind::Changed && is_below(&change.path, &parent.path);
+        if !changes.iter().rev().any(below) {
+            changes.push(change);
+        }
+    }
+    for change in &mut changes {
+        if change.path.is_empty() {
+            change.path = "$".to_string();
+        }
+    }
+    Diff { changes }
+}
+
+// Types by key path, e.g. `[*].tags[*]`. Keys some of the objects of an array don't have, or that
+// are marked with a `?`, are optional.
+fn flatten(schema: &Value, path: &mut String, optional: bool, paths: &mut BTreeMap<String, Node>) {
+    let node = paths.entry(path.clone()).or_default();
+    node.optional |= optional;
+    match schema {
+        Value::String(kind) => node.types.extend(kind.split('|').map(String::from)),
+        Value::Object(members) if is_enum(members) => {
+            node.types.insert("string".to_string());
+            if members["enum"].as_array().is_some_and(|values| values.contains(&Value::Null)) {
+                node.types.insert("null".to_string());
+            }
+        }
+        Value::Object(members) => {
+            node.types.insert("object".to_string());
+            let base = path.len();
+            for (key, member) in members {
+                let (key, optional) = parse_key(key);
+                if base > 0 {
+                    path.push('.');
+                }
+                path.push_str(key);
+                flatten(member, path, optional, paths);
+                path.truncate(base);
+            }
+        }
+        Value::Array(variants) => {
+            node.types.insert("array".to_string());
+            let base = path.len();
+            path.push_str("[*]");
+            for variant in variants {
+                flatten(variant, path, false, paths);
+            }
+            // keys of object variants the others lack
+            let objects: Vec<&Map<String, Value>> = variants.iter().filter_map(Value::as_object).filter(|members| !is_enum(members)).collect();
+            let keys: BTreeSet<&str> = objects.iter().flat_map(|members| members.keys()).map(|key| parse_key(key).0).collect();
+            for key in keys {
+                if !objects.iter().all(|members| members.keys().any(|written| parse_key(written).0 == key)) {
+                    if let Some(node) = paths.get_mut(&format!("{}.{}", path, key)) {
+                        node.optional = true;
+                    }
+                }
+            }
+            path.truncate(base);
+        }
+        _ => {}
+    }
+}
+
+// whether values of type `kind` were allowed by `types`, integers are numbers and formats strings
+fn covers(types: &BTreeSet<String>, kind: &str) -> bool {
+    types.contains(kind) || (kind == "integer" && types.contains("number")) || (FORMATS.contains(&kind) && types.contains("string"))
+}
+
+fn is_below(path: &str, parent: &str) -> bool {
+    path.strip_prefix(parent).is_some_and(|rest| rest.starts_with('.') || rest.starts_with('['))
+}
+
+// e.g. "optional number|string|null", null last
+fn describe(node: &Node) -> String {
+    let mut types: Vec<&str> = node.types.iter().map(String::as_str).filter(|kind| *kind != "null").collect();
+    if node.types.contains("null") {
+        types.push("null");
+    }
+    let types = types.join("|");
+    if node.optional {
+        format!("optional {}", types)
+    } else {
+        types
+    }
+}
+
+impl Diff {
+    pub fn breaking(&self) -> bool {
+        self.changes.iter().any(|change| change.breaking)
+    }
+
+    // {"breaking": ..., "added": [...], "removed": [...], "changed": [...]}
+    pub fn report(&self) -> Value {
+        let of = |kind: ChangeKind| -> Vec<Value> {
+            let changes = self.changes.iter().filter(|change| change.kind == kind);
+            changes
+                .map(|change| match kind {
+                    ChangeKind::Added => json!({"path": change.path, "type": change.to}),
+                    ChangeKind::Removed => json!({"path": change.path, "type": change.from}),
+                    ChangeKind::Changed => json!({"path": change.path, "from": change.from, "to": change.to, "breaking": change.breaking}),
+                })
+                .collect()
+        };
+        json!({
+            "breaking": self.breaking(),
+            "added": of(ChangeKind::Added),
+            "removed": of(ChangeKind::Removed),
+            "changed": of(ChangeKind::Changed),
+        })
+    }
+}
+
+// one line per change, e.g. `~ [*].id: number -> string (breaking)`
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            let breaking = if change.breaking { " (breaking)" } else { "" };
+            match (&change.from, &change.to) {
+                (Some(from), Some(to)) => writeln!(f, "~ {}: {} -> {}{}", change.path, from, to, breaking)?,
+                (Some(from), None) => writeln!(f, "- {}: {}{}", change.path, from, breaking)?,
+                (None, Some(to)) => writeln!(f, "+ {}: {}{}", change.path, to, breaking)?,
+                (None, None) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_test() {
+        let a = json!([{"geo": {"lat": "number", "lon": "number"}, "id": "number", "note?": "string", "tags": ["string"]}]);
+        let b = json!([{"email": "email", "id": "number|string", "note": "string|null", "tags?": ["string"]}]);
+        let changes = diff(&a, &b);
+        assert_eq!(
+            changes.to_string(),
+            "+ [*].email: email\n\
+             - [*].geo: object (breaking)\n\
+             ~ [*].id: number -> number|string (breaking)\n\
+             ~ [*].note: optional string -> string|null (breaking)\n\
+             ~ [*].tags: array -> optional array (breaking)\n"
+        );
+        assert!(changes.breaking());
+        assert_eq!(changes.report()["removed"], json!([{"path": "[*].geo", "type": "object"}]));
+
+        // narrowing and new keys aren't breaking, variants of an array lacking a key make it optional
+        let a = json!([{"a": "number", "b": "string"}, {"a": "number"}]);
+        let b = json!([{"a": "integer", "b?": "date", "c": {"enum": ["x", null]}}]);
+        assert_eq!(diff(&a, &b).report(), json!({
+            "breaking": false,
+            "added": [{"path": "[*].c", "type": "string|null"}],
+            "removed": [],
+            "changed": [
+                {"path": "[*].a", "from": "number", "to": "integer", "breaking": false},
+                {"path": "[*].b", "from": "optional string", "to": "optional date", "breaking": false}
+            ]
+        }));
+        assert_eq!(diff(&json!({"a": "string"}), &json!(["string"])).to_string(), "~ $: object -> array (breaking)\n+ [*]: string\n- a: string (breaking)\n");
+
+        // a `?` ending a key is doubled in the schema, the key `ok?` isn't the optional key `ok`
+        let a = json!([{"ok??": "boolean"}, {"ok??": "boolean", "ok": "string"}]);
+        let b = json!([{"ok???": "boolean", "ok?": "string"}]);
+        assert_eq!(diff(&a, &b).to_string(), "~ [*].ok?: boolean -> optional boolean (breaking)\n");
+    }
+
+    #[test]
+    fn saved_test() {
+        assert_eq!(saved(json!({"schema": [{"a?": "string|null", "b": {"enum": ["x"]}}], "fields": {}})), Some(json!([{"a?": "string|null", "b": {"enum": ["x"]}}])));
+        assert_eq!(saved(json!([{"a": "date-time", "n": ["integer"]}])), Some(json!([{"a": "date-time", "n": ["integer"]}])));
+        assert_eq!(saved(json!({"status": "ok"})), None);
+        assert_eq!(saved(json!({"n": 1})), None);
+    }
+}
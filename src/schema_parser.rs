@@ -1,3 +1,10 @@
+use crate::codegen::{self, Language};
+use crate::error::JistError;
+use crate::input::{self, Mapped};
+use crate::json_schema;
+use crate::presence::{escape_keys, Presence};
+use crate::stats::Stats;
+use crate::simd_parser;
 use crate::model::j_struct_tracker::JStructTracker;
 use crate::model::stream_tracker::StreamTracker;
 use crate::utils::{find_str, is_ndjson, sanitize_output, token_pos};
@@ -71,48 +78,256 @@ pub(crate) fn deduplicate_arrays(value: Value) -> Value {
     }
 }
 
-pub fn summarize(haystack: &str, unionize: bool) -> Result<String, &'static str> {
+// Format of the summarized schema
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SchemaFormat {
+    // jist's own shorthand, e.g. {"a":"string","e":["number"]}
+    #[default]
+    Jist,
+    // a JSON Schema (draft 2020-12) document, describing a single record for NDJSON
+    JsonSchema,
+}
+
+// How a schema is summarized
+#[derive(Debug, Clone, Default)]
+pub struct SchemaOptions {
+    // merge the elements of a top-level array into one
+    pub unionize: bool,
+    pub format: SchemaFormat,
+    // report how often every key is present and whether it's ever null along with the schema, as
+    // {"schema": ..., "fields": ...}
+    pub verbose: bool,
+    // report statistics of the values at every key path along with the schema, as
+    // {"schema": ..., "stats": ...}. NDJSON is then read line by line rather than by simdjson.
+    pub stats: bool,
+    // Tell integers from other numbers and name the format of strings, e.g. "date-time" or "uuid", when
+    // at least this share of the sampled strings at a key path have it. Like `stats` NDJSON is then read
+    // line by line.
+    pub formats: Option<f64>,
+    // Name the distinct values of strings at a key path, as {"enum": [...]} in place of "string", when
+    // there are at most this many. Like `stats` NDJSON is then read line by line.
+    pub enums: Option<usize>,
+    // Print type definitions in this language instead, of the schema with the elements of a top-level
+    // array merged as with `unionize`. `format` and the reports are left out.
+    pub emit: Option<Language>,
+}
+
+impl SchemaOptions {
+    // the statistics to gather during the token walk, if any are needed
+    pub(crate) fn new_stats(&self) -> Option<Stats> {
+        (self.stats || self.formats.is_some() || self.enums.is_some()).then(|| Stats::new(self.stats, self.enums))
+    }
+
+    // the schema as printed, JSON or type definitions
+    pub(crate) fn render(&self, schema: Value) -> String {
+        match self.emit {
+            Some(language) => codegen::emit(&schema, language),
+            None => schema.to_string(),
+        }
+    }
+
+    // the types of a schema refined by the gathered statistics
+    fn refine(&self, schema: Value, stats: Option<&Stats>) -> Value {
+        match stats {
+            Some(stats) if self.formats.is_some() || self.enums.is_some() => stats.refine(schema, self.formats),
+            _ => schema,
+        }
+    }
+}
+
+pub fn summarize(haystack: &str, unionize: bool) -> Result<String, JistError> {
+    summarize_as(haystack, &SchemaOptions { unionize, ..SchemaOptions::default() })
+}
+
+pub fn summarize_as(haystack: &str, options: &SchemaOptions) -> Result<String, JistError> {
     if is_ndjson(haystack) {
-        let lines: Vec<String> = haystack.lines()
-            .map(String::from)
-            .collect();
+        let mut stats = options.new_stats();
+        let lines = || haystack.lines().map(|line| Ok(line.to_string()));
+        let schemas = match stats.as_mut() {
+            Some(stats) => line_schemas(lines(), Some(stats))?,
+            None => match simd_parser::record_schemas(Some(haystack), None) {
+                Err(JistError::CapacityExceeded) => line_schemas(lines(), None)?,
+                schemas => schemas?,
+            },
+        };
+        Ok(records_as(schemas, options, stats.as_ref()))
+    } else {
+        parse_as(haystack, options)
+    }
+}
 
-        let num_threads = available_parallelism().unwrap().get();
-        let pool = ThreadPoolBuilder::new()
-            .pool_size(num_threads)
-            .create()
-            .unwrap();
+// Like `summarize` for a file. A plain file is mapped into memory, a compressed one decompressed on
+// the fly: NDJSON lines are handed out as they are read, anything else is parsed once fully read.
+pub fn summarize_file(file: &str, unionize: bool) -> Result<String, JistError> {
+    summarize_file_as(file, &SchemaOptions { unionize, ..SchemaOptions::default() })
+}
 
-        let (tx, rx) = mpsc::channel();
+pub fn summarize_file_as(file: &str, options: &SchemaOptions) -> Result<String, JistError> {
+    if let Some(map) = Mapped::open(file)? {
+        let first_line = &map[..map.iter().position(|b| *b == b'\n').map_or(map.len(), |end| end + 1)];
+        if options.new_stats().is_none() && std::str::from_utf8(first_line).is_ok_and(is_ndjson) {
+            match simd_parser::record_schemas(None, Some(file)) {
+                Err(JistError::CapacityExceeded) => {}
+                schemas => return schemas.map(|schemas| records_as(schemas, options, None)),
+            }
+        }
+        let haystack = std::str::from_utf8(&map)
+            .map_err(|e| JistError::malformed(Some(e.valid_up_to() as u64), "invalid UTF-8"))?;
+        return summarize_as(haystack, options);
+    }
+    let mut reader = BufReader::new(input::reader(file)?);
+    let mut first = String::new();
+    reader.read_line(&mut first)?;
+    if is_ndjson(&first) {
+        let rest = reader.lines().map(|line| line.map_err(JistError::from));
+        let mut stats = options.new_stats();
+        let schemas = line_schemas(std::iter::once(Ok(first.trim_end().to_string())).chain(rest), stats.as_mut())?;
+        Ok(records_as(schemas, options, stats.as_ref()))
+    } else {
+        reader.read_to_string(&mut first)?;
+        parse_as(&first, options)
+    }
+}
+
+// Distinct schemas of NDJSON lines with their number of lines, every line is parsed on the thread
+// pool. With `stats` lines are parsed in order on this thread instead, gathering statistics.
+fn line_schemas<I: Iterator<Item = Result<String, JistError>>>(lines: I, stats: Option<&mut Stats>) -> Result<Vec<(Value, u64)>, JistError> {
+    let mut schemas: Vec<(Value, u64)> = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut add = |schema: Value| {
+        let i = *seen.entry(schema.to_string()).or_insert_with(|| {
+            schemas.push((schema, 0));
+            schemas.len() - 1
+        });
+        schemas[i].1 += 1;
+    };
+    let lines = lines.filter(|line| !line.as_ref().is_ok_and(|line| line.trim().is_empty()));
+
+    if let Some(stats) = stats {
         for line in lines {
-            let tx = tx.clone();
-            let future = async move {
-                tx.send(parse(&line, true).unwrap()).unwrap();
-            };
-            pool.spawn(future).unwrap();
+            add(sort_serde_json(&deduplicate_arrays(tape(&line?, Some(&mut *stats))?)));
         }
+        return Ok(schemas);
+    }
+
+    let num_threads = available_parallelism().unwrap().get();
+    let pool = ThreadPoolBuilder::new()
+        .pool_size(num_threads)
+        .create()
+        .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    for line in lines {
+        let line = line?;
+        let tx = tx.clone();
+        let future = async move {
+            tx.send(tape(&line, None).map(|tape| sort_serde_json(&deduplicate_arrays(tape)))).unwrap();
+        };
+        pool.spawn(future).unwrap();
+    }
+
+    drop(tx);
+
+    for schema in rx.iter() {
+        add(schema?);
+    }
+    Ok(schemas)
+}
+
+// schemas of NDJSON records in the requested format
+fn records_as(schemas: Vec<(Value, u64)>, options: &SchemaOptions, stats: Option<&Stats>) -> String {
+    let presence = Presence::records(&schemas);
+    let schemas: Vec<Value> = schemas.into_iter().map(|(schema, _)| options.refine(schema, stats)).collect();
+    let schema = match options.format {
+        _ if options.emit.is_some() => return options.render(merge_schemas(schemas, &presence)),
+        SchemaFormat::Jist => merge_schemas(schemas, &presence),
+        SchemaFormat::JsonSchema => json_schema::records(&schemas),
+    };
+    with_reports(schema, &presence, stats.filter(|_| options.stats), options.verbose).to_string()
+}
 
-        drop(tx);
+// schemas of NDJSON records merged into the schema of the whole input, with optional and nullable keys marked
+fn merge_schemas(schemas: Vec<Value>, presence: &Presence) -> Value {
+    let mut schemas = schemas.into_iter().map(|schema| sort_serde_json(&deduplicate_arrays(schema)));
+    let Some(mut first) = schemas.next() else {
+        return Value::Array(vec![]);
+    };
+    for next in schemas {
+        merge_nullable(&mut first, &next);
+    }
+    let mut json_schema: Value = serde_json::from_str("[]").unwrap();
+    json_schema.merge(&presence.mark(sort_serde_json(&first), ""));
+    json_schema = deduplicate_arrays(json_schema);
+    sort_serde_json(&json_schema)
+}
 
-        let mut schemas: Vec<String> = rx.iter().collect();
-        schemas.dedup();
-        let first_schema = schemas.first().unwrap().as_str();
-        let mut first: Value = serde_json::from_str(first_schema).unwrap();
-        for next in schemas.iter() {
-            let next_schema = serde_json::from_str(next).unwrap();
-            first.merge(&next_schema);
+// `next` merged into `schema` like `Merge::merge` does, except that a null gives way to any other type
+fn merge_nullable(schema: &mut Value, next: &Value) {
+    match (schema, next) {
+        (_, Value::String(kind)) if kind == "null" => {}
+        // enums of the same path are the same, merging would repeat their values
+        (schema, next) if schema == next => {}
+        (Value::Object(members), Value::Object(next)) => {
+            for (key, value) in next {
+                match members.get_mut(key) {
+                    Some(member) => merge_nullable(member, value),
+                    None => {
+                        members.insert(key.clone(), value.clone());
+                    }
+                }
+            }
         }
-        let mut json_schema: Value = serde_json::from_str("[]").unwrap();
-        json_schema.merge(&first);
-        json_schema = deduplicate_arrays(json_schema);
-        json_schema = sort_serde_json(&json_schema);
-        Ok(json_schema.to_string())
-    } else {
-        parse(haystack, unionize)
+        (schema, next) if *schema == "null" => *schema = next.clone(),
+        (schema, next) => schema.merge(next),
+    }
+}
+
+pub fn parse(haystack: &str, unionize: bool) -> Result<String, JistError> {
+    parse_as(haystack, &SchemaOptions { unionize, ..SchemaOptions::default() })
+}
+
+// Like `parse` with all options
+fn parse_as(haystack: &str, options: &SchemaOptions) -> Result<String, JistError> {
+    let mut stats = options.new_stats();
+    let tape = tape(haystack, stats.as_mut())?;
+    Ok(options.render(describe(tape, options, stats.as_ref())))
+}
+
+// The schema of a document from its raw schema tape, with the elements of arrays not deduplicated
+// yet. A JSON Schema is built from the schema without unionizing, so that merged objects only
+// require the keys every variant has.
+pub(crate) fn describe(tape: Value, options: &SchemaOptions, stats: Option<&Stats>) -> Value {
+    let presence = if options.unionize || options.verbose || options.emit.is_some() { Presence::document(&tape) } else { Presence::default() };
+    let json = options.refine(sort_serde_json(&deduplicate_arrays(tape)), stats);
+    let schema = match options.format {
+        _ if options.emit.is_some() => return unionize_schema(json, &presence),
+        // Perform union at top level
+        SchemaFormat::Jist if options.unionize => unionize_schema(json, &presence),
+        SchemaFormat::Jist => escape_keys(json),
+        SchemaFormat::JsonSchema => json_schema::document(&json, options.unionize),
+    };
+    with_reports(schema, &presence, stats.filter(|_| options.stats), options.verbose)
+}
+
+// the schema along with the reports asked for
+fn with_reports(schema: Value, presence: &Presence, stats: Option<&Stats>, verbose: bool) -> Value {
+    if !verbose && stats.is_none() {
+        return schema;
+    }
+    let mut described = Map::new();
+    described.insert("schema".to_string(), schema);
+    if verbose {
+        described.insert("fields".to_string(), presence.report());
+    }
+    if let Some(stats) = stats {
+        described.insert("stats".to_string(), stats.report());
     }
+    Value::Object(described)
 }
 
-pub fn parse(haystack: &str, unionize: bool) -> Result<String, &'static str> {
+// the schema tape of a document, types in place of values and every array element kept, handing
+// every token to `stats` when there is one
+fn tape(haystack: &str, mut stats: Option<&mut Stats>) -> Result<Value, JistError> {
     let mut struct_t = JStructTracker::init();
     let mut schema_tape = String::new();
 
@@ -125,64 +340,53 @@ pub fn parse(haystack: &str, unionize: bool) -> Result<String, &'static str> {
 
         // token processing
         let mut token = token_opt.unwrap();
+        if let Some(stats) = &mut stats {
+            let (first, end) = token_pos(&token.buf)?;
+            stats.token(token.kind.clone(), &haystack.as_bytes()[first as usize..end as usize]);
+        }
         match token.kind {
             TokenType::CurlyOpen => {
-                struct_t.depth_curr.0 += 1;
-                struct_t.depth_curr.2 += 1;
-                let (first, end) = token_pos(&token.buf)?;
-                struct_t
-                    .last_open_pin
-                    .push((TokenType::CurlyOpen, first, schema_tape.len()));
+                struct_t.last_open.push(TokenType::CurlyOpen);
                 schema_tape = schema_tape + "{";
             }
             TokenType::CurlyClose => {
-                struct_t.depth_curr.0 -= 1;
-                struct_t.depth_curr.2 -= 1;
                 schema_tape = schema_tape + "}";
 
                 let last_curly_open = struct_t
-                    .last_open_pin
+                    .last_open
                     .iter()
-                    .filter(|sym| sym.0 == TokenType::CurlyOpen)
+                    .filter(|sym| **sym == TokenType::CurlyOpen)
                     .last();
                 if last_curly_open.is_none() {
-                    return Err("invalid json: missing opening curly brace");
+                    let (first, _) = token_pos(&token.buf)?;
+                    return Err(JistError::malformed(Some(first), "missing opening curly brace"));
                 }
 
-                struct_t.last_open_pin.pop();
+                struct_t.last_open.pop();
             }
             TokenType::BracketOpen => {
-                struct_t.depth_curr.0 += 1;
-                struct_t.depth_curr.1 += 1;
                 struct_t.arr_idx.push(0);
-                let (first, end) = token_pos(&token.buf)?;
-                struct_t
-                    .last_open_pin
-                    .push((TokenType::BracketOpen, first, schema_tape.len()));
+                struct_t.last_open.push(TokenType::BracketOpen);
                 schema_tape = schema_tape + "[";
             }
             TokenType::BracketClose => {
-                struct_t.depth_curr.0 -= 1;
-                struct_t.depth_curr.1 -= 1;
                 schema_tape = schema_tape + "]";
 
                 let last_bracket_open = struct_t
-                    .last_open_pin
+                    .last_open
                     .iter()
-                    .filter(|sym| sym.0 == TokenType::BracketOpen)
+                    .filter(|sym| **sym == TokenType::BracketOpen)
                     .last();
                 if last_bracket_open.is_none() {
-                    return Err("invalid json: missing opening curly brace");
+                    let (first, _) = token_pos(&token.buf)?;
+                    return Err(JistError::malformed(Some(first), "missing opening bracket"));
                 }
 
                 struct_t.arr_idx.pop();
-                struct_t.last_open_pin.pop();
+                struct_t.last_open.pop();
             }
             TokenType::Comma => {
-                let (token_type, _, _) = struct_t.last_open_pin.last().unwrap();
-                if struct_t.depth_curr.1 > -1                    // must be inside an array
-                    && token_type.eq(&TokenType::BracketOpen)
-                {
+                if struct_t.last_open.last() == Some(&TokenType::BracketOpen) {
                     let arr_idx_len = struct_t.arr_idx.len();
                     struct_t.arr_idx[arr_idx_len - 1] += 1;
                 }
@@ -193,12 +397,11 @@ pub fn parse(haystack: &str, unionize: bool) -> Result<String, &'static str> {
                 schema_tape = schema_tape + "\"boolean\""
             }
             TokenType::Number => schema_tape = schema_tape + "\"number\"",
-            TokenType::Null => schema_tape = schema_tape + "\"string\"",
+            TokenType::Null => schema_tape = schema_tape + "\"null\"",
             _ => {} // String type can be an object key which requires special handling
         }
 
-        if !struct_t.last_open_pin.is_empty() {
-            let (token_type, _, _) = struct_t.last_open_pin.last().unwrap();
+        if let Some(token_type) = struct_t.last_open.last() {
             if token_type.eq(&TokenType::BracketOpen) {
                 if token.kind == TokenType::String {
                     schema_tape = schema_tape + "\"string\"";
@@ -225,31 +428,22 @@ pub fn parse(haystack: &str, unionize: bool) -> Result<String, &'static str> {
     }
 
     info!("done");
-    let mut json: Value = serde_json::from_str(schema_tape.as_str()).expect("JSON parsing error");
-    json = deduplicate_arrays(json);
-    json = sort_serde_json(&json);
-
-    // Perform union at top level
-    if unionize {
-        Ok(unionize_schema(&json, true))
-    } else {
-        Ok(json.to_string())
-    }
+    serde_json::from_str(schema_tape.as_str()).map_err(|e| JistError::malformed(None, e))
 }
 
-fn unionize_schema(json: &Value, array_wrap: bool) -> String {
-    if json.is_array() {
-        let mut first = json.as_array().unwrap().first().unwrap().clone();
-        for next in json.as_array().unwrap().iter() {
-            first.merge(next);
-        }
-
-        if array_wrap {
-            return "[".to_string() + first.to_string().as_str() + "]";
-        }
-        return first.to_string();
+// the element variants of a top-level array merged into one, with optional and nullable keys marked
+fn unionize_schema(json: Value, presence: &Presence) -> Value {
+    let Value::Array(variants) = json else {
+        return escape_keys(json);
+    };
+    let mut variants = variants.into_iter();
+    let Some(mut first) = variants.next() else {
+        return Value::Array(vec![]);
+    };
+    for next in variants {
+        merge_nullable(&mut first, &next);
     }
-    json.to_string()
+    Value::Array(vec![presence.mark(sort_serde_json(&first), "[*]")])
 }
 
 #[cfg(test)]
@@ -323,6 +517,14 @@ mod tests {
             parse(r#"[{"a":"b"},{"f":"g","h":{"a":"c"}},{"a":"d"}]"#, false),
             Ok(r#"[{"a":"string"},{"f":"string","h":{"a":"string"}}]"#.to_string())
         );
+        assert_eq!(
+            parse(r#"[{"a":"b"},{"f":"g","h":{"a":"c"}},{"a":null}]"#, true),
+            Ok(r#"[{"a?":"string|null","f?":"string","h?":{"a":"string"}}]"#.to_string())
+        );
+        assert_eq!(
+            parse(r#"{"a":null,"b":[null,1]}"#, false),
+            Ok(r#"{"a":"null","b":["null","number"]}"#.to_string())
+        );
     }
 
     #[test]
@@ -331,9 +533,86 @@ mod tests {
         {"a":"c"}"#, true), Ok(r#"[{"a":"string"}]"#.to_string()));
 
         assert_eq!(summarize(r#"{"a":"b","f":12}
-        {"a":"c","d":"c"}"#, true), Ok(r#"[{"a":"string","d":"string","f":"number"}]"#.to_string()));
+        {"a":"c","d":"c"}"#, true), Ok(r#"[{"a":"string","d?":"string","f?":"number"}]"#.to_string()));
 
         assert_eq!(summarize(r#"{"a":"b","f":[{"x":"y"},{"x":"v"}]}
-        {"a":"c"}"#, true), Ok(r#"[{"a":"string","f":[{"x":"string"}]}]"#.to_string()));
+        {"a":"c"}"#, true), Ok(r#"[{"a":"string","f?":[{"x":"string"}]}]"#.to_string()));
+
+        // null is a type of its own, keys that are sometimes null are nullable
+        assert_eq!(summarize(r#"{"a":null,"b":null}
+        {"a":"c","b":null}
+        {"a":"d","b":null}"#, true), Ok(r#"[{"a":"string|null","b":"null"}]"#.to_string()));
+
+        let verbose = SchemaOptions { verbose: true, ..SchemaOptions::default() };
+        assert_eq!(
+            summarize_as("{\"a\":1,\"b\":{\"c\":null}}\n{\"a\":2}\n{\"a\":3,\"b\":{\"c\":true}}\n{\"a\":4}", &verbose),
+            Ok(r#"{"schema":[{"a":"number","b?":{"c":"boolean|null"}}],"fields":{"a":{"required":true,"nullable":false,"presence":1.0},"b":{"required":false,"nullable":false,"presence":0.5},"b.c":{"required":true,"nullable":true,"presence":1.0}}}"#.to_string())
+        );
+    }
+
+    #[test]
+    fn formats_test() {
+        let options = |threshold| SchemaOptions { formats: Some(threshold), ..SchemaOptions::default() };
+        let sample = r#"[
+            {"timestamp": "2024-06-01T12:30:00Z", "sensor_uuid": "123e4567-e89b-12d3-a456-426614174000", "dst": {"ip": "10.0.0.1", "port": 443, "latitude": 51.5}},
+            {"timestamp": "2024-06-01T12:31:00.250+02:00", "sensor_uuid": "123e4567-e89b-12d3-a456-426614174001", "dst": {"ip": "fe80::1", "port": 80, "latitude": 48}},
+            {"timestamp": "yesterday", "sensor_uuid": "123e4567-e89b-12d3-a456-426614174002", "dst": {"ip": "10.0.0.3", "port": 8080, "latitude": -3.25}}
+        ]"#;
+        assert_eq!(
+            summarize_as(sample, &SchemaOptions { unionize: true, ..options(0.6) }),
+            Ok(r#"[{"dst":{"ip":"ipv4","latitude":"number","port":"integer"},"sensor_uuid":"uuid","timestamp":"date-time"}]"#.to_string())
+        );
+        assert_eq!(
+            summarize_as(sample, &SchemaOptions { unionize: true, ..options(0.9) }),
+            Ok(r#"[{"dst":{"ip":"string","latitude":"number","port":"integer"},"sensor_uuid":"uuid","timestamp":"string"}]"#.to_string())
+        );
+        assert_eq!(
+            summarize_as("{\"at\": \"2024-06-01\", \"n\": 1}\n{\"at\": null, \"n\": 2}\n", &options(0.9)),
+            Ok(r#"[{"at":"date|null","n":"integer"}]"#.to_string())
+        );
+    }
+
+    #[test]
+    fn enums_test() {
+        let options = |enums| SchemaOptions { enums: Some(enums), ..SchemaOptions::default() };
+        let records = "{\"proto\": \"UDP\", \"host\": \"a\"}\n{\"proto\": \"TCP\", \"host\": \"b\"}\n{\"proto\": null, \"host\": \"c\", \"tags\": [\"x\"]}\n";
+        assert_eq!(
+            summarize_as(records, &options(2)),
+            Ok(r#"[{"host":"string","proto":{"enum":["TCP","UDP",null]},"tags?":[{"enum":["x"]}]}]"#.to_string())
+        );
+        assert_eq!(
+            summarize_as(r#"[{"proto": "UDP", "n": 1}, {"proto": "TCP", "n": null}]"#, &SchemaOptions { unionize: true, ..options(2) }),
+            Ok(r#"[{"n":"number|null","proto":{"enum":["TCP","UDP"]}}]"#.to_string())
+        );
+        let schema = summarize_as(r#"{"proto": "UDP"}"#, &SchemaOptions { format: SchemaFormat::JsonSchema, ..options(2) }).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&schema).unwrap()["properties"]["proto"], serde_json::json!({"type": "string", "enum": ["UDP"]}));
+    }
+
+    #[test]
+    fn emit_test() {
+        let options = SchemaOptions { emit: Some(Language::TypeScript), ..SchemaOptions::default() };
+        assert_eq!(
+            summarize_as("{\"id\": 1, \"note\": null}\n{\"id\": 2, \"note\": \"x\"}\n{\"id\": 3}\n", &options),
+            Ok("export type Root = Item[];\n\nexport interface Item {\n  id: number;\n  note?: string | null;\n}".to_string())
+        );
+        // the elements of a top-level array are merged without `unionize`
+        assert_eq!(summarize_as(r#"[{"a": 1}, {"b": true}]"#, &options), Ok("export type Root = Item[];\n\nexport interface Item {\n  a?: number;\n  b?: boolean;\n}".to_string()));
+    }
+
+    #[test]
+    fn json_schema_test() {
+        let options = |unionize| SchemaOptions { unionize, format: SchemaFormat::JsonSchema, ..SchemaOptions::default() };
+        let schema = |haystack, unionize| serde_json::from_str::<Value>(&summarize_as(haystack, &options(unionize)).unwrap()).unwrap();
+        let records = schema(r#"{"a":"b","f":12}
+        {"a":"c","d":"c"}"#, false);
+        assert_eq!(records["type"], "object");
+        assert_eq!(records["required"], serde_json::json!(["a"]));
+        assert_eq!(records["properties"]["f"], serde_json::json!({"type": "number"}));
+
+        let array = schema(r#"[{"a":"b","f":12}, {"a":"c","d":"c"}]"#, false);
+        assert_eq!(array["items"]["anyOf"].as_array().map(Vec::len), Some(2));
+        let unionized = schema(r#"[{"a":"b","f":12}, {"a":"c","d":"c"}]"#, true);
+        assert_eq!(unionized["items"]["required"], serde_json::json!(["a"]));
+        assert_eq!(unionized["items"]["additionalProperties"], false);
     }
 }
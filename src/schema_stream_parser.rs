@@ -1,193 +1,78 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::io::Read;
 use json_tools::{BufferType, Lexer, Token, TokenType};
 use json_tools::TokenType::Invalid;
 use log::debug;
 use serde_json::Value;
 use crate::buf_parser::_search;
+use crate::error::JistError;
+use crate::input::{self, Mapped};
 use crate::model::j_struct_tracker::JStructTracker;
 use crate::model::stream_tracker::StreamTracker;
+use crate::schema_parser::{self, SchemaOptions};
+use crate::stats::Stats;
 use crate::utils;
-use crate::utils::{find_str, token_pos};
+use crate::utils::token_pos;
+
+const CHUNK_SIZE: usize = 1_000_000;
 
 pub fn parse(
     haystack: Option<&str>,
     file: Option<&str>, // Keep this as Option<&str> for future flexibility with testing & dev
-) -> Result<String, &'static str> {
-    if file.is_some() {
-        let f = File::open(file.unwrap()).unwrap();
-        let mut reader = BufReader::new(&f);
-        let mut seeker = BufReader::new(&f);
-        _parse(&mut reader, &mut seeker)
+) -> Result<String, JistError> {
+    parse_as(haystack, file, &SchemaOptions::default())
+}
+
+// Like `parse` with all options
+pub fn parse_as(haystack: Option<&str>, file: Option<&str>, options: &SchemaOptions) -> Result<String, JistError> {
+    let mut stats = options.new_stats();
+    let tape = if let Some(file) = file {
+        match Mapped::open(file)? {
+            Some(map) => _parse_slice(&map, |pos| map.release(pos), stats.as_mut())?,
+            None => _parse(input::reader(file)?, stats.as_mut())?,
+        }
     } else if haystack.is_some() {
         let haystack_str = haystack.unwrap();
         if haystack_str.is_empty() {
-            return Err("Invalid input - empty data");
+            return Err(JistError::InvalidInput("Invalid input - empty data"));
         }
-        let mut reader = Cursor::new(haystack_str.as_bytes());
-        let mut seeker = Cursor::new(haystack_str.as_bytes());
-        _parse(&mut reader, &mut seeker)
+        _parse_slice(haystack_str.as_bytes(), |_| {}, stats.as_mut())?
     } else {
-        Err("Invalid input - empty data")
-    }
+        return Err(JistError::InvalidInput("Invalid input - empty data"));
+    };
+    Ok(options.render(schema_parser::describe(tape, options, stats.as_ref())))
 }
 
-pub fn _parse<R: Read + Seek + BufRead>(
-    mut reader: R,
-    mut seeker: R,
-) -> Result<String, &'static str> {
-    let chunk_size = 1_000_000;
+// Raw schema tape of the input, read front to back, so it also works on a decompressing reader.
+// Every token is handed to `stats` when there is one.
+pub(crate) fn _parse<R: Read>(mut reader: R, mut stats: Option<&mut Stats>) -> Result<Value, JistError> {
+    let chunk_size = CHUNK_SIZE;
     let mut stream_t = StreamTracker::new(chunk_size);
     let mut struct_t = JStructTracker::init();
     let mut schema_tape = String::new();
 
     loop {
-        reader.seek(SeekFrom::Start(stream_t.last_stream_pos)).expect("Unable to seek");
         let bytes_read = reader
             .by_ref()
             .take(chunk_size as u64)
-            .read_to_end(&mut stream_t.buffer)
-            .unwrap();
+            .read_to_end(&mut stream_t.buffer)?;
         if bytes_read == 0 && stream_t.buffer.is_empty() {
             break;
         }
 
-        // Find the last line break (add one if last buffer read)
-        let mut chunk_str = String::from_utf8(stream_t.buffer.clone()).unwrap();
-        if bytes_read < chunk_size {
-            chunk_str.push('\n');
-        }
-
-        if let Some(last_chunk_tup) = chunk_str.rsplit_once('\n') {
-            let last_chunk = last_chunk_tup.0;
+        // Find the end of the last complete token
+        let exhausted = bytes_read < chunk_size;
+        let last_chunk = utils::complete_tokens(&stream_t.buffer, exhausted, stream_t.last_stream_pos)?;
+        if let Some(last_chunk) = last_chunk {
             debug!("last_chunk: {}", last_chunk);
-            // Process the chunk that ends with a newline
-            stream_t.chunk.extend_from_slice(last_chunk.as_bytes());
-            stream_t.chunk.push(b'\n');
-            stream_t.last_chunk_len = stream_t.chunk.len();
-
-            // Process chunk here
-            let mut token_iter = Lexer::new(stream_t.chunk.clone(), BufferType::Span).peekable();
-            loop {
-                let token_opt = token_iter.next();
-                if token_opt.is_none() {
-                    break;
-                }
-
-                // token processing
-                let mut token = token_opt.unwrap();
-                match token.kind {
-                    Invalid => {
-                        panic!("At the disco!");
-                    }
-                    TokenType::CurlyOpen => {
-                        struct_t.depth_curr.0 += 1;
-                        struct_t.depth_curr.2 += 1;
-                        let (first, end) = token_pos(&token.buf)?;
-                        struct_t
-                            .last_open_pin
-                            .push((TokenType::CurlyOpen, first, schema_tape.len()));
-                        schema_tape = schema_tape + "{";
-                    }
-                    TokenType::CurlyClose => {
-                        struct_t.depth_curr.0 -= 1;
-                        struct_t.depth_curr.2 -= 1;
-                        schema_tape = schema_tape + "}";
-
-                        let last_curly_open = struct_t
-                            .last_open_pin
-                            .iter()
-                            .filter(|sym| sym.0 == TokenType::CurlyOpen)
-                            .last();
-                        if last_curly_open.is_none() {
-                            return Err("invalid json: missing opening curly brace");
-                        }
-
-                        struct_t.last_open_pin.pop();
-                    }
-                    TokenType::BracketOpen => {
-                        struct_t.depth_curr.0 += 1;
-                        struct_t.depth_curr.1 += 1;
-                        struct_t.arr_idx.push(0);
-                        let (first, end) = token_pos(&token.buf)?;
-                        struct_t
-                            .last_open_pin
-                            .push((TokenType::BracketOpen, first, schema_tape.len()));
-                        schema_tape = schema_tape + "[";
-                    }
-                    TokenType::BracketClose => {
-                        struct_t.depth_curr.0 -= 1;
-                        struct_t.depth_curr.1 -= 1;
-                        schema_tape = schema_tape + "]";
-
-                        let last_bracket_open = struct_t
-                            .last_open_pin
-                            .iter()
-                            .filter(|sym| sym.0 == TokenType::BracketOpen)
-                            .last();
-                        if last_bracket_open.is_none() {
-                            return Err("invalid json: missing opening bracket");
-                        }
-
-                        struct_t.arr_idx.pop();
-                        struct_t.last_open_pin.pop();
-                    }
-                    TokenType::Comma => {
-                        let (token_type, _, _) = struct_t.last_open_pin.last().unwrap();
-                        if struct_t.depth_curr.1 > -1                    // must be inside an array
-                            && token_type.eq(&TokenType::BracketOpen)
-                        {
-                            let arr_idx_len = struct_t.arr_idx.len();
-                            struct_t.arr_idx[arr_idx_len - 1] += 1;
-                        }
-                        schema_tape = schema_tape + ",";
-                    }
-                    TokenType::Colon => schema_tape = schema_tape + ":",
-                    TokenType::BooleanTrue | TokenType::BooleanFalse => {
-                        schema_tape = schema_tape + "\"boolean\""
-                    }
-                    TokenType::Number => schema_tape = schema_tape + "\"number\"",
-                    TokenType::Null => schema_tape = schema_tape + "\"string\"",
-                    _ => {} // String type can be an object key which requires special handling
-                }
-
-                if !struct_t.last_open_pin.is_empty() {
-                    let (token_type, _, _) = struct_t.last_open_pin.last().unwrap();
-                    if token_type.eq(&TokenType::BracketOpen) {
-                        if token.kind == TokenType::String {
-                            schema_tape = schema_tape + "\"string\"";
-                        }
-                    } else if token_type.eq(&TokenType::CurlyOpen) {
-                        if token.kind == TokenType::String && struct_t.last_token_key_delimiter {
-                            let (first, end) = token_pos(&token.buf)?;
-                            let key = find_str(
-                                &mut seeker,
-                                first + stream_t.last_stream_pos,
-                                end + stream_t.last_stream_pos,
-                            ).unwrap();
-                            schema_tape = schema_tape + &key;
-                            struct_t.last_token_key_delimiter = false;
-                        } else if token.kind == TokenType::String {
-                            schema_tape = schema_tape + "\"string\"";
-                        }
-
-                        if token.kind == TokenType::CurlyOpen || token.kind == TokenType::Comma {
-                            struct_t.last_token_key_delimiter = true;
-                        }
-                    }
-                }
-
-                if token_iter.peek().is_none() {
-                    break;
-                }
-            }
+            // Process the chunk that ends on a token boundary
+            schema_tape = lex(last_chunk.as_bytes(), stream_t.last_stream_pos, exhausted, &mut struct_t, schema_tape, stats.as_deref_mut())?;
+            stream_t.last_chunk_len = last_chunk.len();
 
-            // Clear chunk for next iteration
-            stream_t.chunk.clear();
-            // Remove processed data from buffer
-            stream_t.buffer.clear();
+            // Remove processed data from buffer, a token cut off at its end is lexed with the next chunk
+            stream_t.buffer.drain(..stream_t.last_chunk_len);
         } else {
-            return Err("result not found");
+            // no token boundary yet, keep reading
+            continue;
         }
 
         stream_t.last_stream_pos += stream_t.last_chunk_len as u64;
@@ -197,11 +82,176 @@ pub fn _parse<R: Read + Seek + BufRead>(
         );
     }
 
-    let mut json: Value = serde_json::from_str(schema_tape.as_str()).expect("JSON parsing error");
-    json = crate::schema_parser::deduplicate_arrays(json);
-    json = crate::schema_parser::sort_serde_json(&json);
+    finish(&schema_tape)
+}
+
+// Raw schema tape of `bytes`, lexed in place a chunk at a time, e.g. a file mapped into memory.
+// `release` is told how far the lexer got after every chunk.
+pub(crate) fn _parse_slice<F: FnMut(usize)>(bytes: &[u8], mut release: F, mut stats: Option<&mut Stats>) -> Result<Value, JistError> {
+    let mut struct_t = JStructTracker::init();
+    let mut schema_tape = String::new();
+    for chunk in utils::token_chunks(bytes, CHUNK_SIZE) {
+        let (pos, chunk, last) = chunk?;
+        schema_tape = lex(chunk.as_bytes(), pos, last, &mut struct_t, schema_tape, stats.as_deref_mut())?;
+        release(pos as usize + chunk.len());
+    }
+    finish(&schema_tape)
+}
+
+// Every token of the input with its text and stream position, read a chunk at a time like
+// `parse_as` reads it but without building a schema tape, e.g. to validate the input
+pub(crate) fn tokens<F>(haystack: Option<&str>, file: Option<&str>, mut f: F) -> Result<(), JistError>
+where
+    F: FnMut(TokenType, &[u8], u64) -> Result<(), JistError>,
+{
+    let mapped = match file {
+        Some(file) => Mapped::open(file)?,
+        None => None,
+    };
+    let bytes = match (&mapped, haystack, file) {
+        (Some(map), _, _) => &map[..],
+        (None, _, Some(file)) => return reader_tokens(input::reader(file)?, f),
+        (None, Some(haystack), None) if !haystack.is_empty() => haystack.as_bytes(),
+        _ => return Err(JistError::InvalidInput("Invalid input - empty data")),
+    };
+    for chunk in utils::token_chunks(bytes, CHUNK_SIZE) {
+        let (pos, chunk, last) = chunk?;
+        lex_tokens(chunk.as_bytes(), pos, last, &mut f)?;
+        if let Some(map) = &mapped {
+            map.release(pos as usize + chunk.len());
+        }
+    }
+    Ok(())
+}
+
+fn reader_tokens<R: Read, F: FnMut(TokenType, &[u8], u64) -> Result<(), JistError>>(mut reader: R, mut f: F) -> Result<(), JistError> {
+    let mut stream_t = StreamTracker::new(CHUNK_SIZE);
+    loop {
+        let bytes_read = reader.by_ref().take(CHUNK_SIZE as u64).read_to_end(&mut stream_t.buffer)?;
+        if bytes_read == 0 && stream_t.buffer.is_empty() {
+            return Ok(());
+        }
+        let exhausted = bytes_read < CHUNK_SIZE;
+        let Some(chunk) = utils::complete_tokens(&stream_t.buffer, exhausted, stream_t.last_stream_pos)? else {
+            continue;
+        };
+        lex_tokens(chunk.as_bytes(), stream_t.last_stream_pos, exhausted, &mut f)?;
+        let len = chunk.len();
+        stream_t.buffer.drain(..len);
+        stream_t.last_stream_pos += len as u64;
+    }
+}
+
+// Hand every token of `chunk`, which starts at stream position `pos` and ends on a token boundary, to `f`
+fn lex_tokens<F: FnMut(TokenType, &[u8], u64) -> Result<(), JistError>>(chunk: &[u8], pos: u64, last: bool, f: &mut F) -> Result<(), JistError> {
+    let delimiter = last.then_some(b'\n');
+    for token in Lexer::new(chunk.iter().copied().chain(delimiter), BufferType::Span) {
+        let (first, end) = token_pos(&token.buf)?;
+        if token.kind == Invalid {
+            return Err(JistError::malformed(Some(first + pos), "invalid token"));
+        }
+        f(token.kind, &chunk[first as usize..(end as usize).min(chunk.len())], first + pos)?;
+    }
+    Ok(())
+}
+
+fn finish(schema_tape: &str) -> Result<Value, JistError> {
+    serde_json::from_str(schema_tape).map_err(|e| JistError::malformed(None, e))
+}
+
+// Lex `chunk`, which starts at stream position `pos` and ends on a token boundary, adding to the schema tape
+fn lex(chunk: &[u8], pos: u64, last: bool, struct_t: &mut JStructTracker, mut schema_tape: String, mut stats: Option<&mut Stats>) -> Result<String, JistError> {
+    // the lexer needs a delimiter after the last token of the input
+    let delimiter = last.then_some(b'\n');
+    for token in Lexer::new(chunk.iter().copied().chain(delimiter), BufferType::Span) {
+        if let Some(stats) = &mut stats {
+            let (first, end) = token_pos(&token.buf)?;
+            stats.token(token.kind.clone(), &chunk[first as usize..(end as usize).min(chunk.len())]);
+        }
+        // token processing
+        match token.kind {
+            Invalid => {
+                let (first, _) = token_pos(&token.buf)?;
+                return Err(JistError::malformed(Some(first + pos), "invalid token"));
+            }
+            TokenType::CurlyOpen => {
+                struct_t.last_open.push(TokenType::CurlyOpen);
+                schema_tape = schema_tape + "{";
+            }
+            TokenType::CurlyClose => {
+                schema_tape = schema_tape + "}";
+
+                let last_curly_open = struct_t
+                    .last_open
+                    .iter()
+                    .filter(|sym| **sym == TokenType::CurlyOpen)
+                    .last();
+                if last_curly_open.is_none() {
+                    let (first, _) = token_pos(&token.buf)?;
+                    return Err(JistError::malformed(Some(first + pos), "missing opening curly brace"));
+                }
+
+                struct_t.last_open.pop();
+            }
+            TokenType::BracketOpen => {
+                struct_t.arr_idx.push(0);
+                struct_t.last_open.push(TokenType::BracketOpen);
+                schema_tape = schema_tape + "[";
+            }
+            TokenType::BracketClose => {
+                schema_tape = schema_tape + "]";
 
-    Ok(json.to_string())
+                let last_bracket_open = struct_t
+                    .last_open
+                    .iter()
+                    .filter(|sym| **sym == TokenType::BracketOpen)
+                    .last();
+                if last_bracket_open.is_none() {
+                    let (first, _) = token_pos(&token.buf)?;
+                    return Err(JistError::malformed(Some(first + pos), "missing opening bracket"));
+                }
+
+                struct_t.arr_idx.pop();
+                struct_t.last_open.pop();
+            }
+            TokenType::Comma => {
+                if struct_t.last_open.last() == Some(&TokenType::BracketOpen) {
+                    let arr_idx_len = struct_t.arr_idx.len();
+                    struct_t.arr_idx[arr_idx_len - 1] += 1;
+                }
+                schema_tape = schema_tape + ",";
+            }
+            TokenType::Colon => schema_tape = schema_tape + ":",
+            TokenType::BooleanTrue | TokenType::BooleanFalse => {
+                schema_tape = schema_tape + "\"boolean\""
+            }
+            TokenType::Number => schema_tape = schema_tape + "\"number\"",
+            TokenType::Null => schema_tape = schema_tape + "\"null\"",
+            _ => {} // String type can be an object key which requires special handling
+        }
+
+        if let Some(token_type) = struct_t.last_open.last() {
+            if token_type.eq(&TokenType::BracketOpen) {
+                if token.kind == TokenType::String {
+                    schema_tape = schema_tape + "\"string\"";
+                }
+            } else if token_type.eq(&TokenType::CurlyOpen) {
+                if token.kind == TokenType::String && struct_t.last_token_key_delimiter {
+                    let (first, end) = token_pos(&token.buf)?;
+                    let key = String::from_utf8_lossy(&chunk[first as usize..end as usize]);
+                    schema_tape = schema_tape + &key;
+                    struct_t.last_token_key_delimiter = false;
+                } else if token.kind == TokenType::String {
+                    schema_tape = schema_tape + "\"string\"";
+                }
+
+                if token.kind == TokenType::CurlyOpen || token.kind == TokenType::Comma {
+                    struct_t.last_token_key_delimiter = true;
+                }
+            }
+        }
+    }
+    Ok(schema_tape)
 }
 
 #[cfg(test)]
@@ -214,8 +264,7 @@ mod tests {
     fn test_parse() {
         let f = File::open("test.json").unwrap();
         let mut reader = BufReader::new(&f);
-        let mut seeker = BufReader::new(&f);
-        let result = _parse(&mut reader, &mut seeker);
+        let result = _parse(&mut reader, None);
         print!("{:?}", result.unwrap());
     }
 }
\ No newline at end of file
@@ -1,38 +1,340 @@
-use crate::utils::{array_ind, parse_search_key, sanitize_output};
+use crate::error::JistError;
+use crate::input::Mapped;
+use crate::model::json_path::JsonPath;
+use crate::model::path_segment::{select, PathSegment};
+use crate::utils::sanitize_output;
+use crate::windows::{array_windows, window_heads};
 use autocxx::prelude::*;
+use serde_json::{json, Value};
+use std::fs;
+use std::pin::Pin;
 
 include_cpp! {
     #include "simdjson/wrapper.h"
     safety!(unsafe)
+    generate_pod!("Status")
     generate!("value_at_path")
+    generate!("values_at_path")
+    generate!("values_at_paths")
+    generate!("values_at_window")
+    generate!("records_at_path")
+    generate!("record_schemas")
 }
+
+// mirrors the STATUS_* codes in wrapper.h
+const STATUS_OK: i32 = 0;
+const STATUS_NOT_FOUND: i32 = 1;
+const STATUS_MALFORMED_JSON: i32 = 2;
+const STATUS_INVALID_PATH: i32 = 4;
+const STATUS_CAPACITY: i32 = 5;
+
+// simdjson parses documents up to 4GB, larger files are searched a window of the top-level array at a time
+const MAX_DOCUMENT: u64 = u32::MAX as u64;
+const WINDOW_SIZE: usize = 1 << 30;
+// NDJSON text is handed over this much at a time, up to the end of a line, so that a batch of records
+// only copies its own part of the input. Files are mapped by the C++ side instead.
+const RECORD_WINDOW: usize = 64 << 20;
+
+// turn the status of a bridge call into the result it describes
+fn check(result: String, status: &ffi::Status) -> Result<String, JistError> {
+    match status.code {
+        STATUS_OK => Ok(result),
+        STATUS_NOT_FOUND => Err(JistError::NotFound),
+        STATUS_MALFORMED_JSON => Err(JistError::malformed(u64::try_from(status.offset).ok(), result)),
+        STATUS_INVALID_PATH => Err(JistError::InvalidPath("invalid search key")),
+        STATUS_CAPACITY => Err(JistError::CapacityExceeded),
+        _ => Err(JistError::Io(result)),
+    }
+}
+
 pub fn search(
     haystack: Option<&str>,
     file: Option<&str>,
     search_key: &str,
-) -> Result<String, &'static str> {
-    let search_path = parse_search_key(search_key);
-    if search_path.is_empty() {
-        return Err("search key must not be empty");
+) -> Result<String, JistError> {
+    let query = JsonPath::parse(search_key).map_err(JistError::InvalidPath)?;
+    if query.segments.is_empty() && !query.rfc {
+        return Err(JistError::InvalidInput("search key must not be empty"));
+    }
+
+    // validate that some data is sent
+    let haystack_s = haystack.unwrap_or("");
+    let file_s = file.unwrap_or("");
+    if haystack_s.is_empty() && file_s.is_empty() {
+        return Err(JistError::InvalidInput("search data must not be empty"));
     }
 
-    let search_key_global = if array_ind(search_path[0].as_str()) < 0 {
-        format!("$.{}", search_key)
-    } else {
-        format!("${}", search_key)
+    // recursive descent of dotted paths reports concrete paths, which only the streaming parser tracks
+    if query.with_paths() {
+        return Err(JistError::UnsupportedPath);
+    }
+    if let Some(file) = file {
+        if too_large(file)? {
+            return search_windows(file, &query, WINDOW_SIZE);
+        }
+    }
+    // at_path only knows plain keys and non-negative indices, and compares keys without decoding escapes
+    let plain = |key: &str| {
+        !key.is_empty() && key.is_ascii() && !key.contains(['/', '~', '.', '[', ']', '\'', '"', '\\', ' '])
     };
+    if query.rfc || !query.segments.iter().all(|segment| match segment {
+        PathSegment::Key(k) => plain(k),
+        PathSegment::Index(i) => *i >= 0,
+        _ => false,
+    }) {
+        return search_all(haystack_s, file_s, &query.segments, query.is_definite());
+    }
 
-    // validate that some data is sent
+    // rebuilt from the segments since the search key may have been a JSON pointer
+    let search_key_global = query.segments.iter().fold("$".to_string(), |path, segment| match segment {
+        PathSegment::Key(k) => path + "." + k,
+        PathSegment::Index(i) => format!("{}[{}]", path, i),
+        _ => path,
+    });
+
+    let mut status = ffi::Status { code: STATUS_OK, offset: -1 };
+    let result: String = ffi::value_at_path(haystack_s, file_s, search_key_global.as_str(), Pin::new(&mut status));
+    let result = check(result, &status)?;
+    sanitize_output(&result).map_err(|e| JistError::malformed(None, e))
+}
+
+// Other paths are walked on the C++ side with the on-demand iterator. The path is handed over as a
+// JSON array where strings are keys, numbers are indices, null is a wildcard, `{"name": name}` is
+// an RFC 9535 name selector and slices are `{"slice": [start, end, step]}`. Filters, unions and
+// recursive descent need the candidate values, so the path is only handed over up to the first of
+// them and the rest is applied to the returned values.
+fn search_all(
+    haystack_s: &str,
+    file_s: &str,
+    segments: &[PathSegment],
+    definite: bool,
+) -> Result<String, JistError> {
+    let (plan, split) = plan(segments);
+    let mut status = ffi::Status { code: STATUS_OK, offset: -1 };
+    let result: String = ffi::values_at_path(haystack_s, file_s, plan.to_string().as_str(), Pin::new(&mut status));
+    let values = serde_json::from_str::<Value>(&check(result, &status)?).map_err(|e| JistError::malformed(None, e))?;
+    match finish(values, &segments[split..])? {
+        Value::Array(values) if definite => match values.first() {
+            Some(value) => sanitize_output(&value.to_string()).map_err(|e| JistError::malformed(None, e)),
+            None => Err(JistError::NotFound),
+        },
+        values => Ok(values.to_string()),
+    }
+}
+
+fn too_large(file: &str) -> Result<bool, JistError> {
+    Ok(fs::metadata(file)?.len() >= MAX_DOCUMENT)
+}
+
+// A file too large for simdjson is searched a window of its top-level array at a time, with the
+// first segment of the path turned into the matching segment of each window. Paths starting with an
+// index go straight to the window holding the element. Only indices, wildcards and forward slices
+// can be split over windows, anything else is left to the streaming parser.
+pub(crate) fn search_windows(file: &str, query: &JsonPath, window_size: usize) -> Result<String, JistError> {
+    let Some((head, rest)) = query.segments.split_first() else {
+        return Err(JistError::UnsupportedPath);
+    };
+    if head.is_filter() {
+        return Err(JistError::UnsupportedPath);
+    }
+    let map = Mapped::open(file)?.ok_or(JistError::CapacityExceeded)?;
+    let windows = array_windows(&map, window_size, |pos| map.release(pos)).ok_or(JistError::CapacityExceeded)?;
+    let heads = window_heads(&windows, head).ok_or(JistError::UnsupportedPath)?;
+
+    let mut found = Vec::new();
+    for (window, head) in heads {
+        let segments: Vec<PathSegment> = std::iter::once(head).chain(rest.iter().cloned()).collect();
+        let (plan, split) = plan(&segments);
+        let mut status = ffi::Status { code: STATUS_OK, offset: -1 };
+        let result: String = ffi::values_at_window(file, plan.to_string().as_str(), window.start, window.end, Pin::new(&mut status));
+        let values = serde_json::from_str::<Value>(&check(result, &status)?).map_err(|e| JistError::malformed(None, e))?;
+        if let Value::Array(values) = finish(values, &segments[split..])? {
+            found.extend(values);
+        }
+    }
+
+    if query.is_definite() {
+        return match found.first() {
+            Some(value) => sanitize_output(&value.to_string()).map_err(|e| JistError::malformed(None, e)),
+            None => Err(JistError::NotFound),
+        };
+    }
+    Ok(Value::Array(found).to_string())
+}
+
+// Several search keys looked up on a single parsed document, one JSON value per key: the value
+// itself for a definite path, otherwise the array of matches
+pub fn search_many(
+    haystack: Option<&str>,
+    file: Option<&str>,
+    search_keys: &[&str],
+) -> Result<Vec<Value>, JistError> {
+    let queries = search_keys
+        .iter()
+        .map(|key| JsonPath::parse(key).map_err(JistError::InvalidPath))
+        .collect::<Result<Vec<JsonPath>, _>>()?;
+    if queries.iter().any(|query| query.segments.is_empty() && !query.rfc) {
+        return Err(JistError::InvalidInput("search key must not be empty"));
+    }
     let haystack_s = haystack.unwrap_or("");
     let file_s = file.unwrap_or("");
     if haystack_s.is_empty() && file_s.is_empty() {
-        return Err("search data must not be empty");
+        return Err(JistError::InvalidInput("search data must not be empty"));
     }
+    if queries.iter().any(|query| query.with_paths()) {
+        return Err(JistError::UnsupportedPath);
+    }
+    if file.is_some_and(|file| too_large(file).unwrap_or(false)) {
+        return Err(JistError::CapacityExceeded);
+    }
+
+    let plans: Vec<(Value, usize)> = queries.iter().map(|query| plan(&query.segments)).collect();
+    let all_plans = Value::Array(plans.iter().map(|(plan, _)| plan.clone()).collect());
+    let mut status = ffi::Status { code: STATUS_OK, offset: -1 };
+    let result: String = ffi::values_at_paths(haystack_s, file_s, all_plans.to_string().as_str(), Pin::new(&mut status));
+    let results = match serde_json::from_str::<Value>(&check(result, &status)?) {
+        Ok(Value::Array(results)) => results,
+        Ok(_) => return Err(JistError::malformed(None, "expected an array of results")),
+        Err(e) => return Err(JistError::malformed(None, e)),
+    };
+    queries
+        .iter()
+        .zip(plans)
+        .zip(results)
+        .map(|((query, (_, split)), values)| match finish(values, &query.segments[split..])? {
+            Value::Array(values) if query.is_definite() => values.into_iter().next().ok_or(JistError::NotFound),
+            values => Ok(values),
+        })
+        .collect()
+}
+
+// The matches of a search key in every record of NDJSON input, one JSON value per record: the value
+// itself for a definite path, otherwise the array of matches. Records where nothing matches are left
+// out. The records are parsed with simdjson's iterate_many and handed back in batches. A record too
+// large for a batch is a `CapacityExceeded` error, `buf_parser::search_records` reads any record.
+pub fn search_records(
+    haystack: Option<&str>,
+    file: Option<&str>,
+    search_key: &str,
+) -> Result<Vec<Value>, JistError> {
+    search_record_windows(haystack, file, search_key, RECORD_WINDOW)
+}
 
-    let result: String = ffi::value_at_path(haystack_s, file_s, search_key_global.as_str());
+// `search_records` with text handed over `window` bytes and the rest of a line at a time
+pub(crate) fn search_record_windows(
+    haystack: Option<&str>,
+    file: Option<&str>,
+    search_key: &str,
+    window: usize,
+) -> Result<Vec<Value>, JistError> {
+    let query = JsonPath::parse(search_key).map_err(JistError::InvalidPath)?;
+    if query.segments.is_empty() && !query.rfc {
+        return Err(JistError::InvalidInput("search key must not be empty"));
+    }
+    let haystack_s = haystack.unwrap_or("");
+    let file_s = file.unwrap_or("");
+    if haystack_s.is_empty() && file_s.is_empty() {
+        return Err(JistError::InvalidInput("search data must not be empty"));
+    }
+    if query.with_paths() {
+        return Err(JistError::UnsupportedPath);
+    }
+
+    let (plan, split) = plan(&query.segments);
+    let plan = plan.to_string();
+    let mut records = Vec::new();
+    let mut start = 0;
+    loop {
+        // positions in a window of text are relative to where it starts
+        let (input, base, end) = if haystack_s.is_empty() {
+            ("", 0, 0)
+        } else {
+            let end = line_end(haystack_s, start + window);
+            (&haystack_s[start..end], start, end)
+        };
+        let mut status = ffi::Status { code: STATUS_OK, offset: -1 };
+        let result: String = ffi::records_at_path(input, file_s, plan.as_str(), (start - base) as u64, Pin::new(&mut status));
+        let result = check(result, &status).map_err(|e| e.shifted(base as u64))?;
+        let mut batch = serde_json::from_str::<Value>(&result).map_err(|e| JistError::malformed(None, e))?;
+        let Value::Array(batch_records) = batch["records"].take() else {
+            return Err(JistError::malformed(None, "expected an array of records"));
+        };
+        for values in batch_records {
+            match finish(values, &query.segments[split..])? {
+                Value::Array(values) if query.is_definite() => records.extend(values.into_iter().next()),
+                Value::Array(values) if values.is_empty() => {}
+                values => records.push(values),
+            }
+        }
+        start = match batch["next"].as_u64() {
+            Some(next) => base + next as usize,
+            None if end < haystack_s.len() => end,
+            None => break,
+        };
+    }
+    if query.is_definite() && records.is_empty() {
+        return Err(JistError::NotFound);
+    }
+    Ok(records)
+}
+
+// the position after the first line break from `at` on, or the end of the text
+fn line_end(text: &str, at: usize) -> usize {
+    match text.as_bytes().get(at..) {
+        Some(rest) => rest.iter().position(|b| *b == b'\n').map_or(text.len(), |pos| at + pos + 1),
+        None => text.len(),
+    }
+}
+
+// The distinct schemas of the records of NDJSON input, in the format of the schema tape with the
+// elements of arrays deduplicated, each with the number of records that have it
+pub fn record_schemas(haystack: Option<&str>, file: Option<&str>) -> Result<Vec<(Value, u64)>, JistError> {
+    let haystack_s = haystack.unwrap_or("");
+    let file_s = file.unwrap_or("");
+    if haystack_s.is_empty() && file_s.is_empty() {
+        return Err(JistError::InvalidInput("Invalid input - empty data"));
+    }
+    let mut status = ffi::Status { code: STATUS_OK, offset: -1 };
+    let result: String = ffi::record_schemas(haystack_s, file_s, Pin::new(&mut status));
+    match serde_json::from_str::<Vec<(Value, u64)>>(&check(result, &status)?) {
+        Ok(schemas) => Ok(schemas),
+        Err(e) => Err(JistError::malformed(None, e)),
+    }
+}
+
+// the part of the path handed over to the C++ side, and where the remainder starts
+fn plan(segments: &[PathSegment]) -> (Value, usize) {
+    let split = segments
+        .iter()
+        .position(|x| matches!(x, PathSegment::Filter(_) | PathSegment::Union(_) | PathSegment::Descendant(_)))
+        .unwrap_or(segments.len());
+    let plan: Vec<Value> = segments[..split]
+        .iter()
+        .map(|x| match x {
+            PathSegment::Key(k) => Value::String(k.clone()),
+            PathSegment::Name(k) => json!({"name": k}),
+            PathSegment::Index(i) => Value::from(*i),
+            PathSegment::Wildcard => Value::Null,
+            PathSegment::Slice(start, end, step) => json!({"slice": [start, end, step]}),
+            PathSegment::Descendant(_) | PathSegment::Filter(_) | PathSegment::Union(_) => {
+                unreachable!("evaluated after the values are returned")
+            }
+        })
+        .collect();
+    (Value::Array(plan), split)
+}
 
-    if !result.is_empty() && result.eq("JIST_ERROR_FILE_TOO_LARGE") {
-        return Err("JIST_ERROR_FILE_TOO_LARGE");
+// apply the rest of the path to the values returned for a plan
+fn finish(values: Value, rest: &[PathSegment]) -> Result<Value, JistError> {
+    match values {
+        Value::Array(values) if !rest.is_empty() => {
+            let mut selected = Vec::new();
+            for value in &values {
+                select(value, rest, "", &mut selected);
+            }
+            Ok(Value::Array(selected.into_iter().map(|(_, v)| v.clone()).collect()))
+        }
+        Value::Array(values) => Ok(Value::Array(values)),
+        _ => Err(JistError::malformed(None, "expected an array of values")),
     }
-    Ok(sanitize_output(&result))
 }
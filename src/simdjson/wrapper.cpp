@@ -2,8 +2,29 @@
 #include <iostream>
 #include <fstream>
 #include <filesystem>
+#include <vector>
+#include <algorithm>
+#include <stdexcept>
+#include <unordered_map>
+#ifndef _WIN32
+#include <fcntl.h>
+#include <sys/mman.h>
+#include <sys/stat.h>
+#include <unistd.h>
+#endif
 
-std::string get_result(simdjson::simdjson_result<simdjson::ondemand::document> &doc, std::string path)
+struct PathSegment
+{
+    // NAME is an RFC 9535 name selector, a KEY that never addresses array elements
+    enum Kind { KEY, NAME, INDEX, WILDCARD, SLICE } kind;
+    std::string key;
+    int64_t index;
+    // python style slice, missing bounds are flagged
+    bool has_start, has_end;
+    int64_t start, end, step;
+};
+
+std::string get_result(simdjson::ondemand::document &doc, std::string path)
 {
     auto result = doc.at_path(path);
     std::ostringstream oss;
@@ -11,44 +32,726 @@ std::string get_result(simdjson::simdjson_result<simdjson::ondemand::document> &
     return std::string(oss.str());
 }
 
-rust::String value_at_path(rust::Str input_str, rust::Str file_name, rust::Str json_path)
+// The JSON to parse: a padded copy of the input text, or a file mapped into memory so that it isn't
+// copied into RAM. simdjson reads up to SIMDJSON_PADDING bytes past the end, which a mapping doesn't
+// have, so the full pages of the file are mapped over zeroed anonymous memory and only the last
+// partial page is copied.
+class JsonInput
 {
-    try
+public:
+    simdjson::padded_string_view view;
+    // byte position of the view in the file, for locating errors
+    uint64_t base = 0;
+
+    JsonInput() = default;
+    JsonInput(const JsonInput &) = delete;
+    JsonInput &operator=(const JsonInput &) = delete;
+    ~JsonInput()
     {
-        if (file_name.empty() && input_str.empty()) {
-            return rust::String(std::string("Error: Input data not provided"));
+#ifndef _WIN32
+        if (mapped != nullptr)
+        {
+            munmap(mapped, mapped_len);
         }
+#endif
+    }
 
-        simdjson::ondemand::parser parser;
-        std::string json_path_s(json_path.data(), json_path.size());
-
+    void load(rust::Str input_str, rust::Str file_name)
+    {
         if (input_str.empty())
         {
             std::filesystem::path abs_path = std::filesystem::absolute(std::string_view(file_name.data(), file_name.size()));
-            auto json = simdjson::padded_string::load(abs_path.string());
-            auto doc = parser.iterate(json);
-            return rust::String(get_result(doc, json_path_s));
+#ifndef _WIN32
+            map_file(abs_path.string(), 0, UINT64_MAX);
+            return;
+#else
+            text = simdjson::padded_string::load(abs_path.string());
+#endif
         }
         else
         {
-            auto json = simdjson::padded_string(std::string_view(input_str.data(), input_str.size()));
-            auto doc = parser.iterate(json);
-            return rust::String(get_result(doc, json_path_s));
+            text = simdjson::padded_string(std::string_view(input_str.data(), input_str.size()));
         }
+        view = simdjson::padded_string_view(text.data(), text.size(), text.size() + simdjson::SIMDJSON_PADDING);
     }
-    catch (const simdjson::simdjson_error &e)
+
+    // The `len` bytes of a file from `start` on, where the first and the last byte separate top-level
+    // array elements. They are replaced by brackets so that the elements in between read as an array.
+    void load_window(rust::Str file_name, uint64_t start, uint64_t len)
+    {
+        std::filesystem::path abs_path = std::filesystem::absolute(std::string_view(file_name.data(), file_name.size()));
+        char *window;
+#ifndef _WIN32
+        map_file(abs_path.string(), start, len);
+        // the mapping is writable
+        window = const_cast<char *>(view.data());
+#else
+        std::ifstream in(abs_path, std::ios::binary);
+        text = simdjson::padded_string(len);
+        in.seekg(start);
+        in.read(text.data(), len);
+        if (!in)
+        {
+            throw simdjson::simdjson_error(simdjson::IO_ERROR);
+        }
+        view = simdjson::padded_string_view(text.data(), text.size(), text.size() + simdjson::SIMDJSON_PADDING);
+        window = text.data();
+        base = start;
+#endif
+        if (view.length() < 2)
+        {
+            throw simdjson::simdjson_error(simdjson::EMPTY);
+        }
+        window[0] = '[';
+        window[view.length() - 1] = ']';
+    }
+
+    const char *data() const
+    {
+        return view.data();
+    }
+
+private:
+    simdjson::padded_string text;
+    char *mapped = nullptr;
+    size_t mapped_len = 0;
+
+#ifndef _WIN32
+    // Maps `len` bytes of the file from `start` on, or up to its end. The mapping is private, so
+    // writing to it doesn't change the file.
+    void map_file(const std::string &path, uint64_t start, uint64_t len)
+    {
+        int fd = open(path.c_str(), O_RDONLY);
+        struct stat st;
+        if (fd < 0 || fstat(fd, &st) != 0)
+        {
+            if (fd >= 0)
+            {
+                close(fd);
+            }
+            throw simdjson::simdjson_error(simdjson::IO_ERROR);
+        }
+        uint64_t size = st.st_size;
+        start = std::min(start, size);
+        uint64_t end = len > size - start ? size : start + len;
+        size_t page = sysconf(_SC_PAGESIZE);
+        // mappings start on a page boundary, only pages that lie within the file are mapped from it
+        uint64_t offset = start - start % page;
+        uint64_t file_pages_end = std::min(size - size % page, (end + page - 1) / page * page);
+        size_t whole_pages = file_pages_end > offset ? file_pages_end - offset : 0;
+        mapped_len = (end - offset + simdjson::SIMDJSON_PADDING + page - 1) / page * page;
+        void *region = mmap(nullptr, mapped_len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+        if (region == MAP_FAILED)
+        {
+            close(fd);
+            throw simdjson::simdjson_error(simdjson::MEMALLOC);
+        }
+        mapped = static_cast<char *>(region);
+        bool ok = whole_pages == 0 || mmap(mapped, whole_pages, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_FIXED, fd, offset) != MAP_FAILED;
+        for (uint64_t done = offset + whole_pages; ok && done < end;)
+        {
+            ssize_t n = pread(fd, mapped + (done - offset), end - done, done);
+            ok = n > 0;
+            done += ok ? n : 0;
+        }
+        close(fd);
+        if (!ok)
+        {
+            throw simdjson::simdjson_error(simdjson::IO_ERROR);
+        }
+        if (whole_pages > 0)
+        {
+            madvise(mapped, whole_pages, MADV_SEQUENTIAL);
+        }
+        view = simdjson::padded_string_view(mapped + (start - offset), end - start, mapped_len - (start - offset));
+        base = start;
+    }
+#endif
+};
+
+// The search path arrives as a JSON array: strings are keys, numbers are indices, null is a wildcard,
+// names are {"name": name} and slices are {"slice": [start, end, step]}
+std::vector<PathSegment> parse_path(rust::Str json_path)
+{
+    simdjson::dom::parser path_parser;
+    simdjson::padded_string path_json(std::string_view(json_path.data(), json_path.size()));
+    simdjson::dom::array segments = path_parser.parse(path_json).get_array();
+
+    std::vector<PathSegment> path;
+    for (simdjson::dom::element segment : segments)
     {
-        if (e.error() == simdjson::error_code::MEMALLOC || e.error() == simdjson::error_code::CAPACITY) {
-            return rust::String(std::string("JIST_ERROR_FILE_TOO_LARGE"));
+        if (segment.is_null())
+        {
+            path.push_back({PathSegment::WILDCARD, "", 0});
+        }
+        else if (segment.is_string())
+        {
+            path.push_back({PathSegment::KEY, std::string(std::string_view(segment.get_string())), 0});
+        }
+        else if (segment.is_object() && segment["name"].error() == simdjson::SUCCESS)
+        {
+            path.push_back({PathSegment::NAME, std::string(std::string_view(segment["name"].get_string())), 0});
         }
-        return rust::String(std::string("JSON error: ") + e.what());
+        else if (segment.is_object())
+        {
+            simdjson::dom::array bounds = segment["slice"].get_array();
+            simdjson::dom::element start = bounds.at(0);
+            simdjson::dom::element end = bounds.at(1);
+            PathSegment slice{PathSegment::SLICE, "", 0};
+            slice.has_start = !start.is_null();
+            slice.start = slice.has_start ? int64_t(start.get_int64()) : 0;
+            slice.has_end = !end.is_null();
+            slice.end = slice.has_end ? int64_t(end.get_int64()) : 0;
+            slice.step = int64_t(bounds.at(2).get_int64());
+            path.push_back(slice);
+        }
+        else
+        {
+            path.push_back({PathSegment::INDEX, "", int64_t(segment.get_int64())});
+        }
+    }
+    return path;
+}
+
+// canonical non-negative array index written as a key
+bool is_index(const std::string &key)
+{
+    if (key.empty() || key.size() > 18 || (key.size() > 1 && key[0] == '0'))
+    {
+        return false;
+    }
+    return std::all_of(key.begin(), key.end(), [](char c) { return c >= '0' && c <= '9'; });
+}
+
+// Resolve slice bounds against the array length: [lo, hi) walking forward or (hi, lo] walking backward
+void slice_bounds(const PathSegment &segment, int64_t len, int64_t &lo, int64_t &hi)
+{
+    auto bound = [len](int64_t b, int64_t lower, int64_t upper) {
+        return std::clamp(b < 0 ? b + len : b, lower, upper);
+    };
+    if (segment.step > 0)
+    {
+        lo = segment.has_start ? bound(segment.start, 0, len) : 0;
+        hi = segment.has_end ? bound(segment.end, 0, len) : len;
+    }
+    else
+    {
+        lo = segment.has_start ? bound(segment.start, -1, len - 1) : len - 1;
+        hi = segment.has_end ? bound(segment.end, -1, len - 1) : -1;
+    }
+}
+
+void collect_matches(simdjson::ondemand::value value, const std::vector<PathSegment> &path, size_t pos, std::vector<std::string> &matches)
+{
+    if (pos == path.size())
+    {
+        std::string_view raw = simdjson::to_json_string(value);
+        matches.emplace_back(raw);
+        return;
+    }
+
+    const PathSegment &segment = path[pos];
+    simdjson::ondemand::json_type type = value.type();
+    bool by_name = segment.kind == PathSegment::KEY || segment.kind == PathSegment::NAME;
+    if (type == simdjson::ondemand::json_type::object && (by_name || segment.kind == PathSegment::WILDCARD))
+    {
+        simdjson::ondemand::object object = value.get_object();
+        for (auto field : object)
+        {
+            // find_field compares raw keys, escapes have to be decoded to match quoted keys
+            if (by_name)
+            {
+                std::string_view key = field.unescaped_key();
+                if (key != segment.key)
+                {
+                    continue;
+                }
+                collect_matches(field.value(), path, pos + 1, matches);
+                return;
+            }
+            collect_matches(field.value(), path, pos + 1, matches);
+        }
+    }
+    else if (type == simdjson::ondemand::json_type::array && segment.kind != PathSegment::NAME && (segment.kind != PathSegment::KEY || is_index(segment.key)))
+    {
+        simdjson::ondemand::array array = value.get_array();
+        int64_t lo = 0, hi = INT64_MAX, step = 1;
+        if (segment.kind == PathSegment::KEY)
+        {
+            // numeric keys address array elements like JSON pointers do
+            lo = std::stoll(segment.key);
+            hi = lo + 1;
+        }
+        else if (segment.kind == PathSegment::INDEX)
+        {
+            // negative indices need the array length, which costs an extra pass over the array
+            lo = segment.index < 0 ? segment.index + int64_t(size_t(array.count_elements())) : segment.index;
+            if (lo < 0)
+            {
+                return;
+            }
+            hi = lo + 1;
+        }
+        else if (segment.kind == PathSegment::SLICE)
+        {
+            step = segment.step;
+            bool needs_len = step < 0 || (segment.has_start && segment.start < 0) || (segment.has_end && segment.end < 0);
+            slice_bounds(segment, needs_len ? int64_t(size_t(array.count_elements())) : INT64_MAX, lo, hi);
+            if (step == 0)
+            {
+                return;
+            }
+        }
+
+        // the on-demand iterator only walks forward, so a negative step collects per element and reverses
+        std::vector<std::vector<std::string>> backwards;
+        int64_t idx = 0;
+        for (auto element : array)
+        {
+            if (step > 0 && idx >= lo && idx < hi && (idx - lo) % step == 0)
+            {
+                collect_matches(element.value(), path, pos + 1, matches);
+            }
+            else if (step < 0 && idx <= lo && idx > hi && (lo - idx) % -step == 0)
+            {
+                backwards.emplace_back();
+                collect_matches(element.value(), path, pos + 1, backwards.back());
+            }
+            if (step > 0 && idx + 1 >= hi)
+            {
+                return;
+            }
+            idx++;
+        }
+        for (auto it = backwards.rbegin(); it != backwards.rend(); it++)
+        {
+            matches.insert(matches.end(), it->begin(), it->end());
+        }
+    }
+}
+
+// status code of a simdjson error
+int32_t status_code(simdjson::error_code error)
+{
+    switch (error)
+    {
+    case simdjson::NO_SUCH_FIELD:
+    case simdjson::INDEX_OUT_OF_BOUNDS:
+    case simdjson::OUT_OF_BOUNDS:
+    case simdjson::INCORRECT_TYPE:
+        return STATUS_NOT_FOUND;
+    case simdjson::MEMALLOC:
+    case simdjson::CAPACITY:
+        return STATUS_CAPACITY;
+    case simdjson::IO_ERROR:
+        return STATUS_IO;
+    case simdjson::INVALID_JSON_POINTER:
+        return STATUS_INVALID_PATH;
+    default:
+        return STATUS_MALFORMED_JSON;
+    }
+}
+
+// Runs `body`, which stores its result in `result`, and turns whatever it throws into the status of
+// the call with the message in `result`. Malformed JSON is reported without an offset, the caller
+// knows where it was found.
+template <class F>
+Status guarded(rust::String &result, F &&body)
+{
+    try
+    {
+        body();
+        return {STATUS_OK, -1};
+    }
+    catch (const simdjson::simdjson_error &e)
+    {
+        result = rust::String(e.what());
+        return {status_code(e.error()), -1};
+    }
+    catch (const std::bad_alloc &e)
+    {
+        result = rust::String(e.what());
+        return {STATUS_CAPACITY, -1};
     }
     catch (const std::exception &e)
     {
-        return rust::String(std::string("Error: ") + e.what());
+        result = rust::String(e.what());
+        return {STATUS_IO, -1};
     }
     catch (...)
     {
-        return rust::String("Unknown error occurred");
+        result = rust::String("Unknown error occurred");
+        return {STATUS_IO, -1};
+    }
+}
+
+// byte position in the input where iterating the document stopped, -1 when unknown
+int64_t location(const JsonInput &json, simdjson::ondemand::document &doc)
+{
+    const char *at;
+    if (doc.current_location().get(at) != simdjson::SUCCESS)
+    {
+        return -1;
     }
-}
\ No newline at end of file
+    return json.base + (at - json.data());
+}
+
+// the input is text or a file
+void require_input(rust::Str input_str, rust::Str file_name)
+{
+    if (file_name.empty() && input_str.empty())
+    {
+        throw std::invalid_argument("Input data not provided");
+    }
+}
+
+// Like guarded for a search of a single document. `body` loads the input into the JsonInput it is
+// handed, calls `iterate` to start iterating the document and returns its result. Malformed JSON
+// found from then on is located where iterating the document stopped, which is why the document
+// lives out here rather than in the body.
+template <class F>
+rust::String guarded_document(Status &status, F &&body)
+{
+    JsonInput json;
+    simdjson::ondemand::parser parser;
+    simdjson::ondemand::document doc;
+    bool iterating = false;
+    rust::String result;
+    status = guarded(result, [&] {
+        auto iterate = [&]() -> simdjson::ondemand::document & {
+            doc = parser.iterate(json.view);
+            iterating = true;
+            return doc;
+        };
+        result = rust::String(body(json, iterate));
+    });
+    if (status.code == STATUS_MALFORMED_JSON && iterating)
+    {
+        status.offset = location(json, doc);
+    }
+    return result;
+}
+
+rust::String value_at_path(rust::Str input_str, rust::Str file_name, rust::Str json_path, Status &status)
+{
+    return guarded_document(status, [&](JsonInput &json, auto &iterate) {
+        require_input(input_str, file_name);
+
+        std::string json_path_s(json_path.data(), json_path.size());
+
+        json.load(input_str, file_name);
+        return get_result(iterate(), json_path_s);
+    });
+}
+
+// every value matching the path as a JSON array of raw values, for a document or a record of a stream
+template <typename Document>
+std::string collect_path(Document &doc, const std::vector<PathSegment> &path)
+{
+    std::vector<std::string> matches;
+    simdjson::ondemand::json_type root_type = doc.type();
+    if (root_type == simdjson::ondemand::json_type::object || root_type == simdjson::ondemand::json_type::array)
+    {
+        collect_matches(doc.get_value(), path, 0, matches);
+    }
+
+    std::string result = "[";
+    for (size_t i = 0; i < matches.size(); i++)
+    {
+        if (i > 0)
+        {
+            result += ",";
+        }
+        result += matches[i];
+    }
+    result += "]";
+    return result;
+}
+
+// Returns every value matching the path as a JSON array of raw values
+rust::String values_at_path(rust::Str input_str, rust::Str file_name, rust::Str json_path, Status &status)
+{
+    return guarded_document(status, [&](JsonInput &json, auto &iterate) {
+        require_input(input_str, file_name);
+
+        std::vector<PathSegment> path = parse_path(json_path);
+
+        json.load(input_str, file_name);
+        return collect_path(iterate(), path);
+    });
+}
+
+// Several paths over one parsed document, given as a JSON array of paths in the values_at_path
+// format. Returns a JSON array holding the matches of each path. The structural index is only built
+// once, the document is rewound before every path.
+rust::String values_at_paths(rust::Str input_str, rust::Str file_name, rust::Str json_paths, Status &status)
+{
+    return guarded_document(status, [&](JsonInput &json, auto &iterate) {
+        require_input(input_str, file_name);
+
+        simdjson::dom::parser paths_parser;
+        simdjson::padded_string paths_json(std::string_view(json_paths.data(), json_paths.size()));
+        std::vector<std::vector<PathSegment>> paths;
+        for (simdjson::dom::element path : paths_parser.parse(paths_json).get_array())
+        {
+            paths.push_back(parse_path(rust::Str(simdjson::to_string(path))));
+        }
+
+        json.load(input_str, file_name);
+        simdjson::ondemand::document &doc = iterate();
+        std::string all = "[";
+        for (size_t i = 0; i < paths.size(); i++)
+        {
+            if (i > 0)
+            {
+                all += ",";
+                doc.rewind();
+            }
+            all += collect_path(doc, paths[i]);
+        }
+        all += "]";
+        return all;
+    });
+}
+
+// Like values_at_path for the top-level array elements of a file from byte `start`, the '[' or ','
+// before the first of them, to byte `end`, the ',' or ']' after the last. The window is read as an
+// array of its own, so files larger than simdjson can parse at once are searched a window at a time.
+rust::String values_at_window(rust::Str file_name, rust::Str json_path, uint64_t start, uint64_t end, Status &status)
+{
+    return guarded_document(status, [&](JsonInput &json, auto &iterate) {
+        if (file_name.empty() || end <= start)
+        {
+            throw std::invalid_argument("Input window not provided");
+        }
+
+        std::vector<PathSegment> path = parse_path(json_path);
+
+        json.load_window(file_name, start, end - start + 1);
+        return collect_path(iterate(), path);
+    });
+}
+
+// iterate_many parses records in batches of this many bytes, a record can't be any larger
+const size_t RECORD_BATCH_SIZE = 4 << 20;
+// records_at_path returns once this much output is collected, the caller asks for the rest
+const size_t RECORD_OUTPUT_SIZE = 16 << 20;
+
+// whether the record at `at` is too large for a batch of iterate_many, records are single lines
+bool oversized(const JsonInput &json, size_t at)
+{
+    std::string_view rest(json.data() + at, json.view.length() - at);
+    size_t end = rest.find('\n');
+    return (end == std::string_view::npos ? rest.size() : end) > RECORD_BATCH_SIZE;
+}
+
+// Evaluates the path against every record of NDJSON input from byte `start` on, with the path in
+// the values_at_path format. Returns {"records": [...], "next": next} holding the matches of each
+// record as a JSON array, where `next` is the byte position to continue from, -1 after the last record.
+rust::String records_at_path(rust::Str input_str, rust::Str file_name, rust::Str json_path, uint64_t start, Status &status)
+{
+    JsonInput json;
+    simdjson::ondemand::parser parser;
+    // byte position of the record being parsed
+    uint64_t record = start;
+    size_t skip = 0;
+    rust::String result;
+    status = guarded(result, [&] {
+        require_input(input_str, file_name);
+
+        std::vector<PathSegment> path = parse_path(json_path);
+
+        // text input is only copied from `start` on, a file is mapped as a whole
+        if (input_str.empty())
+        {
+            json.load(input_str, file_name);
+            skip = std::min<size_t>(start, json.view.length());
+        }
+        else
+        {
+            size_t from = std::min<size_t>(start, input_str.size());
+            json.load(rust::Str(input_str.data() + from, input_str.size() - from), file_name);
+        }
+        simdjson::ondemand::document_stream stream = parser.iterate_many(json.data() + skip, json.view.length() - skip, RECORD_BATCH_SIZE);
+
+        std::string out = "{\"records\":[";
+        int64_t next = -1;
+        bool first = true;
+        for (auto it = stream.begin(); it != stream.end(); ++it)
+        {
+            record = start + it.current_index();
+            if (out.size() >= RECORD_OUTPUT_SIZE)
+            {
+                next = record;
+                break;
+            }
+            simdjson::ondemand::document_reference doc = *it;
+            if (!first)
+            {
+                out += ",";
+            }
+            first = false;
+            out += collect_path(doc, path);
+        }
+        if (next < 0 && stream.truncated_bytes() > 0)
+        {
+            record = start + json.view.length() - skip - stream.truncated_bytes();
+            throw simdjson::simdjson_error(simdjson::INCOMPLETE_ARRAY_OR_OBJECT);
+        }
+        out += "],\"next\":" + std::to_string(next) + "}";
+        result = rust::String(out);
+    });
+    // malformed JSON is located at the start of its record, a record larger than a batch is left to
+    // the streaming parser
+    if (status.code == STATUS_MALFORMED_JSON && oversized(json, skip + (record - start)))
+    {
+        status.code = STATUS_CAPACITY;
+    }
+    else if (status.code == STATUS_MALFORMED_JSON)
+    {
+        status.offset = record;
+    }
+    return result;
+}
+
+// schema type of a scalar, like in the schema tape
+const char *scalar_schema(simdjson::ondemand::json_type type)
+{
+    switch (type)
+    {
+    case simdjson::ondemand::json_type::number:
+        return "\"number\"";
+    case simdjson::ondemand::json_type::boolean:
+        return "\"boolean\"";
+    case simdjson::ondemand::json_type::null:
+        return "\"null\"";
+    default:
+        return "\"string\"";
+    }
+}
+
+// Appends the schema of a value: objects keep their keys as written, arrays hold the distinct
+// schemas of their elements and scalars are replaced by their type. `elements` is scratch space
+// holding the (position, length) in `out` of the element schemas of the arrays being walked.
+void value_schema(simdjson::ondemand::value value, std::string &out, std::vector<std::pair<size_t, size_t>> &elements)
+{
+    simdjson::ondemand::json_type type = value.type();
+    if (type == simdjson::ondemand::json_type::object)
+    {
+        out += "{";
+        bool first = true;
+        for (auto field : value.get_object())
+        {
+            if (!first)
+            {
+                out += ",";
+            }
+            first = false;
+            std::string_view key = field.escaped_key();
+            out += "\"";
+            out += key;
+            out += "\":";
+            value_schema(field.value(), out, elements);
+        }
+        out += "}";
+    }
+    else if (type == simdjson::ondemand::json_type::array)
+    {
+        // element schemas are appended in place and dropped again when they were seen before
+        out += "[";
+        size_t base = elements.size();
+        for (auto element : value.get_array())
+        {
+            size_t start = out.size();
+            if (elements.size() > base)
+            {
+                out += ",";
+            }
+            size_t schema_start = out.size();
+            value_schema(element.value(), out, elements);
+            std::string_view schema(out.data() + schema_start, out.size() - schema_start);
+            bool seen = std::any_of(elements.begin() + base, elements.end(), [&](const std::pair<size_t, size_t> &e) {
+                return std::string_view(out.data() + e.first, e.second) == schema;
+            });
+            if (seen)
+            {
+                out.resize(start);
+            }
+            else
+            {
+                elements.emplace_back(schema_start, schema.size());
+            }
+        }
+        elements.resize(base);
+        out += "]";
+    }
+    else
+    {
+        out += scalar_schema(type);
+    }
+}
+
+// Schema of every record of NDJSON input, in the format of the schema tape. Returns the distinct
+// schemas as a JSON array of `[schema, records]` pairs, records of the same shape are only reported
+// once along with how many there were.
+rust::String record_schemas(rust::Str input_str, rust::Str file_name, Status &status)
+{
+    JsonInput json;
+    simdjson::ondemand::parser parser;
+    uint64_t record = 0;
+    rust::String result;
+    status = guarded(result, [&] {
+        require_input(input_str, file_name);
+
+        json.load(input_str, file_name);
+        simdjson::ondemand::document_stream stream = parser.iterate_many(json.data(), json.view.length(), RECORD_BATCH_SIZE);
+
+        // distinct schemas in the order they were first seen, with their number of records
+        std::unordered_map<std::string, size_t> seen;
+        std::vector<std::pair<std::string, uint64_t>> shapes;
+        std::string schema;
+        std::vector<std::pair<size_t, size_t>> elements;
+        for (auto it = stream.begin(); it != stream.end(); ++it)
+        {
+            record = it.current_index();
+            simdjson::ondemand::document_reference doc = *it;
+            simdjson::ondemand::json_type type = doc.type();
+            schema.clear();
+            if (type == simdjson::ondemand::json_type::object || type == simdjson::ondemand::json_type::array)
+            {
+                value_schema(doc.get_value(), schema, elements);
+            }
+            else
+            {
+                schema = scalar_schema(type);
+            }
+            auto found = seen.emplace(schema, shapes.size());
+            if (found.second)
+            {
+                shapes.emplace_back(schema, 0);
+            }
+            shapes[found.first->second].second++;
+        }
+        if (stream.truncated_bytes() > 0)
+        {
+            record = json.view.length() - stream.truncated_bytes();
+            throw simdjson::simdjson_error(simdjson::INCOMPLETE_ARRAY_OR_OBJECT);
+        }
+        std::string out = "[";
+        for (size_t i = 0; i < shapes.size(); i++)
+        {
+            if (i > 0)
+            {
+                out += ",";
+            }
+            out += "[" + shapes[i].first + "," + std::to_string(shapes[i].second) + "]";
+        }
+        out += "]";
+        result = rust::String(out);
+    });
+    if (status.code == STATUS_MALFORMED_JSON)
+    {
+        status.offset = record;
+    }
+    return result;
+}
@@ -2,4 +2,26 @@
 #include "simdjson/simdjson.h"
 #include "cxx.h"
 
-rust::String value_at_path(rust::Str input_str, rust::Str file_name, rust::Str json_path);
\ No newline at end of file
+// Outcome of a call across the bridge. Unless the code is STATUS_OK the returned string holds the
+// error message, and `offset` is the byte position of malformed JSON in the input (-1 when unknown).
+struct Status
+{
+    int32_t code;
+    int64_t offset;
+};
+
+const int32_t STATUS_OK = 0;
+const int32_t STATUS_NOT_FOUND = 1;
+const int32_t STATUS_MALFORMED_JSON = 2;
+const int32_t STATUS_IO = 3;
+const int32_t STATUS_INVALID_PATH = 4;
+const int32_t STATUS_CAPACITY = 5;
+
+rust::String value_at_path(rust::Str input_str, rust::Str file_name, rust::Str json_path, Status &status);
+rust::String values_at_path(rust::Str input_str, rust::Str file_name, rust::Str json_path, Status &status);
+rust::String values_at_paths(rust::Str input_str, rust::Str file_name, rust::Str json_paths, Status &status);
+rust::String values_at_window(rust::Str file_name, rust::Str json_path, uint64_t start, uint64_t end, Status &status);
+
+// NDJSON input, every record parsed in turn with iterate_many
+rust::String records_at_path(rust::Str input_str, rust::Str file_name, rust::Str json_path, uint64_t start, Status &status);
+rust::String record_schemas(rust::Str input_str, rust::Str file_name, Status &status);
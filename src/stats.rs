@@ -0,0 +1,407 @@
+use crate::formats::{detect, FORMATS};
+use crate::utils::json_str;
+use json_tools::TokenType;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+// values are counted exactly until a path has more distinct values than this, for its most common values
+const TOP_DISTINCT: usize = 100;
+// most common values reported
+const TOP_N: usize = 10;
+// values longer than this aren't counted exactly, a path with one has no most common values
+const TOP_VALUE_LEN: usize = 128;
+// key paths with statistics, further paths are left out
+const MAX_PATHS: usize = 10_000;
+// 2^12 HyperLogLog registers per path, for a standard error of about 1.6%
+const HLL_BITS: u32 = 12;
+// strings checked for a format per path, and after those one in this many
+const FORMAT_SAMPLE: u64 = 1000;
+const FORMAT_STRIDE: u64 = 64;
+
+// Statistics of the values at each key path, e.g. `items[*].id`, gathered token by token while the
+// schema tape is built. Memory is bounded by the number of paths, values are only kept as sketches.
+// Without `full` only what's needed to refine types is gathered: integers and string formats, and
+// with `enums` the distinct strings of paths that have at most that many.
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    full: bool,
+    enums: Option<usize>,
+    paths: BTreeMap<String, Field>,
+    // path of the value the walk is at
+    path: String,
+    // containers the walk is in
+    frames: Vec<Frame>,
+    // the next string is an object key
+    key_next: bool,
+    // top-level values, or elements of a top-level array
+    records: u64,
+    // paths were left out after MAX_PATHS
+    truncated: bool,
+}
+
+#[derive(Debug)]
+struct Frame {
+    array: bool,
+    // length of the path of the container
+    base: usize,
+}
+
+#[derive(Debug)]
+struct Field {
+    count: u64,
+    nulls: u64,
+    numbers: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+    // numbers without a fraction or exponent
+    integers: u64,
+    strings: u64,
+    min_len: usize,
+    max_len: usize,
+    // strings checked for a format, and how many had each of FORMATS
+    sampled: u64,
+    formats: [u64; FORMATS.len()],
+    distinct: HyperLogLog,
+    // exact counts of the values as written, None once there are too many
+    top: Option<HashMap<String, u64>>,
+    // distinct strings while there are at most as many as the enum cap, None once there are more
+    values: Option<BTreeSet<String>>,
+}
+
+impl Stats {
+    pub fn new(full: bool, enums: Option<usize>) -> Stats {
+        Stats { full, enums, ..Stats::default() }
+    }
+
+    // `raw` is the text of the token
+    pub fn token(&mut self, kind: TokenType, raw: &[u8]) {
+        match kind {
+            TokenType::CurlyOpen | TokenType::BracketOpen => {
+                let array = kind == TokenType::BracketOpen;
+                self.value(None, array);
+                self.frames.push(Frame { array, base: self.path.len() });
+                if array {
+                    self.path.push_str("[*]");
+                }
+                self.key_next = !array;
+            }
+            TokenType::CurlyClose | TokenType::BracketClose => {
+                if let Some(frame) = self.frames.pop() {
+                    self.path.truncate(frame.base);
+                }
+            }
+            TokenType::Comma => self.key_next = self.frames.last().is_some_and(|frame| !frame.array),
+            TokenType::String if self.key_next => {
+                let base = self.frames.last().map_or(0, |frame| frame.base);
+                self.path.truncate(base);
+                if base > 0 {
+                    self.path.push('.');
+                }
+                self.path.push_str(&json_str(raw));
+                self.key_next = false;
+            }
+            TokenType::String | TokenType::Number | TokenType::BooleanTrue | TokenType::BooleanFalse | TokenType::Null => {
+                self.value(Some((kind, raw)), false)
+            }
+            _ => {}
+        }
+    }
+
+    // a value at the current path, `scalar` unless it's an object or array
+    fn value(&mut self, scalar: Option<(TokenType, &[u8])>, array: bool) {
+        let top_level = match self.frames.as_slice() {
+            [] => !array,
+            [frame] => frame.array,
+            _ => false,
+        };
+        if top_level {
+            self.records += 1;
+        }
+        if !self.paths.contains_key(&self.path) {
+            if self.paths.len() >= MAX_PATHS {
+                self.truncated = true;
+                return;
+            }
+            self.paths.insert(self.path.clone(), Field::new(self.enums.is_some()));
+        }
+        let field = self.paths.get_mut(&self.path).unwrap();
+        field.count += 1;
+        if let Some((kind, raw)) = scalar {
+            field.scalar(kind, raw, self.full, self.enums);
+        }
+    }
+
+    // The types of a schema named more precisely where the values allow it. With `formats` "integer"
+    // for numbers without fractions and the format of strings, if at least that share of the sampled
+    // strings at a path have it. With enums {"enum": [...]} for strings with few distinct values.
+    pub fn refine(&self, schema: Value, formats: Option<f64>) -> Value {
+        self.refine_at(schema, &mut String::new(), formats)
+    }
+
+    fn refine_at(&self, schema: Value, path: &mut String, formats: Option<f64>) -> Value {
+        let base = path.len();
+        match schema {
+            Value::Object(members) => Value::Object(
+                members
+                    .into_iter()
+                    .map(|(key, member)| {
+                        if base > 0 {
+                            path.push('.');
+                        }
+                        path.push_str(&key);
+                        let member = self.refine_at(member, path, formats);
+                        path.truncate(base);
+                        (key, member)
+                    })
+                    .collect(),
+            ),
+            Value::Array(variants) => {
+                path.push_str("[*]");
+                let variants = variants.into_iter().map(|variant| self.refine_at(variant, path, formats)).collect();
+                path.truncate(base);
+                Value::Array(variants)
+            }
+            Value::String(kind) => {
+                let field = self.paths.get(path.as_str());
+                let values = field.and_then(|field| field.values.as_ref());
+                if let Some(values) = values.filter(|values| kind == "string" && !values.is_empty()) {
+                    return json!({ "enum": values });
+                }
+                let refined = match (kind.as_str(), formats) {
+                    ("number", Some(_)) => field.filter(|field| field.numbers > 0 && field.integers == field.numbers).map(|_| "integer"),
+                    ("string", Some(threshold)) => field.and_then(|field| field.format(threshold)).map(|(format, _)| format),
+                    _ => None,
+                };
+                Value::String(refined.map_or(kind, String::from))
+            }
+            schema => schema,
+        }
+    }
+
+    pub fn report(&self) -> Value {
+        let mut stats = Map::new();
+        stats.insert("records".to_string(), json!(self.records));
+        // the top-level value itself is at `$`
+        let paths = self.paths.iter().map(|(path, field)| (if path.is_empty() { "$".to_string() } else { path.clone() }, field.report()));
+        stats.insert("paths".to_string(), paths.collect());
+        if self.truncated {
+            stats.insert("truncated".to_string(), json!(true));
+        }
+        Value::Object(stats)
+    }
+}
+
+impl Field {
+    fn new(enums: bool) -> Field {
+        Field {
+            count: 0,
+            nulls: 0,
+            numbers: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            integers: 0,
+            strings: 0,
+            min_len: usize::MAX,
+            max_len: 0,
+            sampled: 0,
+            formats: [0; FORMATS.len()],
+            distinct: HyperLogLog::default(),
+            top: Some(HashMap::new()),
+            values: enums.then(BTreeSet::new),
+        }
+    }
+
+    fn scalar(&mut self, kind: TokenType, raw: &[u8], full: bool, enums: Option<usize>) {
+        match kind {
+            TokenType::Null => self.nulls += 1,
+            TokenType::Number => {
+                self.numbers += 1;
+                if !raw.iter().any(|b| matches!(b, b'.' | b'e' | b'E')) {
+                    self.integers += 1;
+                }
+                if let Some(n) = std::str::from_utf8(raw).ok().and_then(|text| text.parse::<f64>().ok()).filter(|_| full) {
+                    self.sum += n;
+                    self.min = self.min.min(n);
+                    self.max = self.max.max(n);
+                }
+            }
+            TokenType::String => {
+                self.strings += 1;
+                let sample = self.strings <= FORMAT_SAMPLE || self.strings.is_multiple_of(FORMAT_STRIDE);
+                if full || sample || self.values.is_some() {
+                    let text = json_str(raw);
+                    if full {
+                        let len = text.chars().count();
+                        self.min_len = self.min_len.min(len);
+                        self.max_len = self.max_len.max(len);
+                    }
+                    if sample {
+                        self.sampled += 1;
+                        if let Some(format) = detect(&text) {
+                            self.formats[format] += 1;
+                        }
+                    }
+                    if let Some(values) = &mut self.values {
+                        values.insert(text);
+                        if values.len() > enums.unwrap_or(0) || raw.len() > TOP_VALUE_LEN {
+                            self.values = None;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        if !full {
+            return;
+        }
+        self.distinct.insert(raw);
+        if raw.len() > TOP_VALUE_LEN {
+            self.top = None;
+        }
+        if let Some(top) = &mut self.top {
+            *top.entry(String::from_utf8_lossy(raw).into_owned()).or_insert(0) += 1;
+            if top.len() > TOP_DISTINCT {
+                self.top = None;
+            }
+        }
+    }
+
+    // the format most sampled strings have and the share of them that do, if it's at least `threshold`
+    fn format(&self, threshold: f64) -> Option<(&'static str, f64)> {
+        let (format, count) = self.formats.iter().enumerate().max_by_key(|(i, count)| (**count, std::cmp::Reverse(*i)))?;
+        let confidence = *count as f64 / self.sampled.max(1) as f64;
+        (*count > 0 && confidence >= threshold).then_some((FORMATS[format], confidence))
+    }
+
+    fn report(&self) -> Value {
+        let mut field = Map::new();
+        field.insert("count".to_string(), json!(self.count));
+        if self.nulls > 0 {
+            field.insert("nulls".to_string(), json!(self.nulls));
+        }
+        if self.numbers > 0 {
+            let mean = self.sum / self.numbers as f64;
+            field.insert("number".to_string(), json!({"min": number(self.min), "max": number(self.max), "mean": number(mean), "integers": self.integers}));
+        }
+        if self.strings > 0 {
+            field.insert("string".to_string(), json!({"min_length": self.min_len, "max_length": self.max_len}));
+        }
+        if let Some((format, confidence)) = self.format(0.0) {
+            field.insert("format".to_string(), json!({"name": format, "confidence": (confidence * 10000.0).round() / 10000.0}));
+        }
+        match &self.top {
+            Some(top) if !top.is_empty() => {
+                let mut values: Vec<(&String, &u64)> = top.iter().collect();
+                values.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+                let values: Vec<Value> = values
+                    .into_iter()
+                    .take(TOP_N)
+                    .map(|(value, count)| json!({"value": serde_json::from_str::<Value>(value).unwrap_or(Value::Null), "count": count}))
+                    .collect();
+                field.insert("distinct".to_string(), json!(top.len()));
+                field.insert("top".to_string(), Value::Array(values));
+            }
+            Some(_) => {}
+            None => {
+                field.insert("distinct".to_string(), json!(self.distinct.estimate()));
+            }
+        }
+        Value::Object(field)
+    }
+}
+
+// whether an object schema is the {"enum": [...]} of a string path rather than an object with an
+// `enum` key
+pub(crate) fn is_enum(members: &Map<String, Value>) -> bool {
+    members.len() == 1 && members.get("enum").and_then(Value::as_array).is_some_and(|values| !values.is_empty() && values.iter().all(|v| v.is_string() || v.is_null()))
+}
+
+// a whole number without a fraction, e.g. 3 rather than 3.0
+fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < (1u64 << 53) as f64 {
+        json!(n as i64)
+    } else {
+        json!(n)
+    }
+}
+
+// Approximate number of distinct values, registers are allocated with the first value
+#[derive(Debug, Default)]
+struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn insert(&mut self, value: &[u8]) {
+        if self.registers.is_empty() {
+            self.registers = vec![0; 1 << HLL_BITS];
+        }
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let index = (hash >> (64 - HLL_BITS)) as usize;
+        // position of the first set bit after the index bits, the sentinel bit caps it
+        let rank = ((hash << HLL_BITS) | (1 << (HLL_BITS - 1))).leading_zeros() as u8 + 1;
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    fn estimate(&self) -> u64 {
+        if self.registers.is_empty() {
+            return 0;
+        }
+        let m = self.registers.len() as f64;
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let estimate = 0.7213 / (1.0 + 1.079 / m) * m * m / sum;
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        // linear counting while many registers are still empty
+        if estimate <= 2.5 * m && zeros > 0 {
+            return (m * (m / zeros as f64).ln()).round() as u64;
+        }
+        estimate.round() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use json_tools::{BufferType, Lexer};
+
+    fn gather(text: &str) -> Value {
+        let mut stats = Stats::new(true, None);
+        for token in Lexer::new(text.bytes(), BufferType::Span) {
+            let (first, end) = crate::utils::token_pos(&token.buf).unwrap();
+            stats.token(token.kind, &text.as_bytes()[first as usize..end as usize]);
+        }
+        stats.report()
+    }
+
+    #[test]
+    fn stats_test() {
+        let stats = gather(r#"[{"id": 1, "tag": "a", "v": null}, {"id": 2.5, "tag": "abc", "v": [true]}, {"id": 3, "tag": "aé"}]"#);
+        assert_eq!(stats["records"], 3);
+        assert_eq!(stats["paths"]["[*]"], json!({"count": 3}));
+        assert_eq!(stats["paths"]["[*].id"]["number"], json!({"min": 1, "max": 3, "mean": 2.1666666666666665, "integers": 2}));
+        assert_eq!(stats["paths"]["[*].tag"]["string"], json!({"min_length": 1, "max_length": 3}));
+        assert_eq!(stats["paths"]["[*].tag"]["top"][0], json!({"value": "a", "count": 1}));
+        assert_eq!(stats["paths"]["[*].v"], json!({"count": 2, "nulls": 1, "distinct": 1, "top": [{"value": null, "count": 1}]}));
+        assert_eq!(stats["paths"]["[*].v[*]"]["top"], json!([{"value": true, "count": 1}]));
+
+        let stats = gather(r#"["10.0.0.1", "10.0.0.2", "ops@example.com", "x"]"#);
+        assert_eq!(stats["paths"]["[*]"]["format"], json!({"name": "ipv4", "confidence": 0.5}));
+
+        // NDJSON, one record per line
+        let stats = gather("{\"a\": {\"b\": 1}}\n{\"a\": {\"b\": 1}}\n");
+        assert_eq!(stats["records"], 2);
+        assert_eq!(stats["paths"]["a.b"]["top"], json!([{"value": 1, "count": 2}]));
+
+        // past TOP_DISTINCT values only the estimate is left
+        let many: Vec<String> = (0..5000).map(|i| format!("\"v{}\"", i)).collect();
+        let stats = gather(&format!("[{}]", many.join(",")));
+        let distinct = stats["paths"]["[*]"]["distinct"].as_u64().unwrap();
+        assert!((4800..5200).contains(&distinct), "{}", distinct);
+        assert_eq!(stats["paths"]["[*]"].get("top"), None);
+    }
+}
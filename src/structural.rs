@@ -0,0 +1,111 @@
+use crate::error::JistError;
+use memchr::memchr2;
+
+// the bytes a structural scan has to look at
+pub(crate) const STRUCTURAL: [bool; 256] = {
+    let mut table = [false; 256];
+    let mut i = 0;
+    while i < 8 {
+        table[b"\"\\[]{},:"[i] as usize] = true;
+        i += 1;
+    }
+    table
+};
+
+// scanned input is released in steps of this many bytes
+pub(crate) const RELEASE_STEP: usize = 64 << 20;
+
+// The closing quote of a string whose text goes on at `from`, skipping escaped characters. None
+// when `bytes` end first.
+pub(crate) fn closing_quote(bytes: &[u8], mut from: usize) -> Option<usize> {
+    loop {
+        from += memchr2(b'"', b'\\', bytes.get(from..)?)?;
+        if bytes[from] == b'"' {
+            return Some(from);
+        }
+        from += 2;
+    }
+}
+
+// whether an element comes between the last separator of an array, or its opening bracket, and the
+// closing bracket, i.e. the array isn't empty and doesn't end on a comma
+pub(crate) fn last_element(bytes: &[u8], last_sep: usize, close: usize) -> bool {
+    bytes[last_sep + 1..close].iter().any(|b| !b.is_ascii_whitespace())
+}
+
+// A scan of JSON text that only follows strings and nesting. It yields the position of every
+// bracket, comma and colon and of the opening quote of every string, skipping the text of strings
+// and anything else, and keeps the depth after each. A string still open at the end of `bytes` is
+// yielded too, followed by an error. `release` is told how far the scan got every `RELEASE_STEP`
+// bytes.
+pub(crate) struct Scan<'a, R> {
+    bytes: &'a [u8],
+    // where the scan goes on, just past the closing quote after a string
+    pub pos: usize,
+    pub depth: i64,
+    // the opening quote of a string without a closing one
+    unterminated: Option<usize>,
+    released: usize,
+    release: R,
+}
+
+impl<'a, R: FnMut(usize)> Scan<'a, R> {
+    pub fn new(bytes: &'a [u8], pos: usize, depth: i64, release: R) -> Self {
+        Scan { bytes, pos, depth, unterminated: None, released: pos, release }
+    }
+}
+
+impl<R: FnMut(usize)> Iterator for Scan<'_, R> {
+    type Item = Result<(usize, u8), JistError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(start) = self.unterminated.take() {
+            return Some(Err(JistError::malformed(Some(start as u64), "unterminated string")));
+        }
+        // everything before `pos` has been handled by the caller
+        if self.pos - self.released >= RELEASE_STEP {
+            self.released = self.pos;
+            (self.release)(self.pos);
+        }
+        let bytes = self.bytes;
+        let i = self.pos + bytes.get(self.pos..)?.iter().position(|b| STRUCTURAL[*b as usize])?;
+        self.pos = i + 1;
+        match bytes[i] {
+            b'"' => match closing_quote(bytes, i + 1) {
+                Some(end) => self.pos = end + 1,
+                None => {
+                    self.pos = bytes.len();
+                    self.unterminated = Some(i);
+                }
+            },
+            b'[' | b'{' => self.depth += 1,
+            b']' | b'}' => self.depth -= 1,
+            _ => {}
+        }
+        Some(Ok((i, bytes[i])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_test() {
+        let sample = br#" {"a\"": [1, "]"], "b": {}} "#;
+        let tokens: Vec<_> = Scan::new(sample, 0, 0, |_| {}).map(|token| token.map(|(pos, byte)| (pos, byte as char))).collect();
+        let expected = [(1, '{'), (2, '"'), (7, ':'), (9, '['), (11, ','), (13, '"'), (16, ']'), (17, ','), (19, '"'), (22, ':'), (24, '{'), (25, '}'), (26, '}')];
+        assert_eq!(tokens, expected.into_iter().map(Ok).collect::<Vec<_>>());
+
+        let mut scan = Scan::new(sample, 0, 0, |_| {});
+        assert_eq!(scan.nth(3).map(|token| (token, scan.depth)), Some((Ok((9, b'[')), 2)));
+        assert_eq!(scan.nth(1).map(|token| (token, scan.pos)), Some((Ok((13, b'"')), 16)));
+        let tokens: Vec<_> = Scan::new(br#"["a\"#, 0, 0, |_| {}).collect();
+        assert_eq!(tokens, vec![Ok((0, b'[')), Ok((1, b'"')), Err(JistError::malformed(Some(1), "unterminated string"))]);
+
+        assert_eq!(closing_quote(br#""a\\\"b" "#, 1), Some(7));
+        assert_eq!(closing_quote(br#""a\"#, 1), None);
+        assert!(last_element(b"[1 ]", 0, 3));
+        assert!(!last_element(b"[1, ]", 2, 4));
+    }
+}
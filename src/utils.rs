@@ -1,66 +1,202 @@
 use json_tools::Buffer;
-use lazy_static::lazy_static;
-use regex::Regex;
-use serde_json::Value;
-use std::io::{Read, Seek, SeekFrom};
+use crate::error::JistError;
+use crate::model::json_path::JsonPath;
+use serde_json::{Map, Value};
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
 
-lazy_static! {
-    static ref ARRAY_REGEX: Regex = Regex::new(r"^\[(\d+)\]$").unwrap();
-    static ref SPLIT_JSON_PATH_REGEX: Regex = Regex::new(r"\[(?:[^\[\]]*)\]|[^.\[\]]+").unwrap();
+// Resolve JSON style backslash escapes, anything else following a backslash is taken literally
+pub(crate) fn unescape(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('b') => out.push('\u{8}'),
+            Some('f') => out.push('\u{c}'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let mut code = u32::from_str_radix(&hex, 16).unwrap_or(0xFFFD);
+                // surrogate pair
+                if (0xD800..0xDC00).contains(&code) && chars.as_str().starts_with("\\u") {
+                    let low = u32::from_str_radix(chars.as_str().get(2..6).unwrap_or(""), 16).unwrap_or(0);
+                    if (0xDC00..0xE000).contains(&low) {
+                        code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                        chars.by_ref().nth(5);
+                    }
+                }
+                out.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+            }
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+// decoded content of a JSON string token
+pub(crate) fn json_str(raw: &[u8]) -> String {
+    let s = String::from_utf8_lossy(raw);
+    let inner = s.strip_prefix('"').unwrap_or(&s);
+    let inner = inner.strip_suffix('"').unwrap_or(inner);
+    if inner.contains('\\') {
+        return unescape(inner);
+    }
+    inner.to_string()
+}
+
+// a definite path addresses at most one value, anything else returns a JSON array of matches
+pub fn is_definite(search_key: &str) -> bool {
+    JsonPath::parse(search_key).is_ok_and(|query| query.is_definite())
 }
 
-pub(crate) fn array_ind(accessor: &str) -> i64 {
-    if !ARRAY_REGEX.is_match(accessor) {
-        return -1;
+// append an object key to a concrete path, quoting it if the dotted syntax can't express it
+pub(crate) fn push_key(path: &str, key: &str) -> String {
+    let plain = !key.is_empty()
+        && key != "*"
+        && !key.starts_with(['/', '$'])
+        && !key.contains(['.', '[', ']', '\'', '"', '\\', ' ']);
+    if !plain {
+        format!("{}['{}']", path, key.replace('\\', "\\\\").replace('\'', "\\'"))
+    } else if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
     }
+}
 
-    let val: i64 = match accessor.split(&['[', ']'][..]).nth(1) {
-        Some(n) => n.parse::<i64>().expect("not a valid array accessor"),
-        None => -1,
-    };
-    val
+pub(crate) fn push_index(path: &str, idx: usize) -> String {
+    format!("{}[{}]", path, idx)
 }
 
-pub fn parse_search_key(search_key: &str) -> Vec<String> {
-    SPLIT_JSON_PATH_REGEX
-        .find_iter(search_key)
-        .map(|m| m.as_str().to_string())
-        .collect()
+// canonical array index written as a plain key, e.g. `items.3` or the pointer `/items/3`
+pub(crate) fn numeric_key(key: &str) -> Option<i64> {
+    if key.is_empty() || !key.bytes().all(|b| b.is_ascii_digit()) || (key.len() > 1 && key.starts_with('0')) {
+        return None;
+    }
+    key.parse::<i64>().ok()
 }
 
-pub(crate) fn token_pos(buf: &Buffer) -> Result<(u64, u64), &'static str> {
+pub(crate) fn token_pos(buf: &Buffer) -> Result<(u64, u64), JistError> {
     let (first, end) = match buf {
         Buffer::Span(pos) => (pos.first, pos.end),
         _ => {
-            return Err("error");
+            return Err(JistError::malformed(None, "lexer token without a position"));
         }
     };
     Ok((first, end))
 }
 
-pub(crate) fn checkpoint_depth(search_path: &[String], idx: usize) -> (i32, i32, i32) {
-    let search_array_nodes = search_path[..idx + 1]
-        .iter()
-        .filter(|x| x.starts_with("["))
-        .count() as i32;
-    let search_obj_nodes = idx as i32 + 1 - search_array_nodes;
-    (idx as i32, search_array_nodes - 1, search_obj_nodes - 1)
-}
-
-pub(crate) fn sanitize_output(out: &str) -> String {
+pub(crate) fn sanitize_output(out: &str) -> Result<String, serde_json::Error> {
     let sanitized = out.trim().trim_start_matches("\"").trim_end_matches("\"");
     if sanitized.starts_with(['{', '[']) {
-        let json: Value = serde_json::from_str(sanitized).expect("JSON parsing error");
-        return json.to_string();
+        let json: Value = serde_json::from_str(sanitized)?;
+        return Ok(json.to_string());
+    }
+    Ok(sanitized.to_string())
+}
+
+// A value found at byte `start` of the input failed to parse, the error points into the input
+pub(crate) fn value_error(start: u64, raw: &str, error: serde_json::Error) -> JistError {
+    let line_start: usize = raw.split_inclusive('\n').take(error.line().saturating_sub(1)).map(str::len).sum();
+    let offset = start + (line_start + error.column().saturating_sub(1)) as u64;
+    let message = error.to_string();
+    let message = message.split(" at line ").next().unwrap_or_default();
+    JistError::malformed(Some(offset), message)
+}
+
+// a value found by the streaming parser: (concrete path, byte offset, raw value)
+pub(crate) type RawMatch = (String, u64, String);
+
+// matches as a JSON array, or an object keyed by path
+pub(crate) fn join_matches(matches: &[RawMatch], with_paths: bool) -> Result<Value, JistError> {
+    let mut values = Vec::with_capacity(matches.len());
+    let mut keyed = Map::new();
+    for (path, start, raw) in matches {
+        let value: Value = serde_json::from_str(raw.trim()).map_err(|e| value_error(*start, raw, e))?;
+        if with_paths {
+            keyed.insert(path.clone(), value);
+        } else {
+            values.push(value);
+        }
+    }
+    if with_paths {
+        return Ok(Value::Object(keyed));
     }
-    sanitized.to_string()
+    Ok(Value::Array(values))
 }
 
-pub fn find_str<R: Read + Seek>(mut seeker: R, start: u64, end: u64) -> Option<String> {
+// The part of a read buffer that can be lexed: everything once the input is exhausted, otherwise up
+// to the last structural character or whitespace outside a string, so neither a token nor a
+// multibyte character is cut in half, even in minified input. The buffer has to start outside a
+// string. `pos` is the stream position of the buffer, for reporting invalid UTF-8.
+pub(crate) fn complete_tokens(buffer: &[u8], exhausted: bool, pos: u64) -> Result<Option<&str>, JistError> {
+    let end = if exhausted { Some(buffer.len()) } else { last_token_end(buffer) };
+    match end {
+        Some(end) => std::str::from_utf8(&buffer[..end])
+            .map(Some)
+            .map_err(|e| JistError::malformed(Some(pos + e.valid_up_to() as u64), "invalid UTF-8")),
+        None => Ok(None),
+    }
+}
+
+// `bytes` in chunks of about `size` bytes ending on token boundaries, as (stream position, chunk, last chunk)
+pub(crate) fn token_chunks(bytes: &[u8], size: usize) -> impl Iterator<Item = Result<(u64, &str, bool), JistError>> {
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+        let mut end = pos;
+        while pos < bytes.len() {
+            end = (end + size).min(bytes.len());
+            let last = end == bytes.len();
+            match complete_tokens(&bytes[pos..end], last, pos as u64) {
+                Ok(Some(chunk)) => {
+                    let start = pos;
+                    pos += chunk.len();
+                    return Some(Ok((start as u64, chunk, last)));
+                }
+                // a token longer than the chunk
+                Ok(None) => continue,
+                Err(error) => {
+                    pos = bytes.len();
+                    return Some(Err(error));
+                }
+            }
+        }
+        None
+    })
+}
+
+fn last_token_end(buffer: &[u8]) -> Option<usize> {
+    let (mut in_string, mut escaped, mut end) = (false, false, None);
+    for (i, b) in buffer.iter().enumerate() {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+        } else {
+            match b {
+                b'"' => in_string = true,
+                b'{' | b'}' | b'[' | b']' | b',' | b':' | b' ' | b'\t' | b'\r' | b'\n' => end = Some(i + 1),
+                _ => {}
+            }
+        }
+    }
+    end
+}
+
+pub fn find_str<R: Read + Seek>(mut seeker: R, start: u64, end: u64) -> Result<String, JistError> {
     let mut buff = vec![0u8; end as usize - start as usize];
-    seeker.seek(SeekFrom::Start(start)).expect("error");
-    seeker.read_exact(&mut buff).expect("error");
-    String::from_utf8(buff.clone()).ok()
+    seeker.seek(SeekFrom::Start(start))?;
+    seeker.read_exact(&mut buff)?;
+    String::from_utf8(buff).map_err(|e| JistError::malformed(Some(start + e.utf8_error().valid_up_to() as u64), "invalid UTF-8"))
 }
 
 pub fn is_ndjson(input: &str) -> bool {
@@ -77,85 +213,128 @@ pub fn is_ndjson(input: &str) -> bool {
     false
 }
 
+// input bytes read at most to tell NDJSON apart from a single document, a longer first record makes
+// the input a single document
+pub const RECORD_HEAD: u64 = 16 << 20;
+
+// The start of the input `is_record_stream` decides on: the first line and the first non-blank byte
+// after it, at most `RECORD_HEAD` bytes. What is returned is consumed from `reader`.
+pub fn record_head<R: BufRead>(reader: R) -> io::Result<Vec<u8>> {
+    let mut reader = reader.take(RECORD_HEAD);
+    let mut head = Vec::new();
+    reader.read_until(b'\n', &mut head)?;
+    loop {
+        let buf = reader.fill_buf()?;
+        match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(at) => {
+                head.extend_from_slice(&buf[..=at]);
+                reader.consume(at + 1);
+                return Ok(head);
+            }
+            None if buf.is_empty() => return Ok(head),
+            None => {
+                let len = buf.len();
+                head.extend_from_slice(buf);
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+// NDJSON holding more than one record, judged by the start of the input. A single document written
+// on one line is searched as a document rather than as a stream of one record.
+pub fn is_record_stream(head: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(head);
+    match head.split_once('\n') {
+        Some((first, rest)) => {
+            let first = first.trim();
+            first.starts_with('{') && first.ends_with('}') && rest.trim_start().starts_with('{')
+        }
+        None => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     #[test]
-    fn array_ind_test() {
-        assert_eq!(array_ind("0"), -1);
-        assert_eq!(array_ind("waef"), -1);
-        assert_eq!(array_ind("[11]"), 11);
+    fn json_str_test() {
+        assert_eq!(json_str(br#""a\"b""#), r#"a"b"#.to_string());
+        assert_eq!(json_str(br#""caf\u00e9""#), "café".to_string());
+        assert_eq!(unescape(r"\ud83d\ude00"), "😀".to_string());
     }
 
     #[test]
-    fn is_ndjson_test() {
-        assert_eq!(is_ndjson("{}"), true);
-        assert_eq!(is_ndjson(r#"{"a":"b"}
-        {"a":"c"}"#), true);
-        assert_eq!(is_ndjson(r#"[{"a":"b"},{"a":"c"}]"#), false);
-        assert_eq!(is_ndjson(r#"[{"a":"b"},
-        {"a":"c"}]"#), false);
+    fn numeric_key_test() {
+        assert_eq!(numeric_key("12"), Some(12));
+        assert_eq!(numeric_key("012"), None);
+        assert_eq!(numeric_key("-1"), None);
     }
 
     #[test]
-    fn parse_search_key_test() {
-        assert_eq!(parse_search_key("myroot"), vec!["myroot"]);
-        assert_eq!(parse_search_key("myroot.child1"), vec!["myroot", "child1"]);
+    fn push_key_test() {
+        assert_eq!(push_key("", "a"), "a");
+        assert_eq!(push_key("a", "b"), "a.b");
+        assert_eq!(push_key("a", "k8s.io/name"), "a['k8s.io/name']");
+        assert_eq!(push_key("", "it's"), r#"['it\'s']"#);
+        assert_eq!(push_key("", "$a"), "['$a']");
+        assert_eq!(push_index("a", 3), "a[3]");
         assert_eq!(
-            parse_search_key("myroot.child1.grandchild1"),
-            vec!["myroot", "child1", "grandchild1"]
-        );
-        assert_eq!(
-            parse_search_key("myroot.child1[0]"),
-            vec!["myroot", "child1", "[0]"]
-        );
-        assert_eq!(
-            parse_search_key("myroot.child1[0].arr1"),
-            vec!["myroot", "child1", "[0]", "arr1"]
-        );
-        assert_eq!(
-            parse_search_key("[2].child1[0].arr1"),
-            vec!["[2]", "child1", "[0]", "arr1"]
-        );
-        assert_eq!(
-            parse_search_key("[1][1][1].b"),
-            vec!["[1]", "[1]", "[1]", "b"]
-        );
-        assert_eq!(
-            parse_search_key("x.y[1][1][1].b"),
-            vec!["x", "y", "[1]", "[1]", "[1]", "b"]
-        );
-        assert_eq!(
-            parse_search_key("x.y[1][1][1].b[1222][439834]"),
-            vec!["x", "y", "[1]", "[1]", "[1]", "b", "[1222]", "[439834]"]
+            JsonPath::parse(&push_key("a", "x.y")).unwrap().segments,
+            JsonPath::parse("a['x.y']").unwrap().segments
         );
     }
 
     #[test]
-    fn checkpoint_depth_test() {
-        assert_eq!(checkpoint_depth(&parse_search_key("a.b[1]"), 0), (0, -1, 0));
-        assert_eq!(checkpoint_depth(&parse_search_key("a.b[1]"), 1), (1, -1, 1));
-        assert_eq!(checkpoint_depth(&parse_search_key("a.b[1]"), 2), (2, 0, 1));
-
-        assert_eq!(checkpoint_depth(&parse_search_key("[2]"), 0), (0, 0, -1));
+    fn is_definite_test() {
+        assert!(is_definite("a.b[1]"));
+        assert!(is_definite("a[-1]"));
+        assert!(!is_definite("a[*].b"));
+        assert!(!is_definite("a[1:3]"));
+        assert!(!is_definite("$.a"));
+    }
 
-        assert_eq!(checkpoint_depth(&vec!["a".to_string()], 0), (0, -1, 0));
+    #[test]
+    fn complete_tokens_test() {
+        assert_eq!(complete_tokens(br#"{"a": [1, 23"#, false, 0), Ok(Some(r#"{"a": [1, "#)));
+        assert_eq!(complete_tokens(br#"{"a,b": "c\"d, e"#, false, 0), Ok(Some(r#"{"a,b": "#)));
+        assert_eq!(complete_tokens(b"123", false, 0), Ok(None));
+        assert_eq!(complete_tokens(b"123", true, 0), Ok(Some("123")));
+        assert!(matches!(complete_tokens(b"[\"\xff\", ", false, 4), Err(JistError::MalformedJson { offset: Some(6), .. })));
+    }
 
+    #[test]
+    fn token_chunks_test() {
+        let chunks: Vec<_> = token_chunks(br#"{"abc": [12345, true]}"#, 4).collect();
         assert_eq!(
-            checkpoint_depth(&parse_search_key("[1][1][1].b"), 0),
-            (0, 0, -1)
-        );
-        assert_eq!(
-            checkpoint_depth(&parse_search_key("[1][1][1].b"), 1),
-            (1, 1, -1)
-        );
-        assert_eq!(
-            checkpoint_depth(&parse_search_key("[1][1][1].b"), 2),
-            (2, 2, -1)
-        );
-        assert_eq!(
-            checkpoint_depth(&parse_search_key("[1][1][1].b"), 3),
-            (3, 2, 0)
+            chunks,
+            vec![Ok((0, "{", false)), Ok((1, r#""abc": ["#, false)), Ok((9, "12345, ", false)), Ok((16, "true]}", true))]
         );
     }
+
+    #[test]
+    fn is_ndjson_test() {
+        assert_eq!(is_ndjson("{}"), true);
+        assert_eq!(is_ndjson(r#"{"a":"b"}
+        {"a":"c"}"#), true);
+        assert_eq!(is_ndjson(r#"[{"a":"b"},{"a":"c"}]"#), false);
+        assert_eq!(is_ndjson(r#"[{"a":"b"},
+        {"a":"c"}]"#), false);
+    }
+
+    #[test]
+    fn is_record_stream_test() {
+        assert!(is_record_stream(b"{\"a\":1}\r\n\n{\"a\":2}"));
+        assert!(!is_record_stream(b"{\"a\":1}\n"));
+        assert!(!is_record_stream(b"{\"a\":1}"));
+        assert!(!is_record_stream(b"{\n  \"a\": {}\n}"));
+
+        let long = format!("{{\"a\":\"{}\"}}\n\n{{\"a\":2}}\n", "x".repeat(100_000));
+        let mut reader = long.as_bytes();
+        let head = record_head(&mut reader).unwrap();
+        assert_eq!(head.len(), 100_011);
+        assert!(is_record_stream(&head));
+        assert_eq!(reader, &b"\"a\":2}\n"[..]);
+        assert!(!is_record_stream(&record_head(&long.as_bytes()[..100_000]).unwrap()));
+    }
 }
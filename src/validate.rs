@@ -0,0 +1,499 @@
+use crate::error::JistError;
+use crate::formats::{self, FORMATS};
+use crate::presence::parse_key;
+use crate::schema_diff;
+use crate::schema_stream_parser;
+use crate::stats::is_enum;
+use crate::utils::{json_str, push_index, push_key};
+use json_tools::TokenType;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+// scalars up to this long are quoted in violations, longer ones are named by their type
+const FOUND_LEN: usize = 40;
+
+// A schema data is checked against, from a jist schema or a subset of JSON Schema: `type`,
+// `properties`, `required`, `additionalProperties`, `items`, `enum`, `const`, `format`, `anyOf` and
+// `oneOf`. Other keywords are ignored.
+#[derive(Debug)]
+pub struct Schema {
+    // the schema of a record: the items of an array schema, otherwise the schema itself
+    record: Shape,
+}
+
+// What a value may be. Alternatives, e.g. the element variants of a jist array or the `anyOf` of a
+// JSON Schema, are merged: objects key by key, a key being required only if all of them require it.
+#[derive(Debug, Default, Clone)]
+struct Shape {
+    any: bool,
+    string: bool,
+    // formats of strings besides any string
+    formats: Vec<&'static str>,
+    number: bool,
+    integer: bool,
+    boolean: bool,
+    null: bool,
+    // scalars allowed whatever their type
+    values: Vec<Value>,
+    object: Option<Object>,
+    // items of arrays
+    array: Option<Box<Shape>>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct Object {
+    // member shapes and whether they're required
+    properties: BTreeMap<String, (Shape, bool)>,
+    additional: bool,
+}
+
+// A value that doesn't match the schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    // index of the record, an NDJSON line or an element of the top-level array, 0 for a single document
+    pub record: u64,
+    // e.g. `$.geo.lat`, `$.tags[2]` or `$['a b']`, from the record
+    pub path: String,
+    // stream position of the value, or the key for an unexpected key
+    pub offset: u64,
+    pub message: String,
+}
+
+impl Schema {
+    // A jist schema, as printed by `jist` or `jist -u` and also inside {"schema": ...}, or a JSON Schema
+    pub fn parse(schema: &Value) -> Result<Schema, JistError> {
+        let shape = match schema_diff::saved(schema.clone()) {
+            Some(jist) => from_jist(&jist)?,
+            None if schema.is_object() || schema.is_boolean() => from_json_schema(schema),
+            None => return Err(JistError::InvalidInput("the schema is neither a jist schema nor a JSON Schema")),
+        };
+        let record = match shape.array {
+            Some(items) if shape.object.is_none() && shape.scalars().is_empty() && !shape.any => *items,
+            _ => shape,
+        };
+        Ok(Schema { record })
+    }
+}
+
+fn from_jist(schema: &Value) -> Result<Shape, JistError> {
+    let mut shape = Shape::default();
+    match schema {
+        Value::String(kind) => {
+            for kind in kind.split('|') {
+                match kind {
+                    "string" => shape.string = true,
+                    "number" => shape.number = true,
+                    "integer" => shape.integer = true,
+                    "boolean" => shape.boolean = true,
+                    "null" => shape.null = true,
+                    kind => shape.formats.push(FORMATS.iter().find(|format| **format == kind).ok_or(JistError::InvalidInput("unknown type in the schema"))?),
+                }
+            }
+        }
+        Value::Object(members) if is_enum(members) => shape.values = members["enum"].as_array().cloned().unwrap_or_default(),
+        Value::Object(members) => {
+            let mut object = Object::default();
+            for (key, member) in members {
+                let (key, optional) = parse_key(key);
+                object.properties.insert(key.to_string(), (from_jist(member)?, !optional));
+            }
+            shape.object = Some(object);
+        }
+        Value::Array(variants) => {
+            let items = variants.iter().map(from_jist).reduce(|a, b| Ok(a?.union(b?)));
+            shape.array = Some(Box::new(items.transpose()?.unwrap_or(Shape { any: true, ..Shape::default() })));
+        }
+        _ => return Err(JistError::InvalidInput("unknown type in the schema")),
+    }
+    Ok(shape)
+}
+
+fn from_json_schema(schema: &Value) -> Shape {
+    let any = Shape { any: true, ..Shape::default() };
+    let Value::Object(schema) = schema else {
+        // `true` allows anything, `false` nothing
+        return if schema == &Value::Bool(false) { Shape::default() } else { any };
+    };
+    if let Some(Value::Array(values)) = schema.get("enum") {
+        return Shape { values: values.clone(), ..Shape::default() };
+    }
+    if let Some(value) = schema.get("const") {
+        return Shape { values: vec![value.clone()], ..Shape::default() };
+    }
+    if let Some(Value::Array(variants)) = schema.get("anyOf").or(schema.get("oneOf")) {
+        return variants.iter().map(from_json_schema).reduce(Shape::union).unwrap_or_default();
+    }
+    let types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(kind)) => vec![kind.as_str()],
+        Some(Value::Array(kinds)) => kinds.iter().filter_map(Value::as_str).collect(),
+        _ if schema.contains_key("properties") => vec!["object"],
+        _ if schema.contains_key("items") => vec!["array"],
+        _ => return any,
+    };
+    let mut shape = Shape::default();
+    for kind in types {
+        match kind {
+            "string" => match schema.get("format").and_then(Value::as_str).and_then(|format| FORMATS.iter().find(|known| **known == format)) {
+                Some(format) => shape.formats.push(format),
+                None => shape.string = true,
+            },
+            "number" => shape.number = true,
+            "integer" => shape.integer = true,
+            "boolean" => shape.boolean = true,
+            "null" => shape.null = true,
+            "object" => {
+                let required: Vec<&str> = schema.get("required").and_then(Value::as_array).map_or(Vec::new(), |keys| keys.iter().filter_map(Value::as_str).collect());
+                let properties = schema.get("properties").and_then(Value::as_object).cloned().unwrap_or_default();
+                let properties = properties.iter().map(|(key, member)| (key.clone(), (from_json_schema(member), required.contains(&key.as_str())))).collect();
+                // missing keys listed in `required` are still reported
+                let mut object = Object { properties, additional: schema.get("additionalProperties") != Some(&Value::Bool(false)) };
+                for key in required {
+                    object.properties.entry(key.to_string()).or_insert((any.clone(), true));
+                }
+                shape.object = Some(object);
+            }
+            "array" => shape.array = Some(Box::new(schema.get("items").map_or(any.clone(), from_json_schema))),
+            _ => {}
+        }
+    }
+    shape
+}
+
+impl Shape {
+    fn union(mut self, other: Shape) -> Shape {
+        self.any |= other.any;
+        self.string |= other.string;
+        for format in other.formats {
+            if !self.formats.contains(&format) {
+                self.formats.push(format);
+            }
+        }
+        self.number |= other.number;
+        self.integer |= other.integer;
+        self.boolean |= other.boolean;
+        self.null |= other.null;
+        self.values.extend(other.values);
+        self.object = match (self.object, other.object) {
+            (Some(a), Some(b)) => Some(a.union(b)),
+            (a, b) => a.or(b),
+        };
+        self.array = match (self.array, other.array) {
+            (Some(a), Some(b)) => Some(Box::new(a.union(*b))),
+            (a, b) => a.or(b),
+        };
+        self
+    }
+
+    // whether a value starting with a token of `kind` and text `raw` may be here
+    fn allows(&self, kind: &TokenType, raw: &[u8]) -> bool {
+        if self.any {
+            return true;
+        }
+        let literal = || serde_json::from_slice::<Value>(raw).is_ok_and(|value| self.values.contains(&value));
+        match kind {
+            TokenType::CurlyOpen => self.object.is_some(),
+            TokenType::BracketOpen => self.array.is_some(),
+            TokenType::String => {
+                self.string || (!self.formats.is_empty() && self.formats.iter().any(|format| formats::matches(format, &json_str(raw)))) || literal()
+            }
+            TokenType::Number => self.number || (self.integer && is_integer(raw)) || literal(),
+            TokenType::BooleanTrue | TokenType::BooleanFalse => self.boolean || literal(),
+            TokenType::Null => self.null || self.values.contains(&Value::Null),
+            _ => true,
+        }
+    }
+
+    fn scalars(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        if self.string {
+            names.push("string".to_string());
+        }
+        names.extend(self.formats.iter().map(|format| format.to_string()));
+        if self.number {
+            names.push("number".to_string());
+        } else if self.integer {
+            names.push("integer".to_string());
+        }
+        if self.boolean {
+            names.push("boolean".to_string());
+        }
+        names.extend(self.values.iter().filter(|value| !value.is_null()).map(Value::to_string));
+        if self.null || self.values.contains(&Value::Null) {
+            names.push("null".to_string());
+        }
+        names
+    }
+
+    // e.g. "object|null" or "\"TCP\"|\"UDP\""
+    fn expected(&self) -> String {
+        let mut names: Vec<String> = Vec::new();
+        if self.object.is_some() {
+            names.push("object".to_string());
+        }
+        if self.array.is_some() {
+            names.push("array".to_string());
+        }
+        names.extend(self.scalars());
+        if names.is_empty() {
+            return "nothing".to_string();
+        }
+        names.join("|")
+    }
+}
+
+impl Object {
+    fn union(mut self, other: Object) -> Object {
+        for (key, (_, required)) in &mut self.properties {
+            if !other.properties.contains_key(key) {
+                *required = false;
+            }
+        }
+        for (key, (shape, required)) in other.properties {
+            let merged = match self.properties.remove(&key) {
+                Some((existing, both)) => (existing.union(shape), both && required),
+                None => (shape, false),
+            };
+            self.properties.insert(key, merged);
+        }
+        self.additional |= other.additional;
+        self
+    }
+}
+
+// a number without a fraction, 1.0 counts
+fn is_integer(raw: &[u8]) -> bool {
+    !raw.iter().any(|b| matches!(b, b'.' | b'e' | b'E')) || std::str::from_utf8(raw).ok().and_then(|n| n.parse::<f64>().ok()).is_some_and(|n| n.fract() == 0.0)
+}
+
+// e.g. `"yesterday"`, or `string` for a long one
+fn found(kind: &TokenType, raw: &[u8]) -> String {
+    let name = match kind {
+        TokenType::CurlyOpen => return "object".to_string(),
+        TokenType::BracketOpen => return "array".to_string(),
+        TokenType::String => "string",
+        TokenType::Number => "number",
+        _ => "",
+    };
+    if raw.len() > FOUND_LEN && !name.is_empty() {
+        return name.to_string();
+    }
+    String::from_utf8_lossy(raw).into_owned()
+}
+
+// a container being walked
+struct Frame<'s> {
+    array: bool,
+    // elements are records, this is the top-level array
+    records: bool,
+    // shapes of the members or elements, None when they aren't checked
+    object: Option<&'s Object>,
+    items: Option<&'s Shape>,
+    // length of the path of the container, and its stream position
+    base: usize,
+    start: u64,
+    // next element
+    index: usize,
+    // keys seen that are required
+    seen: BTreeSet<&'s str>,
+}
+
+// The token walk: values are checked against their shape as they start, keys against the object
+// they're in and required keys as the object ends
+struct Walk<'s, F: FnMut(Violation)> {
+    schema: &'s Schema,
+    report: F,
+    frames: Vec<Frame<'s>>,
+    path: String,
+    // shape of the next value, None when it isn't checked
+    next: Option<&'s Shape>,
+    key_next: bool,
+    // record being walked and the number of records
+    record: u64,
+    records: u64,
+}
+
+impl<'s, F: FnMut(Violation)> Walk<'s, F> {
+    fn token(&mut self, kind: TokenType, raw: &[u8], offset: u64) {
+        match kind {
+            TokenType::CurlyClose | TokenType::BracketClose => {
+                let Some(frame) = self.frames.pop() else {
+                    return;
+                };
+                self.path.truncate(frame.base);
+                for (key, _) in frame.object.into_iter().flat_map(|object| object.properties.iter()).filter(|(_, (_, required))| *required) {
+                    if !frame.seen.contains(key.as_str()) {
+                        self.violation(frame.start, format!("missing key `{}`", key));
+                    }
+                }
+            }
+            TokenType::Comma => match self.frames.last_mut() {
+                Some(frame) if frame.array => frame.index += 1,
+                Some(_) => self.key_next = true,
+                None => {}
+            },
+            TokenType::Colon => {}
+            TokenType::String if self.key_next => {
+                self.key_next = false;
+                let Some(frame) = self.frames.last_mut() else {
+                    return;
+                };
+                let key = json_str(raw);
+                self.path.truncate(frame.base);
+                self.path = push_key(&self.path, &key);
+                self.next = None;
+                let Some(object) = frame.object else {
+                    return;
+                };
+                match object.properties.get_key_value(key.as_str()) {
+                    Some((name, (shape, required))) => {
+                        if *required {
+                            frame.seen.insert(name.as_str());
+                        }
+                        self.next = Some(shape);
+                    }
+                    None if object.additional => {}
+                    None => self.violation(offset, "unexpected key".to_string()),
+                }
+            }
+            _ => self.value(kind, raw, offset),
+        }
+    }
+
+    fn value(&mut self, kind: TokenType, raw: &[u8], offset: u64) {
+        match self.frames.last() {
+            None if kind == TokenType::BracketOpen => {
+                let schema: &'s Schema = self.schema;
+                let record = &schema.record;
+                self.frames.push(Frame { array: true, records: true, object: None, items: Some(record), base: 0, start: offset, index: 0, seen: BTreeSet::new() });
+                return;
+            }
+            Some(frame) if frame.records => self.start_record(),
+            None => self.start_record(),
+            Some(frame) if frame.array => {
+                self.path.truncate(frame.base);
+                self.path = push_index(&self.path, frame.index);
+                self.next = frame.items;
+            }
+            Some(_) => {}
+        }
+        let shape = self.next.take();
+        let allowed = shape.filter(|shape| shape.allows(&kind, raw));
+        if let Some(shape) = shape.filter(|_| allowed.is_none()) {
+            self.violation(offset, format!("expected {}, found {}", shape.expected(), found(&kind, raw)));
+        }
+        // inside a value of the wrong type nothing more is checked
+        let (object, items) = match allowed {
+            Some(shape) if !shape.any => (shape.object.as_ref(), shape.array.as_deref()),
+            _ => (None, None),
+        };
+        if matches!(kind, TokenType::CurlyOpen | TokenType::BracketOpen) {
+            let array = kind == TokenType::BracketOpen;
+            self.frames.push(Frame { array, records: false, object, items, base: self.path.len(), start: offset, index: 0, seen: BTreeSet::new() });
+            self.key_next = !array;
+        }
+    }
+
+    fn start_record(&mut self) {
+        self.record = self.records;
+        self.records += 1;
+        self.path.clear();
+        self.path.push('$');
+        let schema: &'s Schema = self.schema;
+        self.next = Some(&schema.record);
+    }
+
+    fn violation(&mut self, offset: u64, message: String) {
+        (self.report)(Violation { record: self.record, path: self.path.clone(), offset, message });
+    }
+}
+
+// Check every record of the input, the lines of NDJSON, the elements of a top-level array or a
+// single document, against `schema` in a single streaming pass, handing each violation to `report`
+// as it's found. Returns the number of records.
+pub fn validate<F: FnMut(Violation)>(schema: &Schema, haystack: Option<&str>, file: Option<&str>, report: F) -> Result<u64, JistError> {
+    let mut walk = Walk { schema, report, frames: Vec::new(), path: String::new(), next: None, key_next: false, record: 0, records: 0 };
+    schema_stream_parser::tokens(haystack, file, |kind, raw, offset| {
+        walk.token(kind, raw, offset);
+        Ok(())
+    })?;
+    Ok(walk.records)
+}
+
+impl Violation {
+    pub fn report(&self) -> Value {
+        json!({"record": self.record, "path": self.path, "offset": self.offset, "message": self.message})
+    }
+}
+
+// e.g. `record 3 $.geo.lat at byte 120: expected number, found "x"`
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "record {} {} at byte {}: {}", self.record, self.path, self.offset, self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn violations(schema: Value, haystack: &str) -> Vec<String> {
+        let schema = Schema::parse(&schema).unwrap();
+        let mut found = Vec::new();
+        validate(&schema, Some(haystack), None, |violation| found.push(violation.to_string())).unwrap();
+        found
+    }
+
+    #[test]
+    fn jist_schema_test() {
+        let schema = json!([{"id": "integer", "geo?": {"lat": "number"}, "note": "string|null", "proto": {"enum": ["TCP", "UDP"]}, "tags": ["string"]}]);
+        let records = "{\"id\": 1, \"note\": null, \"proto\": \"TCP\", \"tags\": [\"a\"]}\n\
+                       {\"id\": 2.5, \"geo\": {\"lat\": \"x\"}, \"note\": \"n\", \"proto\": \"ICMP\", \"tags\": [\"a\", 3], \"extra\": true}\n\
+                       {\"id\": 3, \"note\": \"n\"}\n";
+        assert_eq!(
+            violations(schema.clone(), records),
+            vec![
+                "record 1 $.id at byte 62: expected integer, found 2.5",
+                "record 1 $.geo.lat at byte 82: expected number, found \"x\"",
+                "record 1 $.proto at byte 110: expected \"TCP\"|\"UDP\", found \"ICMP\"",
+                "record 1 $.tags[1] at byte 132: expected string, found 3",
+                "record 1 $.extra at byte 136: unexpected key",
+                "record 2 $ at byte 151: missing key `proto`",
+                "record 2 $ at byte 151: missing key `tags`",
+            ]
+        );
+        // a top-level array holds the records too
+        assert_eq!(violations(schema, r#"[{"id": 1, "note": null, "proto": "UDP", "tags": []}, {"id": "7"}]"#)[0], "record 1 $.id at byte 61: expected integer, found \"7\"");
+        assert_eq!(violations(json!({"at": "date-time", "a b": "boolean"}), r#"{"at": "yesterday", "a b": true}"#), vec!["record 0 $.at at byte 7: expected date-time, found \"yesterday\""]);
+        assert_eq!(violations(json!({"a b": {"c.d": "boolean"}}), r#"{"a b": {"c.d": 1}}"#), vec!["record 0 $['a b']['c.d'] at byte 16: expected boolean, found 1"]);
+    }
+
+    #[test]
+    fn json_schema_test() {
+        let schema = json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "id": {"anyOf": [{"type": "integer"}, {"type": "string", "format": "uuid"}]},
+                    "level": {"enum": ["info", "warn"]}
+                },
+                "required": ["id", "ts"]
+            }
+        });
+        let records = "{\"id\": 1, \"ts\": 0, \"level\": \"info\", \"more\": {}}\n{\"id\": \"x\", \"ts\": 0, \"level\": 3}\n{\"id\": [1]}\n";
+        assert_eq!(
+            violations(schema, records),
+            vec![
+                "record 1 $.id at byte 55: expected uuid|integer, found \"x\"",
+                "record 1 $.level at byte 78: expected \"info\"|\"warn\", found 3",
+                "record 2 $.id at byte 88: expected uuid|integer, found array",
+                "record 2 $ at byte 81: missing key `ts`",
+            ]
+        );
+        // not a jist schema, so a JSON Schema without keywords
+        assert!(Schema::parse(&json!({"a": "strin"})).is_ok_and(|schema| schema.record.any));
+        assert!(Schema::parse(&json!([{"a": "object"}])).is_err());
+    }
+}
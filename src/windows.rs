@@ -0,0 +1,163 @@
+use crate::model::path_segment::PathSegment;
+use crate::utils::numeric_key;
+use crate::structural::{last_element, Scan};
+
+// A run of consecutive top-level array elements. `start` is the position of the '[' or ',' before
+// the first element and `end` the position of the ',' or ']' after the last, so with those two bytes
+// replaced by brackets the window is an array of its own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Window {
+    pub start: u64,
+    pub end: u64,
+    // index of the first element in the whole array
+    pub first: u64,
+    pub count: u64,
+}
+
+impl Window {
+    pub fn contains(&self, idx: u64) -> bool {
+        idx >= self.first && idx < self.first + self.count
+    }
+}
+
+// The elements of the top-level array of `bytes` split into windows of at most `max_len` bytes,
+// found with a structural pre-scan that only follows strings and nesting. None when the input isn't
+// an array, an element alone is larger than `max_len` or the array isn't closed. `release` is told
+// how far the scan got every now and then.
+pub(crate) fn array_windows<F: FnMut(usize)>(bytes: &[u8], max_len: usize, release: F) -> Option<Vec<Window>> {
+    let open = bytes.iter().position(|b| !b.is_ascii_whitespace())?;
+    if bytes[open] != b'[' {
+        return None;
+    }
+    let mut split = Split { max_len, windows: Vec::new(), window: Window { start: open as u64, end: 0, first: 0, count: 0 }, last_sep: open };
+    let mut scan = Scan::new(bytes, open, 0, release);
+    while let Some(token) = scan.next() {
+        match token.ok()? {
+            (i, b']' | b'}') if scan.depth == 0 => {
+                if last_element(bytes, split.last_sep, i) {
+                    split.element_end(i)?;
+                }
+                if split.window.count > 0 {
+                    split.window.end = i as u64;
+                    split.windows.push(split.window);
+                }
+                return Some(split.windows);
+            }
+            (i, b',') if scan.depth == 1 => split.element_end(i)?,
+            _ => {}
+        }
+    }
+    None
+}
+
+// The windows a path starting with `head` has to look at, along with the segment that takes the
+// place of `head` inside each of them. Indices are moved to the window holding the element, forward
+// slices are cut to each window, wildcards and filters apply to every window. None for any other head.
+pub(crate) fn window_heads(windows: &[Window], head: &PathSegment) -> Option<Vec<(Window, PathSegment)>> {
+    let total = windows.last().map_or(0, |window| window.first + window.count) as i64;
+    let index = match head {
+        PathSegment::Index(i) => Some(*i),
+        PathSegment::Key(k) => Some(numeric_key(k)?),
+        _ => None,
+    };
+    Some(match (index, head) {
+        (Some(i), _) => {
+            let idx = if i < 0 { i + total } else { i };
+            windows
+                .iter()
+                .find(|window| idx >= 0 && window.contains(idx as u64))
+                .map(|window| (*window, PathSegment::Index(idx - window.first as i64)))
+                .into_iter()
+                .collect()
+        }
+        (None, PathSegment::Slice(start, end, step)) if *step > 0 => {
+            let bound = |b: i64| if b < 0 { (b + total).max(0) } else { b.min(total) };
+            let lo = start.map_or(0, bound);
+            let hi = end.map_or(total, bound);
+            let mut heads = Vec::new();
+            for window in windows {
+                let (first, last) = (window.first as i64, (window.first + window.count) as i64);
+                // the first index of the slice in the window
+                let from = lo.max(first);
+                let from = from + (step - (from - lo) % step) % step;
+                if from < hi.min(last) {
+                    heads.push((*window, PathSegment::Slice(Some(from - first), Some(hi.min(last) - first), *step)));
+                }
+            }
+            heads
+        }
+        (None, PathSegment::Wildcard | PathSegment::Filter(_)) => windows.iter().map(|window| (*window, head.clone())).collect(),
+        _ => return None,
+    })
+}
+
+struct Split {
+    max_len: usize,
+    windows: Vec<Window>,
+    window: Window,
+    // the separator after the last element so far
+    last_sep: usize,
+}
+
+impl Split {
+    // an element ends at `sep`, a new window starts at the previous separator if it doesn't fit
+    fn element_end(&mut self, sep: usize) -> Option<()> {
+        if sep - self.window.start as usize + 1 > self.max_len {
+            if self.window.count == 0 {
+                return None;
+            }
+            self.window.end = self.last_sep as u64;
+            self.windows.push(self.window);
+            self.window = Window { start: self.last_sep as u64, end: 0, first: self.window.first + self.window.count, count: 0 };
+            if sep - self.last_sep + 1 > self.max_len {
+                return None;
+            }
+        }
+        self.window.count += 1;
+        self.last_sep = sep;
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn array_windows_test() {
+        let sample = br#" [1, "a,]\"", {"b": [2, 3]} , [4],5 ] "#;
+        let windows = array_windows(sample, 20, |_| {}).unwrap();
+        assert_eq!(
+            windows,
+            vec![
+                Window { start: 1, end: 12, first: 0, count: 2 },
+                Window { start: 12, end: 28, first: 2, count: 1 },
+                Window { start: 28, end: 36, first: 3, count: 2 },
+            ]
+        );
+        let first = &sample[windows[0].start as usize + 1..windows[0].end as usize];
+        assert_eq!(first, br#"1, "a,]\"""#);
+        assert_eq!(array_windows(sample, 1000, |_| {}).unwrap().len(), 1);
+        assert_eq!(array_windows(sample, 10, |_| {}), None);
+        assert_eq!(array_windows(b"[ ]", 10, |_| {}), Some(vec![]));
+        assert_eq!(array_windows(br#"{"a": [1]}"#, 10, |_| {}), None);
+        assert_eq!(array_windows(b"[1, 2", 10, |_| {}), None);
+    }
+
+    #[test]
+    fn window_heads_test() {
+        let windows = array_windows(br#" [1, "a,]\"", {"b": [2, 3]} , [4],5 ] "#, 20, |_| {}).unwrap();
+        let heads = |head| window_heads(&windows, &head).map(|heads| heads.into_iter().map(|(w, h)| (w.first, h)).collect::<Vec<_>>());
+        assert_eq!(heads(PathSegment::Index(3)), Some(vec![(3, PathSegment::Index(0))]));
+        assert_eq!(heads(PathSegment::Index(-1)), Some(vec![(3, PathSegment::Index(1))]));
+        assert_eq!(heads(PathSegment::Key("2".to_string())), Some(vec![(2, PathSegment::Index(0))]));
+        assert_eq!(heads(PathSegment::Index(5)), Some(vec![]));
+        assert_eq!(
+            heads(PathSegment::Slice(Some(1), None, 2)),
+            Some(vec![(0, PathSegment::Slice(Some(1), Some(2), 2)), (3, PathSegment::Slice(Some(0), Some(2), 2))])
+        );
+        assert_eq!(heads(PathSegment::Wildcard).map(|heads| heads.len()), Some(3));
+        assert_eq!(heads(PathSegment::Key("a".to_string())), None);
+        assert_eq!(heads(PathSegment::Slice(None, None, -1)), None);
+    }
+}